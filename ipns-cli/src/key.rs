@@ -0,0 +1,79 @@
+//! `ipns key`: key files hold a protobuf encoded libp2p private key,
+//! the same format as `ipfs key export`.
+use anyhow::{Context, Result};
+use clap::{Subcommand, ValueEnum};
+use ipns_entry::name::IpnsName;
+use libp2p_identity::{Keypair, PeerId};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Subcommand)]
+pub enum KeyCommand {
+    /// Generate a key file and print its IPNS Name
+    Gen {
+        /// Key file to create, an existing file is never overwritten
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(short = 't', long = "type", value_enum, default_value_t = KeyType::Ed25519)]
+        key_type: KeyType,
+    },
+    /// Show the type and IPNS Name of a key file
+    Show {
+        /// Key file
+        key: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum KeyType {
+    Ed25519,
+    Secp256k1,
+}
+
+impl KeyCommand {
+    pub fn run(self) -> Result<()> {
+        match self {
+            KeyCommand::Gen { output, key_type } => {
+                let keypair = match key_type {
+                    KeyType::Ed25519 => Keypair::generate_ed25519(),
+                    KeyType::Secp256k1 => Keypair::generate_secp256k1(),
+                };
+
+                let mut options = std::fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                // Readable by the owner only, like `ipfs` key files
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+                let mut file = options
+                    .open(&output)
+                    .with_context(|| format!("Creating {}", output.display()))?;
+                file.write_all(&keypair.to_protobuf_encoding()?)?;
+
+                println!("{}", name_of(&keypair));
+                Ok(())
+            }
+            KeyCommand::Show { key } => {
+                let keypair = read_key(&key)?;
+                let name = name_of(&keypair);
+
+                println!("type:   {:?}", keypair.key_type());
+                println!("base36: {}", name.to_base36());
+                println!("base58: {}", name.to_base58());
+                println!("cid:    {}", name.to_cid());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Read a protobuf encoded libp2p private key
+pub fn read_key(path: &Path) -> Result<Keypair> {
+    let bytes = std::fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+    Keypair::from_protobuf_encoding(&bytes)
+        .with_context(|| format!("{} is not a libp2p private key", path.display()))
+}
+
+pub fn name_of(keypair: &Keypair) -> IpnsName {
+    IpnsName::from(PeerId::from_public_key(&keypair.public()))
+}
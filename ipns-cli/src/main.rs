@@ -0,0 +1,86 @@
+//! # ipns
+//!
+//! Offline command-line tool for IPNS keys and records, built on [ipns_entry].
+//!
+//! Records are read from and written to files as protobuf bytes, the format published to
+//! the DHT. Use `-` or leave out the path to read stdin or write stdout.
+//!
+//! ```cli
+//! ipns key gen --output my.key
+//! ipns record create --key my.key --value /ipfs/bafkqaaa --output my.ipns-record
+//! ipns record verify --name k51... my.ipns-record
+//! ipns record next --key my.key --value /ipfs/bafkqaab my.ipns-record > next.ipns-record
+//! ```
+mod key;
+mod name;
+mod record;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    name = "ipns",
+    version,
+    about = "Create, inspect and verify IPNS Records offline"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate and show signing keys
+    #[command(subcommand)]
+    Key(key::KeyCommand),
+    /// Create, inspect, verify and update records
+    #[command(subcommand)]
+    Record(record::RecordCommand),
+    /// Convert IPNS Names between forms
+    #[command(subcommand)]
+    Name(name::NameCommand),
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Key(command) => command.run(),
+        Command::Record(command) => command.run(),
+        Command::Name(command) => command.run(),
+    }
+}
+
+/// Read a file, or stdin when the path is `-` or missing
+pub(crate) fn read_input(path: Option<&Path>) -> Result<Vec<u8>> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            std::fs::read(path).with_context(|| format!("Reading {}", path.display()))
+        }
+        _ => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .context("Reading stdin")?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Write bytes to a file, or stdout when the path is `-` or missing.
+/// Refuses to write binary to a terminal.
+pub(crate) fn write_output(path: Option<&PathBuf>, bytes: &[u8]) -> Result<()> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            std::fs::write(path, bytes).with_context(|| format!("Writing {}", path.display()))
+        }
+        _ => {
+            let mut stdout = std::io::stdout();
+            if stdout.is_terminal() {
+                bail!("Not writing binary to a terminal, use --output or redirect stdout");
+            }
+            stdout.write_all(bytes).context("Writing stdout")
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! `ipns name`
+use anyhow::Result;
+use clap::{Subcommand, ValueEnum};
+use ipns_entry::name::IpnsName;
+
+#[derive(Subcommand)]
+pub enum NameCommand {
+    /// Convert an IPNS Name, given as a base58 PeerId or a CID with or without `/ipns/`
+    Convert {
+        name: IpnsName,
+        /// Print only this form, otherwise all of them
+        #[arg(long, value_enum)]
+        to: Option<NameForm>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum NameForm {
+    /// base58btc PeerId, `12D3KooW...`
+    Base58,
+    /// libp2p-key CID in base36, `k51...`
+    Base36,
+    /// libp2p-key CID in base32, `bafz...`
+    Cid,
+}
+
+impl NameCommand {
+    pub fn run(self) -> Result<()> {
+        match self {
+            NameCommand::Convert {
+                name,
+                to: Some(form),
+            } => {
+                println!("{}", format(&name, form));
+            }
+            NameCommand::Convert { name, to: None } => {
+                println!("base36: {}", format(&name, NameForm::Base36));
+                println!("base58: {}", format(&name, NameForm::Base58));
+                println!("cid:    {}", format(&name, NameForm::Cid));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format(name: &IpnsName, form: NameForm) -> String {
+    match form {
+        NameForm::Base58 => name.to_base58(),
+        NameForm::Base36 => name.to_base36(),
+        NameForm::Cid => name.to_cid().to_string(),
+    }
+}
@@ -0,0 +1,177 @@
+//! `ipns record`
+use crate::key::{name_of, read_key};
+use crate::{read_input, write_output};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Subcommand;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::signer::Signer;
+use ipns_entry::{time, DataBuilder};
+use libp2p_identity::Keypair;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum RecordCommand {
+    /// Create and sign a record
+    Create {
+        /// Key file to sign with
+        #[arg(short, long)]
+        key: PathBuf,
+        /// Value, e.g. /ipfs/<cid>
+        #[arg(long)]
+        value: String,
+        /// How long the record stays valid, e.g. 48h or 7days
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "48h")]
+        lifetime: Duration,
+        /// IpnsEntry.ttl, how long resolvers may cache the record, e.g. 1h [default: 48h]
+        #[arg(long, value_parser = humantime::parse_duration)]
+        ttl: Option<Duration>,
+        #[arg(long, default_value_t = 0)]
+        sequence: u64,
+        /// Record file to write, stdout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the record as JSON
+    Inspect {
+        /// Record file, stdin if missing
+        record: Option<PathBuf>,
+    },
+    /// Check the signature against an IPNS Name and that the record has not expired
+    Verify {
+        /// IPNS Name, in any form
+        #[arg(long)]
+        name: IpnsName,
+        /// Record file, stdin if missing
+        record: Option<PathBuf>,
+    },
+    /// Sign the next record after an existing one: sequence + 1, a new validity,
+    /// and the same value and ttl unless given
+    Next {
+        /// Key file which signed the existing record
+        #[arg(short, long)]
+        key: PathBuf,
+        /// New value, the existing one if missing
+        #[arg(long)]
+        value: Option<String>,
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "48h")]
+        lifetime: Duration,
+        /// New IpnsEntry.ttl, e.g. 1h, the existing one if missing
+        #[arg(long, value_parser = humantime::parse_duration)]
+        ttl: Option<Duration>,
+        /// Record file to write, stdout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Existing record file, stdin if missing
+        record: Option<PathBuf>,
+    },
+}
+
+impl RecordCommand {
+    pub fn run(self) -> Result<()> {
+        match self {
+            RecordCommand::Create {
+                key,
+                value,
+                lifetime,
+                ttl,
+                sequence,
+                output,
+            } => {
+                let keypair = read_key(&key)?;
+                let now = time::now();
+
+                let mut builder = DataBuilder::new_at(&value, now);
+                builder.validity_at(now + lifetime).sequence(sequence);
+                if let Some(ttl) = ttl {
+                    builder.ttl(nanos(ttl)?);
+                }
+
+                let entry = sign(&keypair, &builder)?;
+                write_output(output.as_ref(), &entry.try_to_bytes()?)
+            }
+            RecordCommand::Inspect { record } => {
+                let entry = read_record(record)?;
+                println!("{}", serde_json::to_string_pretty(&entry)?);
+                Ok(())
+            }
+            RecordCommand::Verify { name, record } => {
+                let entry = read_record(record)?;
+
+                if !entry.is_valid_for(&name.peer_id())? {
+                    bail!("Invalid signature for {name}");
+                }
+                let data = entry.decode_data()?;
+                let validity = String::from_utf8_lossy(&data.validity);
+                if entry.is_expired()? {
+                    bail!("Record expired at {validity}");
+                }
+
+                println!(
+                    "Valid record for {name}: sequence {}, value {}, valid until {validity}",
+                    data.sequence,
+                    String::from_utf8_lossy(&data.value)
+                );
+                Ok(())
+            }
+            RecordCommand::Next {
+                key,
+                value,
+                lifetime,
+                ttl,
+                output,
+                record,
+            } => {
+                let keypair = read_key(&key)?;
+                let previous = read_record(record)?;
+
+                let name = name_of(&keypair);
+                if !previous.is_valid_for(&name.peer_id())? {
+                    bail!("The existing record was not signed by {}", key.display());
+                }
+                let data = previous.decode_data()?;
+
+                let value = match value {
+                    Some(value) => value,
+                    None => String::from_utf8(data.value)
+                        .context("The existing value is not UTF-8, give a --value")?,
+                };
+                let sequence = data
+                    .sequence
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("The existing record has the maximum sequence"))?;
+
+                let now = time::now();
+                let mut builder = DataBuilder::new_at(&value, now);
+                builder
+                    .validity_at(now + lifetime)
+                    .sequence(sequence)
+                    .ttl(ttl.map(nanos).transpose()?.unwrap_or(data.ttl));
+
+                let entry = sign(&keypair, &builder)?;
+                write_output(output.as_ref(), &entry.try_to_bytes()?)
+            }
+        }
+    }
+}
+
+/// IpnsEntry.ttl is in nanoseconds
+fn nanos(ttl: Duration) -> Result<u64> {
+    u64::try_from(ttl.as_nanos()).map_err(|_| anyhow!("ttl {ttl:?} is too large"))
+}
+
+fn read_record(path: Option<PathBuf>) -> Result<IpnsEntry> {
+    let bytes = read_input(path.as_deref())?;
+    IpnsEntry::from_bytes(&bytes).context("Not a protobuf IPNS Record")
+}
+
+/// Sign the data, embedding the public key when the IPNS Name does not inline it
+fn sign(keypair: &Keypair, builder: &DataBuilder) -> Result<IpnsEntry> {
+    let (data, signables) = builder.build();
+    let signed = Signer::new(keypair.clone()).sign(signables)?;
+
+    let mut entry = IpnsEntry::new(data, signed);
+    entry.embed_public_key(&keypair.public());
+    Ok(entry)
+}
@@ -0,0 +1,176 @@
+//! Runs the `ipns` binary through key, record and name workflows
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Fresh directory for one test's files
+fn test_dir(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(test);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn ipns(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ipns"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn ipns_ok(args: &[&str]) -> String {
+    let output = ipns(args);
+    assert!(
+        output.status.success(),
+        "ipns {args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn ipns_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ipns"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Generate a key, returning its path and base36 IPNS Name
+fn key_gen(dir: &Path, file: &str) -> (String, String) {
+    let key = dir.join(file).to_str().unwrap().to_string();
+    let name = ipns_ok(&["key", "gen", "--output", &key])
+        .trim()
+        .to_string();
+    (key, name)
+}
+
+#[test]
+fn test_key_gen_and_show() {
+    let dir = test_dir("key_gen_and_show");
+    let (key, name) = key_gen(&dir, "a.key");
+    assert!(name.starts_with("k51"));
+
+    let shown = ipns_ok(&["key", "show", &key]);
+    assert!(shown.contains("Ed25519"));
+    assert!(shown.contains(&name));
+
+    // never overwrites a key
+    assert!(!ipns(&["key", "gen", "--output", &key]).status.success());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn test_create_inspect_verify() {
+    let dir = test_dir("create_inspect_verify");
+    let (key, name) = key_gen(&dir, "a.key");
+    let (_, other_name) = key_gen(&dir, "b.key");
+    let record = dir.join("a.ipns-record").to_str().unwrap().to_string();
+
+    ipns_ok(&[
+        "record",
+        "create",
+        "--key",
+        &key,
+        "--value",
+        "/ipfs/bafkqaaa",
+        "--lifetime",
+        "1h",
+        "--ttl",
+        "1m",
+        "--sequence",
+        "5",
+        "--output",
+        &record,
+    ]);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&ipns_ok(&["record", "inspect", &record])).unwrap();
+    assert_eq!(json["decodedData"]["value"], "/ipfs/bafkqaaa");
+    assert_eq!(json["decodedData"]["sequence"], 5);
+    assert_eq!(json["decodedData"]["ttl"], 60_000_000_000u64);
+
+    let verified = ipns_ok(&["record", "verify", "--name", &name, &record]);
+    assert!(verified.contains("sequence 5"));
+
+    let wrong = ipns(&["record", "verify", "--name", &other_name, &record]);
+    assert!(!wrong.status.success());
+
+    // from stdin
+    let bytes = std::fs::read(&record).unwrap();
+    let output = ipns_with_stdin(&["record", "verify", "--name", &name], &bytes);
+    assert!(output.status.success());
+
+    let output = ipns_with_stdin(&["record", "inspect", "-"], b"not a record");
+    assert!(!output.status.success());
+
+    // a value too large for a 10 KiB record is an error, not a panic
+    let value = format!("/ipfs/{}", "a".repeat(6 * 1024));
+    let output = ipns(&["record", "create", "--key", &key, "--value", &value]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("exceeding 10 KiB"));
+}
+
+#[test]
+fn test_record_next() {
+    let dir = test_dir("record_next");
+    let (key, name) = key_gen(&dir, "a.key");
+    let (other_key, _) = key_gen(&dir, "b.key");
+    let first = dir.join("1.ipns-record").to_str().unwrap().to_string();
+    let second = dir.join("2.ipns-record").to_str().unwrap().to_string();
+
+    ipns_ok(&[
+        "record",
+        "create",
+        "--key",
+        &key,
+        "--value",
+        "/ipfs/bafkqaaa",
+        "--ttl",
+        "1m",
+        "--output",
+        &first,
+    ]);
+    ipns_ok(&["record", "next", "--key", &key, "--output", &second, &first]);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&ipns_ok(&["record", "inspect", &second])).unwrap();
+    assert_eq!(json["decodedData"]["value"], "/ipfs/bafkqaaa");
+    assert_eq!(json["decodedData"]["sequence"], 1);
+    assert_eq!(json["decodedData"]["ttl"], 60_000_000_000u64);
+    ipns_ok(&["record", "verify", "--name", &name, &second]);
+
+    // only the key which signed the record can sign the next one
+    let output = ipns(&[
+        "record", "next", "--key", &other_key, "--output", &second, &first,
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_name_convert() {
+    let dir = test_dir("name_convert");
+    let (_, name) = key_gen(&dir, "a.key");
+
+    let base58 = ipns_ok(&["name", "convert", &name, "--to", "base58"]);
+    assert!(base58.starts_with("12D3KooW"));
+
+    let path = format!("/ipns/{}", base58.trim());
+    assert_eq!(
+        ipns_ok(&["name", "convert", &path, "--to", "base36"]).trim(),
+        name
+    );
+
+    assert!(ipns_ok(&["name", "convert", &name]).contains("cid:    bafz"));
+    assert!(!ipns(&["name", "convert", "bafkqaaa"]).status.success());
+}
@@ -0,0 +1,11 @@
+fn main() {
+    // The header is checked in, so C users do not need cbindgen.
+    // Regenerate it with `cargo build -p ipns-entry-ffi --features generate-header`
+    #[cfg(feature = "generate-header")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        cbindgen::generate(&crate_dir)
+            .expect("Unable to generate C header")
+            .write_to_file(format!("{crate_dir}/include/ipns_entry.h"));
+    }
+}
@@ -0,0 +1,240 @@
+#ifndef IPNS_ENTRY_H
+#define IPNS_ENTRY_H
+
+/* Generated with cbindgen, do not edit. Regenerate with `cargo build -p ipns-entry-ffi --features generate-header` */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * `key` is a 32 byte ed25519 secret key (seed)
+ */
+#define IPNS_KEY_ED25519_SEED 0
+
+/**
+ * `key` is a protobuf encoded libp2p private key, e.g. from Go's `crypto.MarshalPrivateKey`
+ */
+#define IPNS_KEY_PROTOBUF 1
+
+/**
+ * base58btc PeerId, e.g. `12D3KooW...`
+ */
+#define IPNS_NAME_BASE58 0
+
+/**
+ * `libp2p-key` CIDv1 in base36, e.g. `k51...`
+ */
+#define IPNS_NAME_BASE36 1
+
+/**
+ * `libp2p-key` CIDv1 in base32, e.g. `bafz...`
+ */
+#define IPNS_NAME_CID 2
+
+/**
+ * Result of every call
+ */
+typedef enum IpnsStatus {
+  IPNS_STATUS_OK = 0,
+  /**
+   * A required pointer was NULL
+   */
+  IPNS_STATUS_NULL_POINTER = 1,
+  /**
+   * A string was not UTF-8, or an unknown format was given
+   */
+  IPNS_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * The private key could not be decoded
+   */
+  IPNS_STATUS_INVALID_KEY = 3,
+  /**
+   * Signing failed
+   */
+  IPNS_STATUS_SIGNING = 4,
+  /**
+   * The record is not valid protobuf, or is larger than 10 KiB
+   */
+  IPNS_STATUS_DECODE = 5,
+  /**
+   * The record fields do not match IpnsEntry.data, or are missing
+   */
+  IPNS_STATUS_INVALID_RECORD = 6,
+  /**
+   * IpnsEntry.signatureV2 does not verify for the IPNS Name
+   */
+  IPNS_STATUS_INVALID_SIGNATURE = 7,
+  /**
+   * The record is valid but its validity is in the past
+   */
+  IPNS_STATUS_EXPIRED = 8,
+  /**
+   * The IPNS Name could not be parsed
+   */
+  IPNS_STATUS_INVALID_NAME = 9,
+  /**
+   * The library panicked, this is a bug
+   */
+  IPNS_STATUS_PANIC = 10,
+} IpnsStatus;
+
+/**
+ * Data of a new record
+ */
+typedef struct IpnsRecordParams {
+  /**
+   * NUL terminated value, e.g. `/ipfs/<cid>`
+   */
+  const char *value;
+  /**
+   * Expiry, seconds since the UNIX epoch
+   */
+  uint64_t validity_secs;
+  /**
+   * Nanoseconds added to `validity_secs`
+   */
+  uint32_t validity_nanos;
+  uint64_t sequence;
+  /**
+   * IpnsEntry.ttl, in nanoseconds
+   */
+  uint64_t ttl;
+} IpnsRecordParams;
+
+/**
+ * Bytes owned by the library, free with [ipns_buffer_free]
+ */
+typedef struct IpnsBuffer {
+  uint8_t *data;
+  size_t len;
+} IpnsBuffer;
+
+/**
+ * Decoded IpnsEntry.data of a record, free with [ipns_record_info_free]
+ */
+typedef struct IpnsRecordInfo {
+  struct IpnsBuffer value;
+  /**
+   * RFC3339 validity, not NUL terminated
+   */
+  struct IpnsBuffer validity;
+  int32_t validity_type;
+  uint64_t sequence;
+  /**
+   * IpnsEntry.ttl, in nanoseconds
+   */
+  uint64_t ttl;
+  /**
+   * Protobuf encoded IpnsEntry.pubKey, empty if the key is inlined in the IPNS Name
+   */
+  struct IpnsBuffer pub_key;
+} IpnsRecordInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Build, sign and encode a record. The protobuf bytes are written to `out_record`.
+ *
+ * # Safety
+ *
+ * `params` and `key` (`key_len` bytes) must be valid for reads, `out_record` for writes.
+ */
+enum IpnsStatus ipns_record_create(const struct IpnsRecordParams *params,
+                                   const uint8_t *key,
+                                   size_t key_len,
+                                   uint32_t key_format,
+                                   struct IpnsBuffer *out_record);
+
+/**
+ * Decode a record and its IpnsEntry.data into `out_info`. Does not validate the signature.
+ *
+ * # Safety
+ *
+ * `record` must be valid for reads of `record_len` bytes, `out_info` for writes.
+ */
+enum IpnsStatus ipns_record_decode(const uint8_t *record,
+                                   size_t record_len,
+                                   struct IpnsRecordInfo *out_info);
+
+/**
+ * Validate a record for the IPNS Name `name` (any form) at `now_secs` (seconds since the UNIX epoch).
+ *
+ * Returns [IpnsStatus::Ok] if the record is valid and unexpired, otherwise
+ * [IpnsStatus::InvalidSignature], [IpnsStatus::Expired], [IpnsStatus::InvalidRecord],
+ * [IpnsStatus::InvalidName] or [IpnsStatus::Decode].
+ *
+ * # Safety
+ *
+ * `record` must be valid for reads of `record_len` bytes, `name` a NUL terminated string.
+ */
+enum IpnsStatus ipns_record_validate(const uint8_t *record,
+                                     size_t record_len,
+                                     const char *name,
+                                     uint64_t now_secs);
+
+/**
+ * Parse an IPNS Name (base58 PeerId or `libp2p-key` CID, with or without `/ipns/`)
+ * and write it in `name_format` to `out_name`.
+ *
+ * # Safety
+ *
+ * `name` must be a NUL terminated string, `out_name` valid for writes.
+ */
+enum IpnsStatus ipns_name_parse(const char *name, uint32_t name_format, char **out_name);
+
+/**
+ * Write the IPNS Name of a private key in `name_format` to `out_name`
+ *
+ * # Safety
+ *
+ * `key` must be valid for reads of `key_len` bytes, `out_name` valid for writes.
+ */
+enum IpnsStatus ipns_key_name(const uint8_t *key,
+                              size_t key_len,
+                              uint32_t key_format,
+                              uint32_t name_format,
+                              char **out_name);
+
+/**
+ * Free a buffer returned by this library, and reset it to empty. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `buffer` must come from this library and not be freed twice.
+ */
+void ipns_buffer_free(struct IpnsBuffer *buffer);
+
+/**
+ * Free the buffers of an [IpnsRecordInfo]. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `info` must come from [ipns_record_decode] and not be freed twice.
+ */
+void ipns_record_info_free(struct IpnsRecordInfo *info);
+
+/**
+ * Free a string returned by this library. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `s` must come from this library and not be freed twice.
+ */
+void ipns_string_free(char *s);
+
+/**
+ * Message of the last error on this thread, or NULL. Owned by the library and valid
+ * until the next call on this thread, do not free it.
+ */
+const char *ipns_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* IPNS_ENTRY_H */
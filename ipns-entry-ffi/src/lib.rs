@@ -0,0 +1,561 @@
+//! # C ABI for ipns-entry
+//!
+//! Build, sign, encode, decode and validate IPNS Records from C, C++, Go (cgo) or any
+//! language with a C FFI, producing the same bytes as the Rust crate.
+//! The header is [include/ipns_entry.h](../include/ipns_entry.h).
+//!
+//! ## Memory ownership
+//!
+//! - Input pointers are borrowed for the duration of the call only.
+//! - Every [IpnsBuffer], [IpnsRecordInfo] and string returned through an `out_*` pointer is
+//!   owned by the caller, who frees it with [ipns_buffer_free], [ipns_record_info_free] or
+//!   [ipns_string_free]. Never free them with `free()`.
+//! - `out_*` pointers are only written on [IpnsStatus::Ok].
+//!
+//! ## Errors
+//!
+//! Every function returns an [IpnsStatus]. On error, [ipns_last_error] returns a message
+//! for the last failed call on the same thread.
+//!
+//! Panics are returned as [IpnsStatus::Panic] when built with unwinding, such as the
+//! `release-ffi` profile. With the workspace `release` profile they abort the process.
+use ipns_entry::entry::{IpnsEntry, MAX_RECORD_SIZE};
+use ipns_entry::name::IpnsName;
+use ipns_entry::signer::Signer;
+use ipns_entry::DataBuilder;
+use libp2p_identity::{Keypair, PeerId};
+use prost::Message;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Duration;
+
+/// `key` is a 32 byte ed25519 secret key (seed)
+pub const IPNS_KEY_ED25519_SEED: u32 = 0;
+/// `key` is a protobuf encoded libp2p private key, e.g. from Go's `crypto.MarshalPrivateKey`
+pub const IPNS_KEY_PROTOBUF: u32 = 1;
+
+/// base58btc PeerId, e.g. `12D3KooW...`
+pub const IPNS_NAME_BASE58: u32 = 0;
+/// `libp2p-key` CIDv1 in base36, e.g. `k51...`
+pub const IPNS_NAME_BASE36: u32 = 1;
+/// `libp2p-key` CIDv1 in base32, e.g. `bafz...`
+pub const IPNS_NAME_CID: u32 = 2;
+
+/// Result of every call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpnsStatus {
+    Ok = 0,
+    /// A required pointer was NULL
+    NullPointer = 1,
+    /// A string was not UTF-8, or an unknown format was given
+    InvalidArgument = 2,
+    /// The private key could not be decoded
+    InvalidKey = 3,
+    /// Signing failed
+    Signing = 4,
+    /// The record is not valid protobuf, or is larger than 10 KiB
+    Decode = 5,
+    /// The record fields do not match IpnsEntry.data, or are missing
+    InvalidRecord = 6,
+    /// IpnsEntry.signatureV2 does not verify for the IPNS Name
+    InvalidSignature = 7,
+    /// The record is valid but its validity is in the past
+    Expired = 8,
+    /// The IPNS Name could not be parsed
+    InvalidName = 9,
+    /// The library panicked, this is a bug
+    Panic = 10,
+}
+
+/// Bytes owned by the library, free with [ipns_buffer_free]
+#[repr(C)]
+#[derive(Debug)]
+pub struct IpnsBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl IpnsBuffer {
+    fn empty() -> Self {
+        IpnsBuffer {
+            data: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+impl From<Vec<u8>> for IpnsBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        IpnsBuffer { data, len }
+    }
+}
+
+/// Data of a new record
+#[repr(C)]
+#[derive(Debug)]
+pub struct IpnsRecordParams {
+    /// NUL terminated value, e.g. `/ipfs/<cid>`
+    pub value: *const c_char,
+    /// Expiry, seconds since the UNIX epoch
+    pub validity_secs: u64,
+    /// Nanoseconds added to `validity_secs`
+    pub validity_nanos: u32,
+    pub sequence: u64,
+    /// IpnsEntry.ttl, in nanoseconds
+    pub ttl: u64,
+}
+
+/// Decoded IpnsEntry.data of a record, free with [ipns_record_info_free]
+#[repr(C)]
+#[derive(Debug)]
+pub struct IpnsRecordInfo {
+    pub value: IpnsBuffer,
+    /// RFC3339 validity, not NUL terminated
+    pub validity: IpnsBuffer,
+    pub validity_type: i32,
+    pub sequence: u64,
+    /// IpnsEntry.ttl, in nanoseconds
+    pub ttl: u64,
+    /// Protobuf encoded IpnsEntry.pubKey, empty if the key is inlined in the IPNS Name
+    pub pub_key: IpnsBuffer,
+}
+
+struct Error {
+    status: IpnsStatus,
+    message: String,
+}
+
+fn error(status: IpnsStatus, message: impl Display) -> Error {
+    Error {
+        status,
+        message: message.to_string(),
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: Option<String>) {
+    let message = message.map(|m| CString::new(m.replace('\0', " ")).expect("NULs replaced"));
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Run `f`, turning errors and panics into an [IpnsStatus] and the last error message
+fn ffi(f: impl FnOnce() -> Result<(), Error>) -> IpnsStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            set_last_error(None);
+            IpnsStatus::Ok
+        }
+        Ok(Err(e)) => {
+            set_last_error(Some(e.message));
+            e.status
+        }
+        Err(_) => {
+            set_last_error(Some("ipns-entry panicked".to_string()));
+            IpnsStatus::Panic
+        }
+    }
+}
+
+unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], Error> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(error(IpnsStatus::NullPointer, "NULL bytes")),
+        (false, _) => Ok(std::slice::from_raw_parts(ptr, len)),
+    }
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Result<&'a str, Error> {
+    if ptr.is_null() {
+        return Err(error(IpnsStatus::NullPointer, "NULL string"));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|e| error(IpnsStatus::InvalidArgument, e))
+}
+
+unsafe fn out_arg<'a, T>(ptr: *mut T) -> Result<&'a mut T, Error> {
+    ptr.as_mut()
+        .ok_or_else(|| error(IpnsStatus::NullPointer, "NULL out pointer"))
+}
+
+fn keypair(key: &[u8], key_format: u32) -> Result<Keypair, Error> {
+    let keypair = match key_format {
+        IPNS_KEY_ED25519_SEED => Keypair::ed25519_from_bytes(key.to_vec())
+            .map_err(|e| error(IpnsStatus::InvalidKey, e))?,
+        IPNS_KEY_PROTOBUF => {
+            Keypair::from_protobuf_encoding(key).map_err(|e| error(IpnsStatus::InvalidKey, e))?
+        }
+        other => {
+            return Err(error(
+                IpnsStatus::InvalidArgument,
+                format!("Unknown key format {other}"),
+            ))
+        }
+    };
+    Ok(keypair)
+}
+
+fn format_name(name: IpnsName, name_format: u32) -> Result<CString, Error> {
+    let name = match name_format {
+        IPNS_NAME_BASE58 => name.to_base58(),
+        IPNS_NAME_BASE36 => name.to_base36(),
+        IPNS_NAME_CID => name.to_cid().to_string(),
+        other => {
+            return Err(error(
+                IpnsStatus::InvalidArgument,
+                format!("Unknown name format {other}"),
+            ))
+        }
+    };
+    Ok(CString::new(name).expect("names have no NUL"))
+}
+
+/// Build, sign and encode a record. The protobuf bytes are written to `out_record`.
+///
+/// # Safety
+///
+/// `params` and `key` (`key_len` bytes) must be valid for reads, `out_record` for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_record_create(
+    params: *const IpnsRecordParams,
+    key: *const u8,
+    key_len: usize,
+    key_format: u32,
+    out_record: *mut IpnsBuffer,
+) -> IpnsStatus {
+    ffi(|| {
+        let params = params
+            .as_ref()
+            .ok_or_else(|| error(IpnsStatus::NullPointer, "NULL params"))?;
+        let value = str_arg(params.value)?;
+        let keypair = keypair(bytes_arg(key, key_len)?, key_format)?;
+        let out_record = out_arg(out_record)?;
+
+        if params.validity_nanos >= 1_000_000_000 {
+            return Err(error(
+                IpnsStatus::InvalidArgument,
+                "validity_nanos must be less than one second",
+            ));
+        }
+        let validity = Duration::new(params.validity_secs, params.validity_nanos);
+
+        let (data, signables) = DataBuilder::new(value)
+            .validity_at(validity)
+            .sequence(params.sequence)
+            .ttl(params.ttl)
+            .build();
+
+        let signed = Signer::new(keypair.clone())
+            .sign(signables)
+            .map_err(|e| error(IpnsStatus::Signing, e))?;
+
+        let mut entry = IpnsEntry::new(data, signed);
+        entry.embed_public_key(&keypair.public());
+
+        if entry.encoded_len() > MAX_RECORD_SIZE {
+            return Err(error(
+                IpnsStatus::InvalidArgument,
+                "IpnsEntry exceeds 10 KiB",
+            ));
+        }
+
+        *out_record = entry.to_bytes().into();
+        Ok(())
+    })
+}
+
+/// Decode a record and its IpnsEntry.data into `out_info`. Does not validate the signature.
+///
+/// # Safety
+///
+/// `record` must be valid for reads of `record_len` bytes, `out_info` for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_record_decode(
+    record: *const u8,
+    record_len: usize,
+    out_info: *mut IpnsRecordInfo,
+) -> IpnsStatus {
+    ffi(|| {
+        let record = bytes_arg(record, record_len)?;
+        let out_info = out_arg(out_info)?;
+
+        let entry = IpnsEntry::from_bytes(record).map_err(|e| error(IpnsStatus::Decode, e))?;
+        let data = entry
+            .decode_data()
+            .map_err(|e| error(IpnsStatus::InvalidRecord, e))?;
+
+        *out_info = IpnsRecordInfo {
+            value: data.value.into(),
+            validity: data.validity.into(),
+            validity_type: data.validity_type,
+            sequence: data.sequence,
+            ttl: data.ttl,
+            pub_key: entry
+                .pub_key
+                .map(IpnsBuffer::from)
+                .unwrap_or_else(IpnsBuffer::empty),
+        };
+        Ok(())
+    })
+}
+
+/// Validate a record for the IPNS Name `name` (any form) at `now_secs` (seconds since the UNIX epoch).
+///
+/// Returns [IpnsStatus::Ok] if the record is valid and unexpired, otherwise
+/// [IpnsStatus::InvalidSignature], [IpnsStatus::Expired], [IpnsStatus::InvalidRecord],
+/// [IpnsStatus::InvalidName] or [IpnsStatus::Decode].
+///
+/// # Safety
+///
+/// `record` must be valid for reads of `record_len` bytes, `name` a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_record_validate(
+    record: *const u8,
+    record_len: usize,
+    name: *const c_char,
+    now_secs: u64,
+) -> IpnsStatus {
+    ffi(|| {
+        let record = bytes_arg(record, record_len)?;
+        let name: IpnsName = str_arg(name)?
+            .parse()
+            .map_err(|e| error(IpnsStatus::InvalidName, e))?;
+
+        let entry = IpnsEntry::from_bytes(record).map_err(|e| error(IpnsStatus::Decode, e))?;
+
+        match entry.is_valid_for(&name.peer_id()) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(error(
+                    IpnsStatus::InvalidSignature,
+                    "IpnsEntry.signatureV2 does not verify",
+                ))
+            }
+            Err(e) => return Err(error(IpnsStatus::InvalidRecord, e)),
+        }
+
+        let expired = entry
+            .is_expired_at(Duration::from_secs(now_secs))
+            .map_err(|e| error(IpnsStatus::InvalidRecord, e))?;
+        if expired {
+            return Err(error(IpnsStatus::Expired, "IPNS Record has expired"));
+        }
+
+        Ok(())
+    })
+}
+
+/// Parse an IPNS Name (base58 PeerId or `libp2p-key` CID, with or without `/ipns/`)
+/// and write it in `name_format` to `out_name`.
+///
+/// # Safety
+///
+/// `name` must be a NUL terminated string, `out_name` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_name_parse(
+    name: *const c_char,
+    name_format: u32,
+    out_name: *mut *mut c_char,
+) -> IpnsStatus {
+    ffi(|| {
+        let name: IpnsName = str_arg(name)?
+            .parse()
+            .map_err(|e| error(IpnsStatus::InvalidName, e))?;
+        let out_name = out_arg(out_name)?;
+
+        *out_name = format_name(name, name_format)?.into_raw();
+        Ok(())
+    })
+}
+
+/// Write the IPNS Name of a private key in `name_format` to `out_name`
+///
+/// # Safety
+///
+/// `key` must be valid for reads of `key_len` bytes, `out_name` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_key_name(
+    key: *const u8,
+    key_len: usize,
+    key_format: u32,
+    name_format: u32,
+    out_name: *mut *mut c_char,
+) -> IpnsStatus {
+    ffi(|| {
+        let keypair = keypair(bytes_arg(key, key_len)?, key_format)?;
+        let out_name = out_arg(out_name)?;
+
+        let name = IpnsName::from(PeerId::from_public_key(&keypair.public()));
+        *out_name = format_name(name, name_format)?.into_raw();
+        Ok(())
+    })
+}
+
+/// Free a buffer returned by this library, and reset it to empty. NULL is ignored.
+///
+/// # Safety
+///
+/// `buffer` must come from this library and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_buffer_free(buffer: *mut IpnsBuffer) {
+    if let Some(buffer) = buffer.as_mut() {
+        if !buffer.data.is_null() {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                buffer.data,
+                buffer.len,
+            )));
+        }
+        *buffer = IpnsBuffer::empty();
+    }
+}
+
+/// Free the buffers of an [IpnsRecordInfo]. NULL is ignored.
+///
+/// # Safety
+///
+/// `info` must come from [ipns_record_decode] and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_record_info_free(info: *mut IpnsRecordInfo) {
+    if let Some(info) = info.as_mut() {
+        ipns_buffer_free(&mut info.value);
+        ipns_buffer_free(&mut info.validity);
+        ipns_buffer_free(&mut info.pub_key);
+    }
+}
+
+/// Free a string returned by this library. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must come from this library and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn ipns_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Message of the last error on this thread, or NULL. Owned by the library and valid
+/// until the next call on this thread, do not free it.
+#[no_mangle]
+pub extern "C" fn ipns_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 32] = [1; 32];
+    const VALIDITY: u64 = 4_102_444_800; // 2100-01-01
+
+    fn create(value: &CStr, validity_secs: u64) -> Result<Vec<u8>, IpnsStatus> {
+        let params = IpnsRecordParams {
+            value: value.as_ptr(),
+            validity_secs,
+            validity_nanos: 0,
+            sequence: 3,
+            ttl: 60,
+        };
+        let mut out = IpnsBuffer::empty();
+        let status = unsafe {
+            ipns_record_create(
+                &params,
+                SEED.as_ptr(),
+                SEED.len(),
+                IPNS_KEY_ED25519_SEED,
+                &mut out,
+            )
+        };
+        if status != IpnsStatus::Ok {
+            return Err(status);
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(out.data, out.len) }.to_vec();
+        unsafe { ipns_buffer_free(&mut out) };
+        assert!(out.data.is_null());
+        Ok(bytes)
+    }
+
+    /// Without `c"..."` literals, which cbindgen 0.24 cannot parse
+    fn cstr(bytes: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(bytes).unwrap()
+    }
+
+    fn name() -> CString {
+        let mut out = std::ptr::null_mut();
+        let status = unsafe {
+            ipns_key_name(
+                SEED.as_ptr(),
+                SEED.len(),
+                IPNS_KEY_ED25519_SEED,
+                IPNS_NAME_BASE36,
+                &mut out,
+            )
+        };
+        assert_eq!(status, IpnsStatus::Ok);
+        let name = unsafe { CStr::from_ptr(out) }.to_owned();
+        unsafe { ipns_string_free(out) };
+        name
+    }
+
+    #[test]
+    fn test_matches_rust_records() {
+        let record = create(cstr(b"/ipfs/bafkqaaa\0"), VALIDITY).unwrap();
+
+        let keypair = Keypair::ed25519_from_bytes(SEED).unwrap();
+        let (data, signables) = DataBuilder::new_at("/ipfs/bafkqaaa", Duration::ZERO)
+            .validity_at(Duration::from_secs(VALIDITY))
+            .sequence(3)
+            .ttl(60)
+            .build();
+        let entry = IpnsEntry::new(data, Signer::new(keypair).sign(signables).unwrap());
+
+        assert_eq!(record, entry.to_bytes());
+    }
+
+    #[test]
+    fn test_validate() {
+        let record = create(cstr(b"/ipfs/bafkqaaa\0"), VALIDITY).unwrap();
+        let name = name();
+        let validate = |record: &[u8], now| unsafe {
+            ipns_record_validate(record.as_ptr(), record.len(), name.as_ptr(), now)
+        };
+
+        assert_eq!(validate(&record, 0), IpnsStatus::Ok);
+        assert_eq!(validate(&record, VALIDITY + 1), IpnsStatus::Expired);
+
+        let mut tampered = record.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert_ne!(validate(&tampered, 0), IpnsStatus::Ok);
+        assert!(!ipns_last_error().is_null());
+
+        assert_eq!(validate(&record[..10], 0), IpnsStatus::Decode);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut out = IpnsBuffer::empty();
+        let status =
+            unsafe { ipns_record_create(std::ptr::null(), SEED.as_ptr(), SEED.len(), 0, &mut out) };
+        assert_eq!(status, IpnsStatus::NullPointer);
+
+        let mut name = std::ptr::null_mut();
+        let status =
+            unsafe { ipns_name_parse(cstr(b"bafkqaaa\0").as_ptr(), IPNS_NAME_BASE58, &mut name) };
+        assert_eq!(status, IpnsStatus::InvalidName);
+        assert!(name.is_null());
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Exercises the C API end to end, run by tests/c_api.rs.
+ * Exits non-zero on the first failed check.
+ */
+#include <stdio.h>
+#include <string.h>
+
+#include "ipns_entry.h"
+
+#define VALIDITY 4102444800ULL /* 2100-01-01 */
+
+#define CHECK(cond)                                                              \
+  do {                                                                           \
+    if (!(cond)) {                                                               \
+      const char *err = ipns_last_error();                                       \
+      fprintf(stderr, "%s:%d: check failed: %s (%s)\n", __FILE__, __LINE__,      \
+              #cond, err ? err : "no error");                                    \
+      return 1;                                                                  \
+    }                                                                            \
+  } while (0)
+
+int main(void) {
+  uint8_t seed[32];
+  memset(seed, 1, sizeof(seed));
+
+  /* the IPNS Name of the key, in every form */
+  char *base36 = NULL;
+  char *base58 = NULL;
+  CHECK(ipns_key_name(seed, sizeof(seed), IPNS_KEY_ED25519_SEED, IPNS_NAME_BASE36,
+                      &base36) == IPNS_STATUS_OK);
+  CHECK(strncmp(base36, "k51", 3) == 0);
+  CHECK(ipns_name_parse(base36, IPNS_NAME_BASE58, &base58) == IPNS_STATUS_OK);
+  CHECK(strncmp(base58, "12D3KooW", 8) == 0);
+
+  char ipns_path[128];
+  char *roundtrip = NULL;
+  snprintf(ipns_path, sizeof(ipns_path), "/ipns/%s", base58);
+  CHECK(ipns_name_parse(ipns_path, IPNS_NAME_BASE36, &roundtrip) == IPNS_STATUS_OK);
+  CHECK(strcmp(roundtrip, base36) == 0);
+  ipns_string_free(roundtrip);
+
+  /* create */
+  IpnsRecordParams params = {
+      .value = "/ipfs/bafkqaaa",
+      .validity_secs = VALIDITY,
+      .validity_nanos = 0,
+      .sequence = 7,
+      .ttl = 60,
+  };
+  IpnsBuffer record = {0};
+  CHECK(ipns_record_create(&params, seed, sizeof(seed), IPNS_KEY_ED25519_SEED, &record) ==
+        IPNS_STATUS_OK);
+  CHECK(record.data != NULL && record.len > 0);
+
+  /* records are deterministic for the same key and data */
+  IpnsBuffer again = {0};
+  CHECK(ipns_record_create(&params, seed, sizeof(seed), IPNS_KEY_ED25519_SEED, &again) ==
+        IPNS_STATUS_OK);
+  CHECK(again.len == record.len && memcmp(again.data, record.data, record.len) == 0);
+  ipns_buffer_free(&again);
+  CHECK(again.data == NULL && again.len == 0);
+
+  /* decode */
+  IpnsRecordInfo info;
+  CHECK(ipns_record_decode(record.data, record.len, &info) == IPNS_STATUS_OK);
+  CHECK(info.value.len == strlen("/ipfs/bafkqaaa"));
+  CHECK(memcmp(info.value.data, "/ipfs/bafkqaaa", info.value.len) == 0);
+  CHECK(info.validity.len == strlen("2100-01-01T00:00:00.000000000Z"));
+  CHECK(memcmp(info.validity.data, "2100-01-01T00:00:00.000000000Z", info.validity.len) == 0);
+  CHECK(info.validity_type == 0);
+  CHECK(info.sequence == 7);
+  CHECK(info.ttl == 60);
+  CHECK(info.pub_key.len == 0); /* ed25519 keys are inlined in the name */
+  ipns_record_info_free(&info);
+
+  /* validate */
+  CHECK(ipns_record_validate(record.data, record.len, base36, 0) == IPNS_STATUS_OK);
+  CHECK(ipns_record_validate(record.data, record.len, base58, 0) == IPNS_STATUS_OK);
+  CHECK(ipns_last_error() == NULL);
+  CHECK(ipns_record_validate(record.data, record.len, base36, VALIDITY + 1) ==
+        IPNS_STATUS_EXPIRED);
+  CHECK(ipns_last_error() != NULL);
+
+  /* a record signed by another key does not validate for this name */
+  uint8_t other_seed[32];
+  memset(other_seed, 2, sizeof(other_seed));
+  IpnsBuffer other = {0};
+  CHECK(ipns_record_create(&params, other_seed, sizeof(other_seed), IPNS_KEY_ED25519_SEED,
+                           &other) == IPNS_STATUS_OK);
+  CHECK(ipns_record_validate(other.data, other.len, base36, 0) != IPNS_STATUS_OK);
+  ipns_buffer_free(&other);
+
+  /* errors */
+  CHECK(ipns_record_validate(record.data, 10, base36, 0) == IPNS_STATUS_DECODE);
+  CHECK(ipns_record_validate(record.data, record.len, "not a name", 0) ==
+        IPNS_STATUS_INVALID_NAME);
+  CHECK(ipns_record_create(NULL, seed, sizeof(seed), IPNS_KEY_ED25519_SEED, &other) ==
+        IPNS_STATUS_NULL_POINTER);
+  CHECK(ipns_record_create(&params, seed, 3, IPNS_KEY_ED25519_SEED, &other) ==
+        IPNS_STATUS_INVALID_KEY);
+  CHECK(ipns_record_create(&params, seed, sizeof(seed), 42, &other) ==
+        IPNS_STATUS_INVALID_ARGUMENT);
+  CHECK(other.data == NULL);
+
+  /* freeing NULL is a no-op */
+  ipns_buffer_free(NULL);
+  ipns_record_info_free(NULL);
+  ipns_string_free(NULL);
+
+  ipns_buffer_free(&record);
+  ipns_string_free(base36);
+  ipns_string_free(base58);
+
+  printf("ok\n");
+  return 0;
+}
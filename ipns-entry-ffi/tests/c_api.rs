@@ -0,0 +1,50 @@
+//! Compiles tests/c/test_ipns.c against include/ipns_entry.h and the static library, then runs it.
+//!
+//! Uses `$CC`, or `cc` from the PATH. Skipped on Windows.
+#![cfg(unix)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The target directory holding `libipns_entry_ffi.a`, the test binary lives in its `deps/`
+fn target_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.ancestors()
+        .find(|dir| dir.join("libipns_entry_ffi.a").exists())
+        .expect("libipns_entry_ffi.a is built next to the test binary")
+        .to_path_buf()
+}
+
+#[test]
+fn test_c_program() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_ipns");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let mut compile = Command::new(cc);
+    compile
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/c/test_ipns.c"))
+        .arg(target_dir().join("libipns_entry_ffi.a"))
+        .arg("-o")
+        .arg(&out)
+        .args(["-lpthread", "-ldl", "-lm"]);
+    if cfg!(target_os = "macos") {
+        compile.args(["-framework", "Security", "-framework", "CoreFoundation"]);
+    }
+
+    let status = compile.status().expect("C compiler runs");
+    assert!(status.success(), "compiling test_ipns.c failed");
+
+    let output = Command::new(&out).output().unwrap();
+    assert!(
+        output.status.success(),
+        "test_ipns failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
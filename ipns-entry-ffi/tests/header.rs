@@ -0,0 +1,17 @@
+//! The checked-in C header matches the Rust ABI
+
+#[test]
+fn test_header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let mut generated = Vec::new();
+    cbindgen::generate(crate_dir)
+        .expect("Unable to generate C header")
+        .write(&mut generated);
+
+    let checked_in = std::fs::read(format!("{crate_dir}/include/ipns_entry.h")).unwrap();
+    assert!(
+        generated == checked_in,
+        "include/ipns_entry.h is out of date, regenerate it with \
+         `cargo build -p ipns-entry-ffi --features generate-header`"
+    );
+}
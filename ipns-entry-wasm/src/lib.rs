@@ -0,0 +1,332 @@
+//! # ipns-entry for JavaScript
+//!
+//! [wasm-bindgen](https://rustwasm.github.io/docs/wasm-bindgen/) bindings to create, sign,
+//! decode and verify IPNS Records in the browser or Node, with the Rust implementation.
+//!
+//! ```js
+//! import { DataBuilder, IpnsRecord, Keypair, IpnsName } from "ipns-entry-wasm";
+//!
+//! const keypair = Keypair.generateEd25519();
+//! const record = new DataBuilder("/ipfs/bafkqaaa").sequence(1n).build().sign(keypair);
+//! const bytes = record.toBytes();
+//!
+//! const received = IpnsRecord.fromBytes(bytes);
+//! received.validate(keypair.name); // throws if invalid or expired
+//! ```
+//!
+//! Time comes from the JS `Date`, as `std::time::SystemTime` is not available in
+//! `wasm32-unknown-unknown`.
+use ipns_entry::cbor::Data;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::signer::{Signables, Signed, Signer};
+use js_sys::{Date, Function, Promise, Uint8Array};
+use libp2p_identity::PublicKey;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors cross into JS as `Error` objects
+fn js_error(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// Current time since the UNIX epoch, from the JS clock
+fn now() -> Duration {
+    Duration::from_millis(Date::now() as u64)
+}
+
+/// Builds the data of an IPNS Record. Each setter returns the builder, so calls can be chained.
+#[wasm_bindgen]
+pub struct DataBuilder {
+    inner: ipns_entry::DataBuilder,
+}
+
+#[wasm_bindgen]
+impl DataBuilder {
+    /// New builder for `value`, e.g. `/ipfs/<cid>`. Validity defaults to 48 hours from now.
+    #[wasm_bindgen(constructor)]
+    pub fn new(value: &str) -> DataBuilder {
+        DataBuilder {
+            inner: ipns_entry::DataBuilder::new_at(value, now()),
+        }
+    }
+
+    pub fn value(mut self, value: &str) -> DataBuilder {
+        self.inner.value(value);
+        self
+    }
+
+    /// Expiry of the record
+    pub fn validity(mut self, validity: &Date) -> DataBuilder {
+        self.inner
+            .validity_at(Duration::from_millis(validity.get_time() as u64));
+        self
+    }
+
+    pub fn sequence(mut self, sequence: u64) -> DataBuilder {
+        self.inner.sequence(sequence);
+        self
+    }
+
+    #[wasm_bindgen(js_name = incrementSequence)]
+    pub fn increment_sequence(mut self) -> DataBuilder {
+        self.inner.increment_sequence();
+        self
+    }
+
+    /// IpnsEntry.ttl, in nanoseconds
+    pub fn ttl(mut self, ttl: u64) -> DataBuilder {
+        self.inner.ttl(ttl);
+        self
+    }
+
+    /// Build the record data and the bytes to sign
+    pub fn build(&self) -> UnsignedRecord {
+        let (data, signables) = self.inner.build();
+        UnsignedRecord { data, signables }
+    }
+}
+
+/// Record data waiting for its signatures
+#[wasm_bindgen]
+pub struct UnsignedRecord {
+    data: Data,
+    signables: Signables,
+}
+
+#[wasm_bindgen]
+impl UnsignedRecord {
+    /// Bytes to sign for IpnsEntry.signatureV1
+    #[wasm_bindgen(getter, js_name = signableV1)]
+    pub fn signable_v1(&self) -> Vec<u8> {
+        self.signables.v1.clone()
+    }
+
+    /// Bytes to sign for IpnsEntry.signatureV2
+    #[wasm_bindgen(getter, js_name = signableV2)]
+    pub fn signable_v2(&self) -> Vec<u8> {
+        self.signables.v2.clone()
+    }
+
+    /// Sign with a libp2p key held by this module
+    pub fn sign(self, keypair: &Keypair) -> Result<IpnsRecord, JsError> {
+        let signed = Signer::new(keypair.inner.clone())
+            .sign(self.signables)
+            .map_err(js_error)?;
+
+        let mut entry = IpnsEntry::new(self.data, signed);
+        entry.embed_public_key(&keypair.inner.public());
+        Ok(IpnsRecord::from(entry))
+    }
+
+    /// Sign with an external signer, such as WebCrypto or a wallet:
+    /// `signer(bytes: Uint8Array) => Uint8Array | Promise<Uint8Array>`.
+    ///
+    /// `public_key` is the protobuf encoded libp2p public key. It is embedded in the record
+    /// when the key is not inlined in the IPNS Name (RSA).
+    #[wasm_bindgen(js_name = signWith)]
+    pub async fn sign_with(
+        self,
+        signer: Function,
+        public_key: Option<Vec<u8>>,
+    ) -> Result<IpnsRecord, JsValue> {
+        let public_key = public_key
+            .map(|pk| PublicKey::try_decode_protobuf(&pk).map_err(js_error))
+            .transpose()?;
+
+        let signed = Signed {
+            v1: call_signer(&signer, &self.signables.v1).await?,
+            v2: call_signer(&signer, &self.signables.v2).await?,
+        };
+
+        let mut entry = IpnsEntry::new(self.data, signed);
+        if let Some(public_key) = public_key {
+            entry.embed_public_key(&public_key);
+        }
+        Ok(IpnsRecord::from(entry))
+    }
+}
+
+/// Call a JS signer, awaiting the result if it returns a Promise
+async fn call_signer(signer: &Function, bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let result = signer.call1(&JsValue::NULL, &Uint8Array::from(bytes))?;
+    let signature = JsFuture::from(Promise::resolve(&result)).await?;
+
+    signature
+        .dyn_into::<Uint8Array>()
+        .map(|sig| sig.to_vec())
+        .map_err(|_| js_error("signer must return a Uint8Array").into())
+}
+
+/// A signed IPNS Record
+#[wasm_bindgen]
+pub struct IpnsRecord {
+    entry: IpnsEntry,
+    /// Decoded IpnsEntry.data, if present and valid DAG-CBOR
+    data: Option<Data>,
+}
+
+impl From<IpnsEntry> for IpnsRecord {
+    fn from(entry: IpnsEntry) -> Self {
+        let data = entry.decode_data().ok();
+        IpnsRecord { entry, data }
+    }
+}
+
+#[wasm_bindgen]
+impl IpnsRecord {
+    /// Decode the protobuf bytes of a record, as received from the network
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<IpnsRecord, JsError> {
+        IpnsEntry::from_bytes(bytes)
+            .map(IpnsRecord::from)
+            .map_err(js_error)
+    }
+
+    /// Encode to protobuf bytes, ready to publish
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entry.to_bytes()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> Option<String> {
+        self.data
+            .as_ref()
+            .map(|data| String::from_utf8_lossy(&data.value).into_owned())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn validity(&self) -> Option<String> {
+        self.data
+            .as_ref()
+            .map(|data| String::from_utf8_lossy(&data.validity).into_owned())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sequence(&self) -> Option<u64> {
+        self.data.as_ref().map(|data| data.sequence)
+    }
+
+    /// IpnsEntry.ttl, in nanoseconds
+    #[wasm_bindgen(getter)]
+    pub fn ttl(&self) -> Option<u64> {
+        self.data.as_ref().map(|data| data.ttl)
+    }
+
+    /// Check the signature and fields against the IPNS Name, in any of its forms.
+    /// Returns `false` for a bad signature, throws for a malformed record.
+    #[wasm_bindgen(js_name = isValidFor)]
+    pub fn is_valid_for(&self, name: &str) -> Result<bool, JsError> {
+        let name: ipns_entry::name::IpnsName = name.parse().map_err(js_error)?;
+        self.entry.is_valid_for(&name.peer_id()).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = isExpired)]
+    pub fn is_expired(&self) -> Result<bool, JsError> {
+        self.entry.is_expired_at(now()).map_err(js_error)
+    }
+
+    /// Throws unless the record is valid for the IPNS Name and has not expired
+    pub fn validate(&self, name: &str) -> Result<(), JsError> {
+        if !self.is_valid_for(name)? {
+            return Err(js_error("Invalid IpnsEntry.signatureV2"));
+        }
+        if self.is_expired()? {
+            return Err(js_error("IPNS Record has expired"));
+        }
+        Ok(())
+    }
+
+    /// JSON view of the record, used by `JSON.stringify`
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<JsValue, JsValue> {
+        let json = serde_json::to_string(&self.entry).map_err(js_error)?;
+        js_sys::JSON::parse(&json)
+    }
+}
+
+/// A libp2p keypair
+#[wasm_bindgen]
+pub struct Keypair {
+    inner: libp2p_identity::Keypair,
+}
+
+#[wasm_bindgen]
+impl Keypair {
+    #[wasm_bindgen(js_name = generateEd25519)]
+    pub fn generate_ed25519() -> Keypair {
+        Keypair {
+            inner: libp2p_identity::Keypair::generate_ed25519(),
+        }
+    }
+
+    /// Decode a protobuf encoded libp2p private key
+    #[wasm_bindgen(js_name = fromProtobuf)]
+    pub fn from_protobuf(bytes: &[u8]) -> Result<Keypair, JsError> {
+        libp2p_identity::Keypair::from_protobuf_encoding(bytes)
+            .map(|inner| Keypair { inner })
+            .map_err(js_error)
+    }
+
+    /// Protobuf encoded private key, keep it secret
+    #[wasm_bindgen(js_name = toProtobuf)]
+    pub fn to_protobuf(&self) -> Result<Vec<u8>, JsError> {
+        self.inner.to_protobuf_encoding().map_err(js_error)
+    }
+
+    /// Protobuf encoded public key
+    #[wasm_bindgen(getter, js_name = publicKey)]
+    pub fn public_key(&self) -> Vec<u8> {
+        self.inner.public().encode_protobuf()
+    }
+
+    /// IPNS Name of this key, as a base36 CID
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        ipns_entry::name::IpnsName::from(self.inner.public().to_peer_id()).to_base36()
+    }
+}
+
+/// An IPNS Name
+#[wasm_bindgen]
+pub struct IpnsName {
+    inner: ipns_entry::name::IpnsName,
+}
+
+#[wasm_bindgen]
+impl IpnsName {
+    /// Parse a base58 PeerId or a `libp2p-key` CID, with or without `/ipns/`
+    pub fn parse(name: &str) -> Result<IpnsName, JsError> {
+        name.parse()
+            .map(|inner| IpnsName { inner })
+            .map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = toBase58)]
+    pub fn to_base58(&self) -> String {
+        self.inner.to_base58()
+    }
+
+    #[wasm_bindgen(js_name = toBase36)]
+    pub fn to_base36(&self) -> String {
+        self.inner.to_base36()
+    }
+
+    /// CIDv1 in base32
+    #[wasm_bindgen(js_name = toCid)]
+    pub fn to_cid(&self) -> String {
+        self.inner.to_cid().to_string()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_string_js(&self) -> String {
+        self.inner.to_string()
+    }
+}
+
+/// Parse an IPNS Name, see [IpnsName::parse]
+#[wasm_bindgen(js_name = parseName)]
+pub fn parse_name(name: &str) -> Result<IpnsName, JsError> {
+    IpnsName::parse(name)
+}
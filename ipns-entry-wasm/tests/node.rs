@@ -0,0 +1,92 @@
+//! Run with `wasm-pack test --node`
+#![cfg(target_arch = "wasm32")]
+
+use ipns_entry_wasm::{parse_name, DataBuilder, IpnsRecord, Keypair};
+use js_sys::{Date, Function, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn sign_and_validate() {
+    let keypair = Keypair::generate_ed25519();
+    let record = DataBuilder::new("/ipfs/bafkqaaa")
+        .sequence(7)
+        .ttl(60)
+        .build()
+        .sign(&keypair)
+        .unwrap();
+
+    let received = IpnsRecord::from_bytes(&record.to_bytes()).unwrap();
+    assert_eq!(received.value().as_deref(), Some("/ipfs/bafkqaaa"));
+    assert_eq!(received.sequence(), Some(7));
+    assert_eq!(received.ttl(), Some(60));
+    assert!(received.is_valid_for(&keypair.name()).unwrap());
+    assert!(received.validate(&keypair.name()).is_ok());
+
+    // another name
+    let other = Keypair::generate_ed25519();
+    assert!(!received.is_valid_for(&other.name()).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn expired_record_fails_validation() {
+    let keypair = Keypair::generate_ed25519();
+    let record = DataBuilder::new("/ipfs/bafkqaaa")
+        .validity(&Date::new(&JsValue::from_f64(0.0)))
+        .build()
+        .sign(&keypair)
+        .unwrap();
+
+    assert!(record.is_valid_for(&keypair.name()).unwrap());
+    assert!(record.is_expired().unwrap());
+    assert!(record.validate(&keypair.name()).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn sign_with_external_signer() {
+    let key_bytes = Keypair::generate_ed25519().to_protobuf().unwrap();
+    let keypair = Keypair::from_protobuf(&key_bytes).unwrap();
+
+    // stands in for WebCrypto or a wallet
+    let signing_key = libp2p_identity::Keypair::from_protobuf_encoding(&key_bytes).unwrap();
+    let signer = Closure::<dyn Fn(Uint8Array) -> Uint8Array>::new(move |bytes: Uint8Array| {
+        let signature = signing_key.sign(&bytes.to_vec()).unwrap();
+        Uint8Array::from(signature.as_slice())
+    });
+
+    let record = DataBuilder::new("/ipfs/bafkqaaa")
+        .build()
+        .sign_with(signer.as_ref().unchecked_ref::<Function>().clone(), None)
+        .await
+        .unwrap();
+
+    assert!(record.validate(&keypair.name()).is_ok());
+}
+
+#[wasm_bindgen_test]
+async fn sign_with_rejects_bad_signer_output() {
+    let signer = Function::new_with_args("bytes", "return 'not bytes'");
+    let result = DataBuilder::new("/ipfs/bafkqaaa")
+        .build()
+        .sign_with(signer, None)
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+fn name_forms() {
+    let keypair = Keypair::generate_ed25519();
+    let name = parse_name(&keypair.name()).unwrap();
+
+    for form in [
+        name.to_base58(),
+        name.to_base36(),
+        name.to_cid(),
+        format!("/ipns/{}", name.to_base58()),
+    ] {
+        assert_eq!(parse_name(&form).unwrap().to_base36(), keypair.name());
+    }
+
+    assert!(parse_name("bafkqaaa").is_err());
+}
@@ -4,6 +4,13 @@
 use std::io::Result;
 
 fn main() -> Result<()> {
+    // prost-build runs protoc. Use the vendored binary unless PROTOC points to one already.
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))?;
+        std::env::set_var("PROTOC", protoc);
+    }
+
     // protos - Paths to .proto files to compile. Any transitively imported .proto files are automatically be included.
     let protos = &["src/pb/ipns_entry.proto"];
 
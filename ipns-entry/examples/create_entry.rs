@@ -10,14 +10,15 @@ fn main() -> Result<(), SigningError> {
     use std::time::SystemTime;
 
     let value = "QmWEekX7EZLUd9VXRNMRXW3LXe4F6x7mB8oPxY5XLptrBq";
-    let ttl = 60 * 60 * 48; // 48 hours, the default
-    let validity: SystemTime = SystemTime::now() + Duration::from_secs(ttl);
+    let ttl = Duration::from_secs(60 * 60 * 48); // 48 hours, the default
+    let validity: SystemTime = SystemTime::now() + ttl;
     let sequence = 0;
 
+    // IpnsEntry.ttl is in nanoseconds
     let (data, signables) = DataBuilder::new(value)
         .validity(validity)
         .sequence(sequence)
-        .ttl(ttl)
+        .ttl(ttl.as_nanos() as u64)
         .build();
 
     // Provide a Signer (holding your private keys) that takes `Signables {v1, v2}` and returns `Signed {v1, v2, pub_key}`
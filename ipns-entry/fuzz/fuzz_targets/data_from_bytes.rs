@@ -0,0 +1,14 @@
+#![no_main]
+
+use ipns_entry::cbor::{Data, DataRef};
+use libfuzzer_sys::fuzz_target;
+
+// Decoding untrusted DAG-CBOR bytes must never panic,
+// and the owned and borrowed decoders must agree.
+fuzz_target!(|data: &[u8]| {
+    match (Data::from_bytes(data), DataRef::from_bytes(data)) {
+        (Ok(owned), Ok(borrowed)) => assert_eq!(owned, Data::from(borrowed)),
+        (Err(_), Err(_)) => {}
+        (owned, borrowed) => panic!("decoders disagree: {owned:?} vs {borrowed:?}"),
+    }
+});
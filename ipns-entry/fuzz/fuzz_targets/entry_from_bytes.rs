@@ -0,0 +1,15 @@
+#![no_main]
+
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::entry_ref::IpnsEntryRef;
+use libfuzzer_sys::fuzz_target;
+
+// Decoding untrusted protobuf bytes must never panic,
+// and the owned and borrowed decoders must agree.
+fuzz_target!(|data: &[u8]| {
+    match (IpnsEntry::from_bytes(data), IpnsEntryRef::from_bytes(data)) {
+        (Ok(owned), Ok(borrowed)) => assert_eq!(owned, IpnsEntry::from(borrowed)),
+        (Err(_), Err(_)) => {}
+        (owned, borrowed) => panic!("decoders disagree: {owned:?} vs {borrowed:?}"),
+    }
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+use ipns_entry::batch::verify_batch;
+use ipns_entry::entry::{IpnsEntry, PeerId};
+use ipns_entry::entry_ref::IpnsEntryRef;
+use libfuzzer_sys::fuzz_target;
+
+// Input is `name length | IPNS Name (PeerId bytes) | IpnsEntry protobuf bytes`.
+// Full validation must never panic and every validation path must agree.
+fuzz_target!(|data: &[u8]| {
+    let Some((&len, rest)) = data.split_first() else {
+        return;
+    };
+    if rest.len() < len as usize {
+        return;
+    }
+    let (name, record) = rest.split_at(len as usize);
+
+    let Ok(peer_id) = PeerId::from_bytes(name) else {
+        return;
+    };
+    let Ok(entry_ref) = IpnsEntryRef::from_bytes(record) else {
+        return;
+    };
+
+    let borrowed = entry_ref.is_valid_for(&peer_id);
+    let owned = IpnsEntry::from(entry_ref).is_valid_for(&peer_id);
+    let batched = verify_batch(&[(entry_ref, peer_id)]).remove(0);
+
+    assert_eq!(borrowed.as_ref().ok(), owned.as_ref().ok());
+    assert_eq!(borrowed.as_ref().ok(), batched.as_ref().ok());
+});
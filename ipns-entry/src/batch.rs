@@ -0,0 +1,264 @@
+//! Batch validation of many IPNS Records at once.
+//!
+//! Records arrive in bursts (Kademlia GET responses, pubsub floods). [verify_batch]
+//! runs the same checks as [IpnsEntryRef::is_valid_for] for every record, then verifies
+//! all ed25519 signatures with a single batch verification. Other key types (RSA, secp256k1)
+//! are verified one by one.
+//!
+//! If the batch fails, each ed25519 signature is re-verified on its own to find the bad ones,
+//! so the verdicts always match the single record path.
+//!
+//! The batch weighs each signature with a coefficient derived from its inputs, and a small order
+//! component in the key or the signature's `R` cancels out for some of them. Such a signature can
+//! pass the batch while failing single verification, so it is verified on its own, as is one
+//! whose `R` is not canonically encoded.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::batch::verify_batch;
+//! use ipns_entry::entry::IpnsEntry;
+//! use ipns_entry::signer::Signer;
+//! use ipns_entry::DataBuilder;
+//! use libp2p_identity::PeerId;
+//!
+//! let signer = Signer::default();
+//! let peer_id = PeerId::from_public_key(&signer.public());
+//!
+//! let entries: Vec<IpnsEntry> = (0..4)
+//!     .map(|sequence| {
+//!         let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").sequence(sequence).build();
+//!         IpnsEntry::new(data, signer.sign(signables).expect("signed"))
+//!     })
+//!     .collect();
+//!
+//! let records: Vec<_> = entries.iter().map(|e| (e.as_entry_ref(), peer_id)).collect();
+//! let verdicts = verify_batch(&records);
+//! assert!(verdicts.iter().all(|v| matches!(v, Ok(true))));
+//! ```
+use crate::entry_ref::IpnsEntryRef;
+use anyhow::Result;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::{Signature, VerifyingKey};
+use libp2p_identity::{PeerId, PublicKey};
+
+/// A record whose signature can go into the ed25519 batch
+struct Batchable {
+    index: usize,
+    pub_key: PublicKey,
+    verifying_key: VerifyingKey,
+    signable: Vec<u8>,
+    signature: Signature,
+}
+
+/// Validate many (record, IPNS Name) pairs. Returns one verdict per record, in order,
+/// equal to what [IpnsEntryRef::is_valid_for] returns for that record.
+pub fn verify_batch(records: &[(IpnsEntryRef<'_>, PeerId)]) -> Vec<Result<bool>> {
+    let mut verdicts = Vec::with_capacity(records.len());
+    let mut batch = Vec::new();
+
+    for (index, (entry, peer_id)) in records.iter().enumerate() {
+        let (pub_key, signable, signature) = match entry.signature_parts(peer_id) {
+            Ok(parts) => parts,
+            Err(e) => {
+                verdicts.push(Err(e));
+                continue;
+            }
+        };
+
+        match batchable(&pub_key, signature) {
+            Some((verifying_key, signature)) => {
+                batch.push(Batchable {
+                    index,
+                    pub_key,
+                    verifying_key,
+                    signable,
+                    signature,
+                });
+                // placeholder, replaced once the batch is verified
+                verdicts.push(Ok(false));
+            }
+            None => verdicts.push(Ok(pub_key.verify(&signable, signature))),
+        }
+    }
+
+    let messages: Vec<&[u8]> = batch.iter().map(|b| b.signable.as_slice()).collect();
+    let signatures: Vec<Signature> = batch.iter().map(|b| b.signature).collect();
+    let keys: Vec<VerifyingKey> = batch.iter().map(|b| b.verifying_key).collect();
+
+    if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok() {
+        for b in &batch {
+            verdicts[b.index] = Ok(true);
+        }
+    } else {
+        // At least one bad signature, fall back to single verification
+        for b in &batch {
+            verdicts[b.index] = Ok(b.pub_key.verify(&b.signable, &b.signature.to_bytes()));
+        }
+    }
+
+    verdicts
+}
+
+/// ed25519 keys and well formed signatures go into the batch.
+/// Weak (small order) keys, and keys or `R` values outside the prime order subgroup,
+/// are verified on their own.
+fn batchable(pub_key: &PublicKey, signature: &[u8]) -> Option<(VerifyingKey, Signature)> {
+    let ed_key = pub_key.clone().try_into_ed25519().ok()?;
+    let verifying_key = VerifyingKey::from_bytes(&ed_key.to_bytes()).ok()?;
+    if verifying_key.is_weak() || !verifying_key.to_edwards().is_torsion_free() {
+        return None;
+    }
+    let signature = Signature::from_slice(signature).ok()?;
+    if !canonical_torsion_free(signature.r_bytes()) {
+        return None;
+    }
+    Some((verifying_key, signature))
+}
+
+/// Whether `bytes` is the canonical encoding of a point in the prime order subgroup
+fn canonical_torsion_free(bytes: &[u8; 32]) -> bool {
+    CompressedEdwardsY(*bytes)
+        .decompress()
+        .is_some_and(|point| point.compress().as_bytes() == bytes && point.is_torsion_free())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::IpnsEntry;
+    use crate::signer::{ed25519, Keypair, Signer};
+    use crate::DataBuilder;
+    use curve25519_dalek::{EdwardsPoint, Scalar};
+    use ed25519_dalek::SigningKey;
+    use sha2::{Digest, Sha512};
+
+    fn get_entry(signer: &Signer, sequence: u64) -> IpnsEntry {
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa")
+            .sequence(sequence)
+            .build();
+        let mut entry = IpnsEntry::new(data, signer.sign(signables).expect("signed"));
+        entry.pub_key = Some(signer.public().encode_protobuf());
+        entry
+    }
+
+    #[test]
+    fn test_matches_single_verification() {
+        let ed_signer = Signer::default();
+        let secp_signer = Signer::new(Keypair::generate_secp256k1());
+        let other = Signer::default();
+
+        let mut tampered = get_entry(&ed_signer, 3);
+        tampered.signature_v2.as_mut().unwrap()[0] ^= 0xff;
+
+        let mut mismatched = get_entry(&ed_signer, 4);
+        mismatched.sequence = Some(5);
+
+        let entries = [
+            (get_entry(&ed_signer, 1), ed_signer.public()),
+            (get_entry(&secp_signer, 2), secp_signer.public()),
+            (tampered, ed_signer.public()),
+            (mismatched, ed_signer.public()),
+            (get_entry(&other, 6), other.public()),
+        ];
+
+        let records: Vec<_> = entries
+            .iter()
+            .map(|(e, pk)| (e.as_entry_ref(), PeerId::from_public_key(pk)))
+            .collect();
+
+        let verdicts = verify_batch(&records);
+        assert_eq!(verdicts.len(), entries.len());
+
+        for ((entry, peer_id), verdict) in records.iter().zip(verdicts) {
+            match (entry.is_valid_for(peer_id), verdict) {
+                (Ok(single), Ok(batched)) => assert_eq!(single, batched),
+                (Err(single), Err(batched)) => {
+                    assert_eq!(single.to_string(), batched.to_string())
+                }
+                (single, batched) => panic!("{single:?} != {batched:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_valid() {
+        let signer = Signer::default();
+        let peer_id = PeerId::from_public_key(&signer.public());
+        let entries: Vec<_> = (0..16).map(|seq| get_entry(&signer, seq)).collect();
+        let records: Vec<_> = entries
+            .iter()
+            .map(|e| (e.as_entry_ref(), peer_id))
+            .collect();
+
+        assert!(verify_batch(&records)
+            .into_iter()
+            .all(|v| v.expect("valid")));
+    }
+
+    /// Sign with `R = rB + T` for a small order `T`. Then `sB - kA - R = -T`, which single
+    /// verification rejects, and the batch accepts when its coefficient is a multiple of 4.
+    fn sign_small_order_r(key: &SigningKey, message: &[u8], nonce: u8) -> Vec<u8> {
+        // (0, 1) encodes as y = 0, a point of order 4
+        let torsion = CompressedEdwardsY([0u8; 32]).decompress().unwrap();
+        assert!(torsion.is_small_order());
+
+        let r = Scalar::from_bytes_mod_order([nonce; 32]);
+        let big_r = (EdwardsPoint::mul_base(&r) + torsion).compress();
+
+        let k = Scalar::from_bytes_mod_order_wide(
+            &Sha512::new()
+                .chain_update(big_r.as_bytes())
+                .chain_update(key.verifying_key().as_bytes())
+                .chain_update(message)
+                .finalize()
+                .into(),
+        );
+        let s = r + k * key.to_scalar();
+
+        [big_r.to_bytes(), s.to_bytes()].concat()
+    }
+
+    #[test]
+    fn test_small_order_r_is_not_batched() {
+        let seed = [9u8; 32];
+        let signing_key = SigningKey::from_bytes(&seed);
+        let secret = ed25519::SecretKey::try_from_bytes(seed).unwrap();
+        let signer = Signer::new(ed25519::Keypair::from(secret).into());
+        let peer_id = PeerId::from_public_key(&signer.public());
+
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+
+        // Grind the nonce until the crafted signature passes a batch of its own
+        let crafted = (1..=u8::MAX)
+            .map(|nonce| {
+                let signed = signables
+                    .clone()
+                    .sign_with(|bytes| {
+                        Ok::<_, core::convert::Infallible>(sign_small_order_r(
+                            &signing_key,
+                            bytes,
+                            nonce,
+                        ))
+                    })
+                    .unwrap();
+                IpnsEntry::new(data.clone(), signed)
+            })
+            .find(|entry| {
+                let signature =
+                    Signature::from_slice(entry.signature_v2.as_ref().unwrap()).unwrap();
+                let signable = entry.as_entry_ref().signature_parts(&peer_id).unwrap().1;
+                ed25519_dalek::verify_batch(
+                    &[signable.as_slice()],
+                    &[signature],
+                    &[signing_key.verifying_key()],
+                )
+                .is_ok()
+            })
+            .expect("a nonce the batch accepts");
+
+        let records = [(crafted.as_entry_ref(), peer_id)];
+        assert!(!records[0].0.is_valid_for(&peer_id).expect("well formed"));
+        assert!(!verify_batch(&records)[0].as_ref().expect("well formed"));
+    }
+}
@@ -0,0 +1,304 @@
+//! Content addressing of IPNS Records and [CAR v1](https://ipld.io/specs/transport/car/carv1/) export.
+//!
+//! Signed IPNS Records are addressed by a CIDv1 using the `ipns-record` multicodec (0x0300)
+//! over the sha2-256 hash of their protobuf bytes. The DAG-CBOR `data` block, if included,
+//! uses the `dag-cbor` multicodec (0x71).
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::car::{Car, CarWriter};
+//! use ipns_entry::entry::IpnsEntry;
+//! use ipns_entry::signer::Signer;
+//! use ipns_entry::DataBuilder;
+//!
+//! let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+//! let signed = Signer::default().sign(signables).expect("signed");
+//! let entry = IpnsEntry::new(data, signed);
+//!
+//! let car_bytes = CarWriter::new()
+//!     .record_with_data(&entry)
+//!     .expect("within 10 KiB")
+//!     .to_bytes();
+//!
+//! let car = Car::from_bytes(&car_bytes).expect("valid CAR");
+//! assert_eq!(car.roots, vec![entry.cid().expect("within 10 KiB")]);
+//! assert_eq!(car.records().expect("records"), vec![entry]);
+//! ```
+use crate::entry::IpnsEntry;
+use anyhow::{anyhow, Result};
+use cbor4ii::core::dec::Decode;
+use cbor4ii::core::enc::Encode;
+use cbor4ii::core::utils::{BufWriter, SliceReader};
+use cbor4ii::core::Value;
+pub use cid::Cid;
+use multihash::{Code, MultihashDigest};
+
+/// The `ipns-record` multicodec
+pub const IPNS_RECORD_CODEC: u64 = 0x0300;
+
+/// The `dag-cbor` multicodec, used for the IpnsEntry.data block
+pub const DAG_CBOR_CODEC: u64 = 0x71;
+
+/// CBOR tag for CIDs in DAG-CBOR
+const CID_TAG: u64 = 42;
+
+impl IpnsEntry {
+    /// CIDv1 of the record: `ipns-record` codec over the sha2-256 of the protobuf bytes.
+    /// Errors if the record exceeds 10 KiB.
+    pub fn cid(&self) -> Result<Cid> {
+        Ok(Cid::new_v1(
+            IPNS_RECORD_CODEC,
+            Code::Sha2_256.digest(&self.try_to_bytes()?),
+        ))
+    }
+
+    /// CIDv1 of the IpnsEntry.data DAG-CBOR block, if present
+    pub fn data_cid(&self) -> Option<Cid> {
+        self.data
+            .as_ref()
+            .map(|data| Cid::new_v1(DAG_CBOR_CODEC, Code::Sha2_256.digest(data)))
+    }
+}
+
+/// Writes one or more IPNS Records into a CAR v1 file.
+///
+/// Each record is a root of the CAR. The DAG-CBOR data block of a record
+/// can be added alongside it with [CarWriter::record_with_data].
+#[derive(Debug, Clone, Default)]
+pub struct CarWriter {
+    roots: Vec<Cid>,
+    blocks: Vec<(Cid, Vec<u8>)>,
+}
+
+impl CarWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record as a root block. Errors if the record exceeds 10 KiB.
+    pub fn record(&mut self, entry: &IpnsEntry) -> Result<&mut CarWriter> {
+        let bytes = entry.try_to_bytes()?;
+        let cid = Cid::new_v1(IPNS_RECORD_CODEC, Code::Sha2_256.digest(&bytes));
+        if !self.roots.contains(&cid) {
+            self.roots.push(cid);
+            self.blocks.push((cid, bytes));
+        }
+        Ok(self)
+    }
+
+    /// Add a record as a root block, followed by its DAG-CBOR data block
+    pub fn record_with_data(&mut self, entry: &IpnsEntry) -> Result<&mut CarWriter> {
+        self.record(entry)?;
+        if let (Some(cid), Some(data)) = (entry.data_cid(), entry.data.as_ref()) {
+            if !self.blocks.iter().any(|(c, _)| *c == cid) {
+                self.blocks.push((cid, data.clone()));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Terminal method which encodes the header and blocks into CAR v1 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint_frame(&mut buf, &[&encode_header(&self.roots)]);
+        for (cid, block) in &self.blocks {
+            write_varint_frame(&mut buf, &[&cid.to_bytes(), block]);
+        }
+        buf
+    }
+}
+
+/// A decoded CAR v1 file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Car {
+    pub roots: Vec<Cid>,
+    pub blocks: Vec<(Cid, Vec<u8>)>,
+}
+
+impl Car {
+    /// Decode CAR v1 bytes. Every block is checked against the hash in its CID.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (header, mut rest) = read_varint_frame(bytes)?;
+        let roots = decode_header(header)?;
+
+        let mut blocks = Vec::new();
+        while !rest.is_empty() {
+            let (section, remaining) = read_varint_frame(rest)?;
+            rest = remaining;
+
+            let mut reader = section;
+            let cid = Cid::read_bytes(&mut reader)?;
+            let block = reader.to_vec();
+
+            let code = Code::try_from(cid.hash().code())?;
+            if code.digest(&block) != *cid.hash() {
+                return Err(anyhow!("Block does not match its CID {cid}"));
+            }
+
+            blocks.push((cid, block));
+        }
+
+        Ok(Car { roots, blocks })
+    }
+
+    /// Get a block by its CID
+    pub fn get(&self, cid: &Cid) -> Option<&[u8]> {
+        self.blocks
+            .iter()
+            .find(|(c, _)| c == cid)
+            .map(|(_, block)| block.as_slice())
+    }
+
+    /// Decode all the `ipns-record` blocks into IpnsEntries
+    pub fn records(&self) -> Result<Vec<IpnsEntry>> {
+        self.blocks
+            .iter()
+            .filter(|(cid, _)| cid.codec() == IPNS_RECORD_CODEC)
+            .map(|(_, block)| IpnsEntry::from_bytes(block).map_err(Into::into))
+            .collect()
+    }
+}
+
+/// DAG-CBOR header: `{"roots": [CID, ...], "version": 1}`
+fn encode_header(roots: &[Cid]) -> Vec<u8> {
+    let roots = roots
+        .iter()
+        .map(|cid| {
+            // DAG-CBOR CIDs are prefixed with the 0x00 multibase identity prefix
+            let bytes = [&[0u8][..], &cid.to_bytes()].concat();
+            Value::Tag(CID_TAG, Box::new(Value::Bytes(bytes)))
+        })
+        .collect();
+
+    let header = Value::Map(vec![
+        (Value::Text("roots".into()), Value::Array(roots)),
+        (Value::Text("version".into()), Value::Integer(1)),
+    ]);
+
+    let mut writer = BufWriter::new(Vec::new());
+    header
+        .encode(&mut writer)
+        .expect("Writing to a Vec not to fail");
+    writer.into_inner()
+}
+
+fn decode_header(bytes: &[u8]) -> Result<Vec<Cid>> {
+    let header = Value::decode(&mut SliceReader::new(bytes))
+        .map_err(|e| anyhow!("Invalid CAR header: {e:?}"))?;
+
+    let Value::Map(entries) = header else {
+        return Err(anyhow!("CAR header is not a map"));
+    };
+
+    let field = |name: &str| {
+        entries
+            .iter()
+            .find(|(k, _)| matches!(k, Value::Text(t) if t == name))
+            .map(|(_, v)| v)
+    };
+
+    match field("version") {
+        Some(Value::Integer(1)) => {}
+        _ => return Err(anyhow!("Only CAR version 1 is supported")),
+    }
+
+    let Some(Value::Array(roots)) = field("roots") else {
+        return Err(anyhow!("CAR header is missing roots"));
+    };
+
+    roots
+        .iter()
+        .map(|root| match root {
+            Value::Tag(CID_TAG, inner) => match &**inner {
+                Value::Bytes(b) if b.first() == Some(&0) => Ok(Cid::try_from(&b[1..])?),
+                _ => Err(anyhow!("Invalid CID in CAR header")),
+            },
+            _ => Err(anyhow!("Invalid CID in CAR header")),
+        })
+        .collect()
+}
+
+/// Write `varint(total length) | part | part...`
+fn write_varint_frame(buf: &mut Vec<u8>, parts: &[&[u8]]) {
+    let len = parts.iter().map(|p| p.len()).sum::<usize>();
+    let mut varint = unsigned_varint::encode::usize_buffer();
+    buf.extend_from_slice(unsigned_varint::encode::usize(len, &mut varint));
+    for part in parts {
+        buf.extend_from_slice(part);
+    }
+}
+
+/// Read a varint length prefixed section, returning it and the remaining bytes
+fn read_varint_frame(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let (len, rest) = unsigned_varint::decode::usize(bytes)
+        .map_err(|e| anyhow!("Invalid CAR section length: {e}"))?;
+    if len > rest.len() {
+        return Err(anyhow!("Truncated CAR section"));
+    }
+    Ok(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::MAX_RECORD_SIZE;
+    use crate::signer::Signer;
+    use crate::DataBuilder;
+
+    fn get_entry(value: &str) -> IpnsEntry {
+        let (data, signables) = DataBuilder::new(value).build();
+        let signed = Signer::default().sign(signables).expect("signed");
+        IpnsEntry::new(data, signed)
+    }
+
+    #[test]
+    fn test_cid() {
+        let entry = get_entry("/ipfs/bafkqaaa");
+        let cid = entry.cid().expect("within 10 KiB");
+
+        assert_eq!(cid.version(), cid::Version::V1);
+        assert_eq!(cid.codec(), IPNS_RECORD_CODEC);
+        assert_eq!(*cid.hash(), Code::Sha2_256.digest(&entry.to_bytes()));
+        assert_eq!(entry.data_cid().expect("data").codec(), DAG_CBOR_CODEC);
+    }
+
+    #[test]
+    fn test_car_roundtrip() {
+        let first = get_entry("/ipfs/bafkqaaa");
+        let second = get_entry("/ipfs/bafkqaab");
+
+        let bytes = CarWriter::new()
+            .record_with_data(&first)
+            .and_then(|writer| writer.record(&second))
+            .expect("within 10 KiB")
+            .to_bytes();
+
+        let car = Car::from_bytes(&bytes).expect("valid CAR");
+        assert_eq!(car.roots, vec![first.cid().unwrap(), second.cid().unwrap()]);
+        assert_eq!(car.blocks.len(), 3);
+        assert_eq!(car.get(&first.data_cid().unwrap()), first.data.as_deref());
+        assert_eq!(car.records().expect("records"), vec![first, second]);
+    }
+
+    #[test]
+    fn test_car_rejects_tampered_block() {
+        let entry = get_entry("/ipfs/bafkqaaa");
+        let mut bytes = CarWriter::new().record(&entry).unwrap().to_bytes();
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(Car::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_oversized_record_errors() {
+        let mut entry = get_entry("/ipfs/bafkqaaa");
+        entry.value = Some(vec![b'a'; MAX_RECORD_SIZE]);
+
+        assert!(entry.try_to_bytes().is_err());
+        assert!(entry.cid().is_err());
+        assert!(CarWriter::new().record(&entry).is_err());
+    }
+}
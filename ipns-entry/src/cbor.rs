@@ -27,10 +27,12 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 
-use crate::entry::ValidityType;
+use crate::entry::{generate_v1_signable, generate_v2_signable, UnknownValidityType, ValidityType};
+use crate::signer::Signables;
+use alloc::vec::Vec;
 use cbor4ii::serde::{from_slice, to_vec, DecodeError};
+use core::convert::Infallible;
 use serde_derive::{Deserialize, Serialize};
-use std::convert::Infallible;
 
 // Types annotated with `Serialize` can be stored as CBOR.
 // To be able to load them again add `Deserialize`.
@@ -94,17 +96,25 @@ impl From<CborData<'_>> for Data {
     }
 }
 
-impl Data {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        create_cbor_data(&self.value, &self.validity, &self.sequence, self.ttl)
-    }
+/// Borrowed view of the DAG-CBOR document, parsed without copying `value` or `validity`.
+///
+/// Convert into the owned [Data] with `Data::from`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DataRef<'a> {
+    pub value: &'a [u8],
+    pub validity: &'a [u8],
+    pub sequence: u64,
+    pub ttl: u64,
+    pub validity_type: i32,
+}
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<Data, DecodeError<Infallible>> {
-        let cbor_data = parse_cbor_data(bytes).expect("Valid cbor");
+impl<'a> DataRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<DataRef<'a>, DecodeError<Infallible>> {
+        let cbor_data = parse_cbor_data(bytes)?;
 
-        Ok(Data {
-            value: cbor_data.Value.to_vec(),
-            validity: cbor_data.Validity.to_vec(),
+        Ok(DataRef {
+            value: cbor_data.Value,
+            validity: cbor_data.Validity,
             sequence: cbor_data.Sequence,
             ttl: cbor_data.TTL,
             validity_type: cbor_data.ValidityType,
@@ -112,11 +122,55 @@ impl Data {
     }
 }
 
-fn create_cbor_data(value: &[u8], validity: &[u8], sequence: &u64, ttl: u64) -> Vec<u8> {
+impl From<DataRef<'_>> for Data {
+    fn from(data: DataRef) -> Self {
+        Data {
+            value: data.value.to_vec(),
+            validity: data.validity.to_vec(),
+            sequence: data.sequence,
+            ttl: data.ttl,
+            validity_type: data.validity_type,
+        }
+    }
+}
+
+impl Data {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        create_cbor_data(
+            &self.value,
+            &self.validity,
+            &self.sequence,
+            self.ttl,
+            self.validity_type,
+        )
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Data, DecodeError<Infallible>> {
+        let cbor_data = parse_cbor_data(bytes)?;
+
+        Ok(Data::from(cbor_data))
+    }
+
+    /// The bytes to sign for IpnsEntry.signatureV1 and IpnsEntry.signatureV2
+    pub fn signables(&self) -> Signables {
+        Signables {
+            v1: generate_v1_signable(&self.value, &self.validity),
+            v2: generate_v2_signable(&self.to_bytes()),
+        }
+    }
+}
+
+fn create_cbor_data(
+    value: &[u8],
+    validity: &[u8],
+    sequence: &u64,
+    ttl: u64,
+    validity_type: i32,
+) -> Vec<u8> {
     let data = CborData {
         Value: value,
         Validity: validity,
-        ValidityType: 0, // the only supported value is zero (0)
+        ValidityType: validity_type, // the only supported value is zero (0), others are kept for inspection
         Sequence: *sequence,
         TTL: ttl,
     };
@@ -139,22 +193,26 @@ impl From<ValidityType> for isize {
     }
 }
 
-// and isize too
-impl From<isize> for ValidityType {
-    fn from(v: isize) -> Self {
+// and back from isize, failing on unknown types
+impl TryFrom<isize> for ValidityType {
+    type Error = UnknownValidityType;
+
+    fn try_from(v: isize) -> Result<Self, Self::Error> {
         match v {
-            0 => ValidityType::Eol,
-            _ => panic!("Invalid ValidityType"),
+            0 => Ok(ValidityType::Eol),
+            v => Err(UnknownValidityType(v as i128)),
         }
     }
 }
 
 // and u64 too
-impl From<u64> for ValidityType {
-    fn from(v: u64) -> Self {
+impl TryFrom<u64> for ValidityType {
+    type Error = UnknownValidityType;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
         match v {
-            0 => ValidityType::Eol,
-            _ => panic!("Invalid ValidityType"),
+            0 => Ok(ValidityType::Eol),
+            v => Err(UnknownValidityType(v as i128)),
         }
     }
 }
@@ -177,11 +235,13 @@ impl From<ValidityType> for usize {
 }
 
 // and back too
-impl From<usize> for ValidityType {
-    fn from(v: usize) -> Self {
+impl TryFrom<usize> for ValidityType {
+    type Error = UnknownValidityType;
+
+    fn try_from(v: usize) -> Result<Self, Self::Error> {
         match v {
-            0 => ValidityType::Eol,
-            _ => panic!("Invalid ValidityType"),
+            0 => Ok(ValidityType::Eol),
+            v => Err(UnknownValidityType(v as i128)),
         }
     }
 }
@@ -217,7 +277,13 @@ mod tests {
         // Test to ensure we have good interop with non-Rust CBOR IPNS encodings (ie Javascript)
         let data = get_entry();
 
-        let cbor = create_cbor_data(data.Value, data.Validity, &data.Sequence, data.TTL);
+        let cbor = create_cbor_data(
+            data.Value,
+            data.Validity,
+            &data.Sequence,
+            data.TTL,
+            data.ValidityType,
+        );
 
         assert_eq!(parse_cbor_data(&cbor).expect("Ok"), data);
 
@@ -236,4 +302,34 @@ mod tests {
         assert_eq!(data_from_js_bytes, data);
         assert_eq!(parse_cbor_data(&bytes).expect("Ok"), data);
     }
+
+    #[test]
+    fn test_validity_type_conversions() {
+        assert_eq!(ValidityType::try_from(0_u64), Ok(ValidityType::Eol));
+        assert_eq!(ValidityType::try_from(0_usize), Ok(ValidityType::Eol));
+        assert_eq!(ValidityType::try_from(0_isize), Ok(ValidityType::Eol));
+
+        assert_eq!(ValidityType::try_from(1_u64), Err(UnknownValidityType(1)));
+        assert_eq!(
+            ValidityType::try_from(-1_isize),
+            Err(UnknownValidityType(-1))
+        );
+        assert_eq!(
+            ValidityType::try_from(u64::MAX),
+            Err(UnknownValidityType(u64::MAX as i128))
+        );
+    }
+
+    #[test]
+    fn test_unknown_validity_type_roundtrip() {
+        let data = Data {
+            value: b"/ipfs/bafkqaaa".to_vec(),
+            validity: b"2033-05-18T03:33:20.000000000Z".to_vec(),
+            sequence: 0,
+            ttl: 0,
+            validity_type: 7,
+        };
+
+        assert_eq!(Data::from_bytes(&data.to_bytes()).expect("Ok"), data);
+    }
 }
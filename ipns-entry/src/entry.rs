@@ -7,14 +7,87 @@ pub mod protobuf {
 
 use crate::cbor;
 use crate::signer::{Signables, Signed};
+use alloc::vec;
+use alloc::vec::Vec;
 use anyhow::{anyhow, Error, Result};
 use cbor::Data;
+use core::time::Duration;
+#[cfg(feature = "std")]
 pub use libp2p_identity::{ed25519, PeerId, PublicKey};
-use multihash::Multihash;
 use prost::Message; // so we can use trait Message
 pub use protobuf::ipns_entry::ValidityType;
 pub use protobuf::IpnsEntry;
-use std::io::Cursor; // so we can use decode
+
+/// Maximum size of a serialized IpnsEntry, 10 KiB
+pub const MAX_RECORD_SIZE: usize = 10 * 1024;
+
+/// IpnsEntry.validityType as found in a record.
+/// Unlike [ValidityType], unknown types are kept so they can be displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordValidityType {
+    /// Expires at the time in IpnsEntry.validity
+    Eol,
+    /// A type this implementation does not know. Records using it are never valid.
+    Unknown(i32),
+}
+
+impl From<i32> for RecordValidityType {
+    fn from(v: i32) -> Self {
+        match ValidityType::from_i32(v) {
+            Some(ValidityType::Eol) => RecordValidityType::Eol,
+            None => RecordValidityType::Unknown(v),
+        }
+    }
+}
+
+impl From<RecordValidityType> for i32 {
+    fn from(v: RecordValidityType) -> Self {
+        match v {
+            RecordValidityType::Eol => ValidityType::Eol as i32,
+            RecordValidityType::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<ValidityType> for RecordValidityType {
+    fn from(v: ValidityType) -> Self {
+        match v {
+            ValidityType::Eol => RecordValidityType::Eol,
+        }
+    }
+}
+
+impl TryFrom<RecordValidityType> for ValidityType {
+    type Error = UnknownValidityType;
+
+    fn try_from(v: RecordValidityType) -> Result<Self, Self::Error> {
+        match v {
+            RecordValidityType::Eol => Ok(ValidityType::Eol),
+            RecordValidityType::Unknown(v) => Err(UnknownValidityType(v.into())),
+        }
+    }
+}
+
+impl core::fmt::Display for RecordValidityType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RecordValidityType::Eol => write!(f, "EOL"),
+            RecordValidityType::Unknown(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Error for a validityType this implementation does not know
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownValidityType(pub i128);
+
+impl core::fmt::Display for UnknownValidityType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Unknown IpnsEntry.validityType {}", self.0)
+    }
+}
+
+impl core::error::Error for UnknownValidityType {}
 
 impl IpnsEntry {
     pub fn new(data: Data, signed: Signed) -> Self {
@@ -22,7 +95,7 @@ impl IpnsEntry {
             data: Some(data.to_bytes()),
             value: Some(data.value),
             validity: Some(data.validity),
-            validity_type: Some(ValidityType::Eol.into()),
+            validity_type: Some(data.validity_type),
             signature_v1: Some(signed.v1),
             signature_v2: Some(signed.v2),
             sequence: Some(data.sequence),
@@ -30,6 +103,13 @@ impl IpnsEntry {
             pub_key: None,
         }
     }
+
+    /// Encode to protobuf bytes.
+    ///
+    /// # Panics
+    ///
+    /// If the encoded IpnsEntry exceeds [MAX_RECORD_SIZE]. Use [IpnsEntry::try_to_bytes]
+    /// for records that were not built locally.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
         buf.reserve(self.encoded_len());
@@ -38,49 +118,50 @@ impl IpnsEntry {
 
         // assert that serialized IpnsEntry less than or equal to 10 KiB in size.
         // This is the maximum size of an IPNS record.
-        assert!(buf.len() <= 10 * 1024);
+        assert!(buf.len() <= MAX_RECORD_SIZE);
 
         buf
     }
 
+    /// Encode to protobuf bytes, or an error if they exceed [MAX_RECORD_SIZE]
+    pub fn try_to_bytes(&self) -> Result<Vec<u8>> {
+        let len = self.encoded_len();
+        if len > MAX_RECORD_SIZE {
+            return Err(anyhow!("IpnsEntry is {len} bytes, exceeding 10 KiB"));
+        }
+        Ok(self.to_bytes())
+    }
+
     /// Decode protobuf bytes into an IpnsEntry
     /// Ensures the bytes are less than or equal to 10 KiB in size.
     pub fn from_bytes(buf: &[u8]) -> Result<Self, prost::DecodeError> {
-        // assert that serialized IpnsEntry less than or equal to 10 KiB in size.
+        // reject serialized IpnsEntry larger than 10 KiB in size.
         // This is the maximum size of an IPNS record.
-        assert!(buf.len() <= 10 * 1024);
+        if buf.len() > MAX_RECORD_SIZE {
+            return Err(prost::DecodeError::new("IpnsEntry exceeds 10 KiB"));
+        }
+
+        Self::decode(buf)
+    }
 
-        Self::decode(&mut Cursor::new(buf))
+    /// IpnsEntry.validityType, keeping unknown types.
+    /// The generated `validity_type()` getter silently maps them to EOL.
+    pub fn record_validity_type(&self) -> Option<RecordValidityType> {
+        self.validity_type.map(RecordValidityType::from)
     }
 
+    #[cfg(feature = "std")]
     pub fn get_public_key(&self, peer_id: &PeerId) -> Result<PublicKey, anyhow::Error> {
-        // Confirm IpnsEntry.signatureV2 and IpnsEntry.data are present and are not empty
-        if self.signature_v2.is_none() || self.data.is_none() {
-            return Err(anyhow!(
-                "Missing both IpnsEntry.signatureV2 and IpnsEntry.data"
-            ));
-        }
+        self.as_entry_ref().get_public_key(peer_id)
+    }
 
-        // Extract public key
-        // Public key is either:
-        // A. IpnsEntry.pubKey
-        // B. inlined in the IPNS Name itself (e.g., Ed25519 inlined using identity multihash)
-        //
-        // If IpnsEntry.pubKey is present, use that.
-        // If not, use the public key from the IPNS Name.
-        match self.pub_key.as_ref() {
-            Some(pk) => Ok(PublicKey::try_decode_protobuf(pk)?),
-            None => match Multihash::from_bytes(&peer_id.to_bytes()) {
-                Ok(mh) => {
-                    let d = mh.digest();
-                    let ed_key =
-                        ed25519::PublicKey::try_from_bytes(&d[4..]).expect("a Valid ed25519 key");
-                    let pub_key: PublicKey = PublicKey::from(ed_key);
-                    Ok(pub_key)
-                }
-                Err(_) => Err(anyhow!("Invalid PeerId")),
-            },
-        }
+    /// Set IpnsEntry.pubKey if the key is not inlined in its IPNS Name (RSA).
+    /// Ed25519 and secp256k1 keys are inlined, so IpnsEntry.pubKey is left empty.
+    #[cfg(feature = "std")]
+    pub fn embed_public_key(&mut self, public_key: &PublicKey) {
+        // inlined keys use the identity multihash (code 0x00)
+        let inlined = PeerId::from_public_key(public_key).to_bytes()[0] == 0x00;
+        self.pub_key = (!inlined).then(|| public_key.encode_protobuf());
     }
 
     /// Get Deserialized IpnsEntry.data as a DAG-CBOR document
@@ -98,77 +179,63 @@ impl IpnsEntry {
         }
     }
 
-    /// Confirm values in IpnsEntry protobuf match deserialized ones from IpnsEntry.data:
+    /// Confirm values in IpnsEntry protobuf match deserialized ones from IpnsEntry.data,
+    /// then verify IpnsEntry.signatureV2. See [crate::entry_ref::IpnsEntryRef::is_valid_for].
+    #[cfg(feature = "std")]
     pub fn is_valid_for(&self, peer_id: &PeerId) -> Result<bool, Error> {
-        let data = self.decode_data()?;
-        // IpnsEntry.value must match IpnsEntry.data[value]
-        if self.value.as_ref().expect("Valid value") != &data.value {
-            return Err(anyhow!(
-                "IpnsEntry.value does not match IpnsEntry.data[value]"
-            ));
-        }
-
-        // IpnsEntry.validity must match IpnsEntry.data[validity]
-        if self.validity.as_ref().expect("Valid validity") != &data.validity {
-            return Err(anyhow!(
-                "IpnsEntry.validity does not match IpnsEntry.data[validity]"
-            ));
-        }
-
-        // IpnsEntry.validityType must match IpnsEntry.data[validityType]
-        if self.validity_type.as_ref().expect("Valid validity_type") != &data.validity_type {
-            return Err(anyhow!(
-                "IpnsEntry.validityType does not match IpnsEntry.data[validityType]"
-            ));
-        }
-        // IpnsEntry.sequence must match IpnsEntry.data[sequence]
-        if self.sequence.as_ref().expect("Valid sequence") != &data.sequence {
-            return Err(anyhow!(
-                "IpnsEntry.sequence does not match IpnsEntry.data[sequence]"
-            ));
-        }
-
-        // IpnsEntry.ttl must match IpnsEntry.data[ttl]
-        if self.ttl.as_ref().expect("Valid TTL") != &data.ttl {
-            return Err(anyhow!("IpnsEntry.ttl does not match IpnsEntry.data[ttl]"));
-        }
+        self.as_entry_ref().is_valid_for(peer_id)
+    }
 
-        // Verify signature in IpnsEntry.signatureV2 against IpnsEntry pub_key and IpnsEntry.data
-        // get_public_key
-        let pub_key = self.get_public_key(peer_id)?;
-        let signature_v2 = self.signature_v2.as_ref().expect("Valid signature_v2");
-        let v2_signable = generate_v2_signable(self.data.as_ref().expect("Valid data"));
+    /// Like [IpnsEntry::is_valid_for], verifying IpnsEntry.signatureV2 with a caller supplied function.
+    /// See [crate::entry_ref::IpnsEntryRef::verify_with].
+    pub fn verify_with(&self, verify: impl FnOnce(&[u8], &[u8]) -> bool) -> Result<bool, Error> {
+        self.as_entry_ref().verify_with(verify)
+    }
 
-        // use V2Signer to create a signer with pubKey and verify signature
-        let valid_sig = pub_key.verify(&v2_signable, signature_v2);
+    /// Whether IpnsEntry.data[validity] is before `now`, a time since the UNIX epoch
+    pub fn is_expired_at(&self, now: Duration) -> Result<bool, Error> {
+        self.as_entry_ref().is_expired_at(now)
+    }
 
-        Ok(valid_sig)
+    /// Whether IpnsEntry.data[validity] is in the past
+    #[cfg(feature = "std")]
+    pub fn is_expired(&self) -> Result<bool, Error> {
+        self.is_expired_at(crate::time::now())
     }
 
+    /// The bytes IpnsEntry.signatureV1 and IpnsEntry.signatureV2 sign.
+    /// V2-only records have no V1 fields to sign, and are an error.
     pub fn signables(&self) -> Result<Signables> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing IpnsEntry.data"))?;
+        let (Some(value), Some(validity)) = (&self.value, &self.validity) else {
+            return Err(anyhow!(
+                "Missing IpnsEntry.value or IpnsEntry.validity in a V2-only record"
+            ));
+        };
+
         Ok(Signables {
-            v1: generate_v1_signable(
-                self.value.as_ref().expect("Valid value"),
-                self.validity.as_ref().expect("Valid validity"),
-            ),
-            v2: generate_v2_signable(self.data.as_ref().expect("Valid data")),
+            v1: generate_v1_signable(value, validity),
+            v2: generate_v2_signable(data),
         })
     }
 }
 
-fn generate_v1_signable(value: &[u8], validity: &[u8]) -> Vec<u8> {
+pub(crate) fn generate_v1_signable(value: &[u8], validity: &[u8]) -> Vec<u8> {
     vec![value, validity, &[ValidityType::Eol as u8]].concat()
 }
 
-fn generate_v2_signable(data: &[u8]) -> Vec<u8> {
+pub(crate) fn generate_v2_signable(data: &[u8]) -> Vec<u8> {
     vec!["ipns-signature:".as_bytes(), data].concat()
 }
 
 pub fn deserialize(buf: &[u8]) -> Result<IpnsEntry, prost::DecodeError> {
-    IpnsEntry::decode(&mut Cursor::new(buf))
+    IpnsEntry::decode(buf)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use libp2p_identity::Keypair;
@@ -225,4 +292,42 @@ mod tests {
         let peer_id = PeerId::from_public_key(&PublicKey::from(keypair.public()));
         assert!(entry.is_valid_for(&peer_id).unwrap());
     }
+
+    #[test]
+    fn test_signables_without_v1_fields() {
+        assert!(IpnsEntry::default().signables().is_err());
+
+        let v2_only = IpnsEntry::from_bytes(include_bytes!(
+            "../tests/vectors/ed25519-v2-only.ipns-record"
+        ))
+        .unwrap();
+        assert!(v2_only.signables().is_err());
+
+        let v1_v2 =
+            IpnsEntry::from_bytes(include_bytes!("../tests/vectors/ed25519-v1-v2.ipns-record"))
+                .unwrap();
+        let signables = v1_v2.signables().unwrap();
+        assert_eq!(
+            signables.v2,
+            generate_v2_signable(v1_v2.data.as_ref().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_embed_public_key() {
+        let mut entry = IpnsEntry::default();
+
+        entry.embed_public_key(&Keypair::generate_ed25519().public());
+        assert_eq!(entry.pub_key, None);
+
+        entry.embed_public_key(&Keypair::generate_secp256k1().public());
+        assert_eq!(entry.pub_key, None);
+
+        let rsa = IpnsEntry::from_bytes(include_bytes!("../tests/vectors/rsa-v1-v2.ipns-record"))
+            .unwrap()
+            .pub_key
+            .unwrap();
+        entry.embed_public_key(&PublicKey::try_decode_protobuf(&rsa).unwrap());
+        assert_eq!(entry.pub_key, Some(rsa));
+    }
 }
@@ -0,0 +1,361 @@
+//! Zero-copy, borrowed view of an IPNS Entry.
+//!
+//! [IpnsEntryRef] parses the protobuf (and the DAG-CBOR `data` through [DataRef])
+//! without allocating, borrowing every field from the received bytes.
+//! Use it to validate incoming records, and only convert to the owned [IpnsEntry]
+//! when the record is stored.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::entry::IpnsEntry;
+//! use ipns_entry::entry_ref::IpnsEntryRef;
+//! use ipns_entry::signer::Signer;
+//! use ipns_entry::DataBuilder;
+//! use libp2p_identity::PeerId;
+//!
+//! let signer = Signer::default();
+//! let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+//! let entry = IpnsEntry::new(data, signer.sign(signables).expect("signed"));
+//! let bytes = entry.to_bytes();
+//!
+//! let entry_ref = IpnsEntryRef::from_bytes(&bytes).expect("valid protobuf");
+//! let peer_id = PeerId::from_public_key(&signer.public());
+//! assert!(entry_ref.is_valid_for(&peer_id).expect("valid"));
+//!
+//! // Only allocate once the record is kept
+//! let owned: IpnsEntry = entry_ref.into();
+//! assert_eq!(owned, entry);
+//! ```
+use crate::cbor::DataRef;
+use crate::entry::{
+    generate_v2_signable, IpnsEntry, RecordValidityType, UnknownValidityType, MAX_RECORD_SIZE,
+};
+use crate::time::parse_rfc3339;
+use anyhow::{anyhow, Result};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use libp2p_identity::{PeerId, PublicKey};
+#[cfg(feature = "std")]
+use multihash::Multihash;
+use prost::encoding::{decode_key, decode_varint, skip_field, DecodeContext, WireType};
+use prost::DecodeError;
+
+/// Borrowed IpnsEntry. Fields match the protobuf [IpnsEntry].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IpnsEntryRef<'a> {
+    pub value: Option<&'a [u8]>,
+    pub signature_v1: Option<&'a [u8]>,
+    pub validity_type: Option<i32>,
+    pub validity: Option<&'a [u8]>,
+    pub sequence: Option<u64>,
+    pub ttl: Option<u64>,
+    pub pub_key: Option<&'a [u8]>,
+    pub signature_v2: Option<&'a [u8]>,
+    pub data: Option<&'a [u8]>,
+}
+
+impl<'a> IpnsEntryRef<'a> {
+    /// Decode protobuf bytes into a borrowed IpnsEntryRef
+    /// Returns an error if the bytes are larger than 10 KiB.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        if buf.len() > MAX_RECORD_SIZE {
+            return Err(DecodeError::new("IpnsEntry exceeds 10 KiB"));
+        }
+
+        let mut entry = IpnsEntryRef::default();
+        let mut buf = buf;
+
+        while !buf.is_empty() {
+            let (tag, wire_type) = decode_key(&mut buf)?;
+            match (tag, wire_type) {
+                (1, WireType::LengthDelimited) => entry.value = Some(read_bytes(&mut buf)?),
+                (2, WireType::LengthDelimited) => entry.signature_v1 = Some(read_bytes(&mut buf)?),
+                (3, WireType::Varint) => {
+                    entry.validity_type = Some(decode_varint(&mut buf)? as i32)
+                }
+                (4, WireType::LengthDelimited) => entry.validity = Some(read_bytes(&mut buf)?),
+                (5, WireType::Varint) => entry.sequence = Some(decode_varint(&mut buf)?),
+                (6, WireType::Varint) => entry.ttl = Some(decode_varint(&mut buf)?),
+                (7, WireType::LengthDelimited) => entry.pub_key = Some(read_bytes(&mut buf)?),
+                (8, WireType::LengthDelimited) => entry.signature_v2 = Some(read_bytes(&mut buf)?),
+                (9, WireType::LengthDelimited) => entry.data = Some(read_bytes(&mut buf)?),
+                (1..=9, _) => return Err(DecodeError::new("invalid wire type")),
+                // unknown fields are skipped, like prost does
+                (tag, wire_type) => skip_field(wire_type, tag, &mut buf, DecodeContext::default())?,
+            }
+        }
+
+        Ok(entry)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn get_public_key(&self, peer_id: &PeerId) -> Result<PublicKey> {
+        // Confirm IpnsEntry.signatureV2 and IpnsEntry.data are present and are not empty
+        if self.signature_v2.is_none() || self.data.is_none() {
+            return Err(anyhow!(
+                "Missing both IpnsEntry.signatureV2 and IpnsEntry.data"
+            ));
+        }
+
+        // Extract public key
+        // Public key is either:
+        // A. IpnsEntry.pubKey
+        // B. inlined in the IPNS Name itself (e.g., Ed25519 inlined using identity multihash)
+        //
+        // If IpnsEntry.pubKey is present, use that.
+        // If not, use the public key from the IPNS Name.
+        match self.pub_key {
+            Some(pk) => {
+                let pub_key = PublicKey::try_decode_protobuf(pk)?;
+                if PeerId::from_public_key(&pub_key) != *peer_id {
+                    return Err(anyhow!("IpnsEntry.pubKey does not match the IPNS Name"));
+                }
+                Ok(pub_key)
+            }
+            None => match Multihash::from_bytes(&peer_id.to_bytes()) {
+                // inlined keys (ed25519, secp256k1) are the identity multihash of the protobuf key
+                Ok(mh) if mh.code() == 0x00 => PublicKey::try_decode_protobuf(mh.digest())
+                    .map_err(|_| anyhow!("IPNS Name does not inline a valid public key")),
+                Ok(_) => Err(anyhow!(
+                    "IPNS Name does not inline its public key, IpnsEntry.pubKey is required"
+                )),
+                Err(_) => Err(anyhow!("Invalid PeerId")),
+            },
+        }
+    }
+
+    /// Get IpnsEntry.data as a borrowed DAG-CBOR document
+    pub fn decode_data(&self) -> Result<DataRef<'a>> {
+        let data = self.data.ok_or_else(|| anyhow!("Missing IpnsEntry.data"))?;
+
+        // Deserialize IpnsEntry.data as a DAG-CBOR document
+        DataRef::from_bytes(data).map_err(|_| anyhow!("Invalid DAG-CBOR"))
+    }
+
+    /// Confirm values in IpnsEntry protobuf match deserialized ones from IpnsEntry.data,
+    /// then verify IpnsEntry.signatureV2
+    #[cfg(feature = "std")]
+    pub fn is_valid_for(&self, peer_id: &PeerId) -> Result<bool> {
+        let (pub_key, v2_signable, signature_v2) = self.signature_parts(peer_id)?;
+
+        Ok(pub_key.verify(&v2_signable, signature_v2))
+    }
+
+    /// Runs the checks of [IpnsEntryRef::is_valid_for], then verifies IpnsEntry.signatureV2 with
+    /// `verify`, called with the signed bytes and the signature.
+    ///
+    /// For use without libp2p keys (`no_std`). The caller must make sure the key used by
+    /// `verify` belongs to the IPNS Name.
+    pub fn verify_with(&self, verify: impl FnOnce(&[u8], &[u8]) -> bool) -> Result<bool> {
+        self.check_fields()?;
+
+        let signature_v2 = self
+            .signature_v2
+            .ok_or_else(|| anyhow!("Missing IpnsEntry.signatureV2"))?;
+        let v2_signable = generate_v2_signable(self.data.expect("checked by decode_data"));
+
+        Ok(verify(&v2_signable, signature_v2))
+    }
+
+    /// Whether IpnsEntry.data[validity] is before `now`, a time since the UNIX epoch
+    pub fn is_expired_at(&self, now: Duration) -> Result<bool> {
+        let data = self.decode_data()?;
+
+        if let RecordValidityType::Unknown(v) = RecordValidityType::from(data.validity_type) {
+            return Err(UnknownValidityType(v.into()).into());
+        }
+
+        Ok(parse_rfc3339(data.validity)? < now)
+    }
+
+    /// Runs every check of [IpnsEntryRef::is_valid_for] except the signature verification,
+    /// returning the public key, signable bytes and signature to verify.
+    #[cfg(feature = "std")]
+    pub(crate) fn signature_parts(
+        &self,
+        peer_id: &PeerId,
+    ) -> Result<(PublicKey, Vec<u8>, &'a [u8])> {
+        self.check_fields()?;
+
+        // Verify signature in IpnsEntry.signatureV2 against IpnsEntry pub_key and IpnsEntry.data
+        let pub_key = self.get_public_key(peer_id)?;
+        let signature_v2 = self.signature_v2.expect("checked by get_public_key");
+        let v2_signable = generate_v2_signable(self.data.expect("checked by decode_data"));
+
+        Ok((pub_key, v2_signable, signature_v2))
+    }
+
+    /// Confirm values in IpnsEntry protobuf match deserialized ones from IpnsEntry.data
+    fn check_fields(&self) -> Result<DataRef<'a>> {
+        let data = self.decode_data()?;
+
+        // V2-only records omit the V1 fields, so they are only compared when present
+
+        // IpnsEntry.value must match IpnsEntry.data[value]
+        if matches!(self.value, Some(v) if v != data.value) {
+            return Err(anyhow!(
+                "IpnsEntry.value does not match IpnsEntry.data[value]"
+            ));
+        }
+
+        // IpnsEntry.validity must match IpnsEntry.data[validity]
+        if matches!(self.validity, Some(v) if v != data.validity) {
+            return Err(anyhow!(
+                "IpnsEntry.validity does not match IpnsEntry.data[validity]"
+            ));
+        }
+
+        // IpnsEntry.validityType must match IpnsEntry.data[validityType]
+        if matches!(self.validity_type, Some(v) if v != data.validity_type) {
+            return Err(anyhow!(
+                "IpnsEntry.validityType does not match IpnsEntry.data[validityType]"
+            ));
+        }
+
+        // Only known validity types can be checked, so others are rejected
+        if let RecordValidityType::Unknown(v) = RecordValidityType::from(data.validity_type) {
+            return Err(UnknownValidityType(v.into()).into());
+        }
+
+        // IpnsEntry.sequence must match IpnsEntry.data[sequence]
+        if matches!(self.sequence, Some(v) if v != data.sequence) {
+            return Err(anyhow!(
+                "IpnsEntry.sequence does not match IpnsEntry.data[sequence]"
+            ));
+        }
+
+        // IpnsEntry.ttl must match IpnsEntry.data[ttl]
+        if matches!(self.ttl, Some(v) if v != data.ttl) {
+            return Err(anyhow!("IpnsEntry.ttl does not match IpnsEntry.data[ttl]"));
+        }
+
+        Ok(data)
+    }
+}
+
+impl From<IpnsEntryRef<'_>> for IpnsEntry {
+    fn from(entry: IpnsEntryRef) -> Self {
+        IpnsEntry {
+            value: entry.value.map(<[u8]>::to_vec),
+            signature_v1: entry.signature_v1.map(<[u8]>::to_vec),
+            validity_type: entry.validity_type,
+            validity: entry.validity.map(<[u8]>::to_vec),
+            sequence: entry.sequence,
+            ttl: entry.ttl,
+            pub_key: entry.pub_key.map(<[u8]>::to_vec),
+            signature_v2: entry.signature_v2.map(<[u8]>::to_vec),
+            data: entry.data.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl IpnsEntry {
+    /// Borrow this IpnsEntry as an [IpnsEntryRef]
+    pub fn as_entry_ref(&self) -> IpnsEntryRef<'_> {
+        IpnsEntryRef {
+            value: self.value.as_deref(),
+            signature_v1: self.signature_v1.as_deref(),
+            validity_type: self.validity_type,
+            validity: self.validity.as_deref(),
+            sequence: self.sequence,
+            ttl: self.ttl,
+            pub_key: self.pub_key.as_deref(),
+            signature_v2: self.signature_v2.as_deref(),
+            data: self.data.as_deref(),
+        }
+    }
+}
+
+/// Read a length delimited field, borrowing it from the buffer
+fn read_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    let len = decode_varint(buf)?;
+    if len > buf.len() as u64 {
+        return Err(DecodeError::new("buffer underflow"));
+    }
+    let (bytes, rest) = buf.split_at(len as usize);
+    *buf = rest;
+    Ok(bytes)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::signer::Signer;
+    use crate::DataBuilder;
+
+    #[test]
+    fn test_matches_prost_decoding() {
+        let signer = Signer::default();
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").sequence(9).build();
+        let mut entry = IpnsEntry::new(data, signer.sign(signables).expect("signed"));
+        entry.pub_key = Some(signer.public().encode_protobuf());
+
+        let bytes = entry.to_bytes();
+        let entry_ref = IpnsEntryRef::from_bytes(&bytes).expect("valid protobuf");
+
+        assert_eq!(entry_ref, entry.as_entry_ref());
+        assert_eq!(IpnsEntry::from(entry_ref), entry);
+
+        let peer_id = PeerId::from_public_key(&signer.public());
+        assert!(entry_ref.is_valid_for(&peer_id).expect("valid"));
+        assert_eq!(entry_ref.decode_data().unwrap().sequence, 9);
+    }
+
+    #[test]
+    fn test_skips_unknown_fields() {
+        let entry = IpnsEntry {
+            value: Some(b"value".to_vec()),
+            ..Default::default()
+        };
+        // field 15, varint 1, then field 16, length delimited "ab"
+        let bytes = [
+            entry.to_bytes(),
+            vec![0x78, 0x01, 0x82, 0x01, 0x02, b'a', b'b'],
+        ]
+        .concat();
+
+        let entry_ref = IpnsEntryRef::from_bytes(&bytes).expect("valid protobuf");
+        assert_eq!(entry_ref.value, Some(&b"value"[..]));
+        assert_eq!(
+            IpnsEntry::from(entry_ref),
+            IpnsEntry::from_bytes(&bytes).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rejects_truncated_bytes() {
+        let entry = IpnsEntry {
+            value: Some(b"value".to_vec()),
+            ..Default::default()
+        };
+        let bytes = entry.to_bytes();
+
+        assert!(IpnsEntryRef::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_validity_type() {
+        let signer = Signer::default();
+        let (mut data, _) = DataBuilder::new("/ipfs/bafkqaaa").build();
+        data.validity_type = 7;
+        let signables = crate::signer::Signables {
+            v1: Vec::new(),
+            v2: generate_v2_signable(&data.to_bytes()),
+        };
+        let entry = IpnsEntry::new(data, signer.sign(signables).expect("signed"));
+        assert_eq!(
+            entry.record_validity_type(),
+            Some(RecordValidityType::Unknown(7))
+        );
+
+        let peer_id = PeerId::from_public_key(&signer.public());
+        let err = entry
+            .is_valid_for(&peer_id)
+            .expect_err("unknown validity type");
+        assert_eq!(
+            err.downcast_ref::<UnknownValidityType>(),
+            Some(&UnknownValidityType(7))
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Human readable JSON and DAG-JSON representations of an [IpnsEntry].
+//!
+//! [JsonEntry] is the serde form used for dashboards and APIs. Bytes are
+//! multibase (base64) encoded, `value` and `validity` are shown as strings and
+//! `data` is additionally decoded into its DAG-CBOR fields for inspection.
+//!
+//! The DAG-JSON rendering follows the [DAG-JSON spec](https://ipld.io/specs/codecs/dag-json/spec/),
+//! where bytes are encoded as `{"/": {"bytes": "<base64>"}}` and map keys are sorted.
+//!
+//! Both forms round-trip back to the exact same protobuf bytes.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::entry::IpnsEntry;
+//! use ipns_entry::signer::Signer;
+//! use ipns_entry::DataBuilder;
+//!
+//! let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+//! let signed = Signer::default().sign(signables).expect("signed");
+//! let entry = IpnsEntry::new(data, signed);
+//!
+//! let json = serde_json::to_string_pretty(&entry).expect("json");
+//! let from_json: IpnsEntry = serde_json::from_str(&json).expect("entry");
+//! assert_eq!(from_json.to_bytes(), entry.to_bytes());
+//!
+//! let dag_json = entry.to_dag_json();
+//! let from_dag_json = IpnsEntry::from_dag_json(&dag_json).expect("entry");
+//! assert_eq!(from_dag_json.to_bytes(), entry.to_bytes());
+//! ```
+use crate::cbor::Data;
+use crate::entry::{IpnsEntry, RecordValidityType, ValidityType};
+use anyhow::{anyhow, Error, Result};
+use multibase::Base;
+use serde::{Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+/// Bytes which are shown as a string when they are valid UTF-8,
+/// otherwise as multibase (base64) encoded binary.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ByteString {
+    Text(String),
+    Binary { multibase: String },
+}
+
+impl From<&[u8]> for ByteString {
+    fn from(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => ByteString::Text(s.to_string()),
+            Err(_) => ByteString::Binary {
+                multibase: multibase::encode(Base::Base64, bytes),
+            },
+        }
+    }
+}
+
+impl TryFrom<ByteString> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(s: ByteString) -> Result<Self> {
+        match s {
+            ByteString::Text(s) => Ok(s.into_bytes()),
+            ByteString::Binary { multibase } => Ok(multibase::decode(multibase)?.1),
+        }
+    }
+}
+
+/// Decoded IpnsEntry.data, for display only.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct JsonData {
+    pub value: ByteString,
+    pub validity: ByteString,
+    #[serde(with = "validity_type")]
+    pub validity_type: Option<i32>,
+    pub sequence: u64,
+    pub ttl: u64,
+}
+
+impl From<&Data> for JsonData {
+    fn from(data: &Data) -> Self {
+        JsonData {
+            value: data.value.as_slice().into(),
+            validity: data.validity.as_slice().into(),
+            validity_type: Some(data.validity_type),
+            sequence: data.sequence,
+            ttl: data.ttl,
+        }
+    }
+}
+
+/// Serde form of an [IpnsEntry].
+///
+/// `decoded_data` is informational and ignored when deserializing,
+/// the signed `data` bytes are always taken from `data`.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<ByteString>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "multibase_bytes"
+    )]
+    pub signature_v1: Option<Vec<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "validity_type"
+    )]
+    pub validity_type: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validity: Option<ByteString>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "multibase_bytes"
+    )]
+    pub pub_key: Option<Vec<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "multibase_bytes"
+    )]
+    pub signature_v2: Option<Vec<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "multibase_bytes"
+    )]
+    pub data: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", skip_deserializing)]
+    pub decoded_data: Option<JsonData>,
+}
+
+impl From<&IpnsEntry> for JsonEntry {
+    fn from(entry: &IpnsEntry) -> Self {
+        JsonEntry {
+            value: entry.value.as_deref().map(ByteString::from),
+            signature_v1: entry.signature_v1.clone(),
+            validity_type: entry.validity_type,
+            validity: entry.validity.as_deref().map(ByteString::from),
+            sequence: entry.sequence,
+            ttl: entry.ttl,
+            pub_key: entry.pub_key.clone(),
+            signature_v2: entry.signature_v2.clone(),
+            data: entry.data.clone(),
+            decoded_data: entry.decode_data().ok().as_ref().map(JsonData::from),
+        }
+    }
+}
+
+impl TryFrom<JsonEntry> for IpnsEntry {
+    type Error = Error;
+
+    fn try_from(json: JsonEntry) -> Result<Self> {
+        Ok(IpnsEntry {
+            value: json.value.map(Vec::try_from).transpose()?,
+            signature_v1: json.signature_v1,
+            validity_type: json.validity_type,
+            validity: json.validity.map(Vec::try_from).transpose()?,
+            sequence: json.sequence,
+            ttl: json.ttl,
+            pub_key: json.pub_key,
+            signature_v2: json.signature_v2,
+            data: json.data,
+        })
+    }
+}
+
+impl serde::Serialize for IpnsEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&JsonEntry::from(self), serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for IpnsEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = <JsonEntry as serde::Deserialize>::deserialize(deserializer)?;
+        IpnsEntry::try_from(json).map_err(serde::de::Error::custom)
+    }
+}
+
+/// DAG-JSON bytes: `{"/": {"bytes": "<base64, no padding>"}}`
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct DagBytes {
+    #[serde(rename = "/")]
+    slash: DagBytesInner,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct DagBytesInner {
+    bytes: String,
+}
+
+impl From<&Vec<u8>> for DagBytes {
+    fn from(bytes: &Vec<u8>) -> Self {
+        DagBytes {
+            slash: DagBytesInner {
+                bytes: Base::Base64.encode(bytes),
+            },
+        }
+    }
+}
+
+impl TryFrom<DagBytes> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(bytes: DagBytes) -> Result<Self> {
+        Ok(Base::Base64.decode(bytes.slash.bytes)?)
+    }
+}
+
+/// DAG-JSON form of the IpnsEntry protobuf fields.
+/// Fields are declared in DAG-JSON key order (sorted bytewise).
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct DagJsonEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<DagBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub_key: Option<DagBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature_v1: Option<DagBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature_v2: Option<DagBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validity: Option<DagBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validity_type: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<DagBytes>,
+}
+
+impl IpnsEntry {
+    /// Render the IpnsEntry as DAG-JSON
+    pub fn to_dag_json(&self) -> String {
+        let dag = DagJsonEntry {
+            data: self.data.as_ref().map(DagBytes::from),
+            pub_key: self.pub_key.as_ref().map(DagBytes::from),
+            sequence: self.sequence,
+            signature_v1: self.signature_v1.as_ref().map(DagBytes::from),
+            signature_v2: self.signature_v2.as_ref().map(DagBytes::from),
+            ttl: self.ttl,
+            validity: self.validity.as_ref().map(DagBytes::from),
+            validity_type: self.validity_type,
+            value: self.value.as_ref().map(DagBytes::from),
+        };
+        serde_json::to_string(&dag).expect("DAG-JSON serialization not to fail")
+    }
+
+    /// Parse an IpnsEntry from its DAG-JSON rendering
+    pub fn from_dag_json(json: &str) -> Result<Self> {
+        let dag: DagJsonEntry =
+            serde_json::from_str(json).map_err(|e| anyhow!("Invalid DAG-JSON: {e}"))?;
+
+        Ok(IpnsEntry {
+            value: dag.value.map(Vec::try_from).transpose()?,
+            signature_v1: dag.signature_v1.map(Vec::try_from).transpose()?,
+            validity_type: dag.validity_type,
+            validity: dag.validity.map(Vec::try_from).transpose()?,
+            sequence: dag.sequence,
+            ttl: dag.ttl,
+            pub_key: dag.pub_key.map(Vec::try_from).transpose()?,
+            signature_v2: dag.signature_v2.map(Vec::try_from).transpose()?,
+            data: dag.data.map(Vec::try_from).transpose()?,
+        })
+    }
+}
+
+/// Optional bytes as a multibase base64 string
+mod multibase_bytes {
+    use super::*;
+    use serde::Deserialize;
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(b) => s.serialize_str(&multibase::encode(Base::Base64, b)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(s) => multibase::decode(s)
+                .map(|(_, b)| Some(b))
+                .map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// ValidityType as its name ("EOL"), or as the number if it is not a known type
+mod validity_type {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum NameOrNumber {
+        Name(String),
+        Number(i32),
+    }
+
+    pub fn serialize<S: Serializer>(v: &Option<i32>, s: S) -> Result<S::Ok, S::Error> {
+        match v.map(RecordValidityType::from) {
+            Some(RecordValidityType::Eol) => s.serialize_str("EOL"),
+            Some(RecordValidityType::Unknown(v)) => s.serialize_i32(v),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<i32>, D::Error> {
+        match Option::<NameOrNumber>::deserialize(d)? {
+            Some(NameOrNumber::Name(name)) if name == "EOL" => Ok(Some(ValidityType::Eol as i32)),
+            Some(NameOrNumber::Name(name)) => Err(serde::de::Error::custom(format!(
+                "Unknown ValidityType {name}"
+            ))),
+            Some(NameOrNumber::Number(n)) => Ok(Some(n)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::Signer;
+    use crate::DataBuilder;
+
+    fn get_entry() -> IpnsEntry {
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").sequence(3).build();
+        let signed = Signer::default().sign(signables).expect("signed");
+        IpnsEntry::new(data, signed)
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let entry = get_entry();
+
+        let json = serde_json::to_string(&entry).expect("json");
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["value"], "/ipfs/bafkqaaa");
+        assert_eq!(value["validityType"], "EOL");
+        assert_eq!(value["decodedData"]["sequence"], 3);
+
+        let from_json: IpnsEntry = serde_json::from_str(&json).expect("entry");
+        assert_eq!(from_json, entry);
+        assert_eq!(from_json.to_bytes(), entry.to_bytes());
+    }
+
+    #[test]
+    fn test_json_binary_value() {
+        let entry = IpnsEntry {
+            value: Some(vec![0xff, 0x00, 0xfe]),
+            validity_type: Some(7),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&entry).expect("json");
+        let from_json: IpnsEntry = serde_json::from_str(&json).expect("entry");
+        assert_eq!(from_json, entry);
+    }
+
+    #[test]
+    fn test_dag_json_roundtrip() {
+        let entry = get_entry();
+
+        let dag_json = entry.to_dag_json();
+        assert!(dag_json.starts_with(r#"{"data":{"/":{"bytes":""#));
+
+        let from_dag_json = IpnsEntry::from_dag_json(&dag_json).expect("entry");
+        assert_eq!(from_dag_json.to_bytes(), entry.to_bytes());
+    }
+}
@@ -7,14 +7,41 @@
 //!
 //! The output from this library can be published to the IPFS DHT, Pubsub, or anywhere else.
 //!
+//! ## `no_std`
+//!
+//! Disable the default `std` feature to use this crate with only `alloc`.
+//! Encoding, decoding, signing and validation are available, with the caller supplying the time
+//! ([DataBuilder::new_at], [entry::IpnsEntry::is_expired_at]) and the signature functions
+//! ([signer::Signables::sign_with], [entry::IpnsEntry::verify_with]).
+//! Keys, PeerIds and the `batch`, `car`, `json`, `name`, `sequence` and `unsigned` modules need `std`.
+//!
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod car;
 pub mod cbor;
 pub mod entry;
+pub mod entry_ref;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod name;
+#[cfg(feature = "std")]
+pub mod sequence;
 pub mod signer;
+pub mod time;
+#[cfg(feature = "std")]
+pub mod unsigned;
 
 use crate::entry::ValidityType;
-use humantime::Rfc3339Timestamp;
+use alloc::string::{String, ToString};
+use core::time::Duration;
 use signer::Signables;
-use std::time::Duration;
+#[cfg(feature = "std")]
 use std::time::SystemTime;
 
 /// # Example
@@ -34,11 +61,12 @@ use std::time::SystemTime;
 /// use libp2p_identity::ed25519;
 ///
 /// let value = "QmWEekX7EZLUd9VXRNMRXW3LXe4F6x7mB8oPxY5XLptrBq";
-/// let ttl = 60 * 60 * 48; // 48 hours, the default
-/// let validity: SystemTime = SystemTime::now() + Duration::from_secs(ttl);
+/// let ttl = Duration::from_secs(60 * 60 * 48); // 48 hours, the default
+/// let validity: SystemTime = SystemTime::now() + ttl;
 /// let sequence = 0;
 ///
-/// let (data, signables) = DataBuilder::new(value).validity(validity).sequence(sequence).ttl(ttl).build();
+/// // IpnsEntry.ttl is in nanoseconds
+/// let (data, signables) = DataBuilder::new(value).validity(validity).sequence(sequence).ttl(ttl.as_nanos() as u64).build();
 ///
 /// // Provide a Signer (holding your private keys) that takes `Signables {v1, v2}` and returns `Signed {v1, v2, pub_key}`
 /// let signer = Signer::default();
@@ -61,7 +89,7 @@ use std::time::SystemTime;
 #[derive(Debug, Clone)]
 pub struct DataBuilder {
     value: String,
-    validity: Rfc3339Timestamp,
+    validity: String,
     validity_type: ValidityType,
     sequence: u64,
     ttl: u64,
@@ -69,7 +97,7 @@ pub struct DataBuilder {
 
 impl DataBuilder {
     /// Create a new DataBuilder with the required value.
-    /// The default ttl is 48 hours.
+    /// The default ttl is 48 hours, in nanoseconds.
     /// The default validity is 48 hours from now.
     /// The default sequence is 0.
     ///
@@ -77,18 +105,24 @@ impl DataBuilder {
     ///
     /// When the DataBuilder is ready, call `signables()` to get the Signables {v1, v2}
     /// which can be signed by the Signer.
+    #[cfg(feature = "std")]
     pub fn new(value: &str) -> Self {
+        Self::new_at(value, time::now())
+    }
+
+    /// Create a new DataBuilder like [DataBuilder::new], with the current time
+    /// (since the UNIX epoch) supplied by the caller.
+    pub fn new_at(value: &str, now: Duration) -> Self {
         // default to 48 hours
-        let ttl = 60 * 60 * 48;
-        let validity: SystemTime = SystemTime::now() + Duration::from_secs(ttl);
-        let validity = humantime::format_rfc3339_nanos(validity);
+        let lifetime = Duration::from_secs(60 * 60 * 48);
+        let validity = time::format_rfc3339_nanos(now + lifetime);
 
         DataBuilder {
             value: value.to_string(),
             validity,
             validity_type: ValidityType::Eol,
             sequence: 0,
-            ttl,
+            ttl: lifetime.as_nanos() as u64,
         }
     }
 
@@ -97,9 +131,15 @@ impl DataBuilder {
         self
     }
 
+    #[cfg(feature = "std")]
     pub fn validity(&mut self, validity: SystemTime) -> &mut DataBuilder {
+        self.validity_at(time::since_epoch(validity))
+    }
+
+    /// Set the validity as a time since the UNIX epoch
+    pub fn validity_at(&mut self, validity: Duration) -> &mut DataBuilder {
         // Convert validity to nanoseconds of rfc3339
-        self.validity = humantime::format_rfc3339_nanos(validity);
+        self.validity = time::format_rfc3339_nanos(validity);
         self
     }
 
@@ -113,6 +153,7 @@ impl DataBuilder {
         self
     }
 
+    /// IpnsEntry.ttl, how long resolvers may cache the record, in nanoseconds
     pub fn ttl(&mut self, ttl: u64) -> &mut DataBuilder {
         self.ttl = ttl;
         self
@@ -120,23 +161,21 @@ impl DataBuilder {
 
     /// Terminal method which generates the Signables from the Builder
     pub fn build(&self) -> (cbor::Data, Signables) {
-        let v1 = vec![
-            self.value.as_bytes(),
-            self.validity.to_string().as_bytes(),
-            &[ValidityType::Eol as u8],
-        ]
-        .concat();
-
         let data = cbor::Data {
             value: self.value.as_bytes().to_vec(),
-            validity: self.validity.to_string().as_bytes().to_vec(),
+            validity: self.validity.as_bytes().to_vec(),
             validity_type: self.validity_type.into(),
             sequence: self.sequence,
             ttl: self.ttl,
         };
+        let signables = data.signables();
 
-        let v2 = vec!["ipns-signature:".as_bytes(), &data.to_bytes()].concat();
+        (data, signables)
+    }
 
-        (data, Signables { v1, v2 })
+    /// Terminal method which generates an [unsigned::UnsignedRecord], to sign elsewhere
+    #[cfg(feature = "std")]
+    pub fn build_unsigned(&self) -> unsigned::UnsignedRecord {
+        unsigned::UnsignedRecord::new(self.build().0)
     }
 }
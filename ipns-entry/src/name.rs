@@ -0,0 +1,126 @@
+//! IPNS Names, the [PeerId] of the key which signs the records.
+//!
+//! Names are written as a base58 PeerId (`12D3KooW...`, `Qm...`) or as a CIDv1
+//! with the `libp2p-key` multicodec, usually base36 (`k51...`), optionally prefixed with `/ipns/`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::name::IpnsName;
+//!
+//! let name: IpnsName = "12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap();
+//! let base36 = name.to_base36();
+//! assert!(base36.starts_with("k51"));
+//! assert_eq!(format!("/ipns/{base36}").parse::<IpnsName>().unwrap(), name);
+//! ```
+use anyhow::{anyhow, Result};
+use cid::multibase::Base;
+use cid::Cid;
+use libp2p_identity::PeerId;
+use multihash::Multihash;
+use std::fmt;
+use std::str::FromStr;
+
+/// The `libp2p-key` multicodec, used in IPNS Name CIDs
+pub const LIBP2P_KEY_CODEC: u64 = 0x72;
+
+/// An IPNS Name, parsed from any of its string forms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpnsName(PeerId);
+
+impl IpnsName {
+    pub fn peer_id(&self) -> PeerId {
+        self.0
+    }
+
+    /// The base58btc PeerId, e.g. `12D3KooW...`
+    pub fn to_base58(&self) -> String {
+        self.0.to_base58()
+    }
+
+    /// CIDv1 with the `libp2p-key` multicodec
+    pub fn to_cid(&self) -> Cid {
+        let hash = Multihash::from_bytes(&self.0.to_bytes()).expect("PeerId is a multihash");
+        Cid::new_v1(LIBP2P_KEY_CODEC, hash)
+    }
+
+    /// The CID in base36, e.g. `k51...`, the recommended form for IPNS Names
+    pub fn to_base36(&self) -> String {
+        self.to_cid()
+            .to_string_of_base(Base::Base36Lower)
+            .expect("CIDv1 encodes to any base")
+    }
+}
+
+impl From<PeerId> for IpnsName {
+    fn from(peer_id: PeerId) -> Self {
+        IpnsName(peer_id)
+    }
+}
+
+impl From<IpnsName> for PeerId {
+    fn from(name: IpnsName) -> Self {
+        name.0
+    }
+}
+
+impl FromStr for IpnsName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.strip_prefix("/ipns/").unwrap_or(s);
+
+        // base58 PeerIds first, as `Qm...` also parses as a CIDv0
+        if let Ok(peer_id) = PeerId::from_str(s) {
+            return Ok(IpnsName(peer_id));
+        }
+
+        let cid = Cid::try_from(s).map_err(|_| anyhow!("Invalid IPNS Name {s}"))?;
+        if cid.codec() != LIBP2P_KEY_CODEC {
+            return Err(anyhow!("IPNS Name CID {s} is not a libp2p-key"));
+        }
+
+        let peer_id = PeerId::from_bytes(&cid.hash().to_bytes())
+            .map_err(|_| anyhow!("IPNS Name CID {s} is not a PeerId"))?;
+        Ok(IpnsName(peer_id))
+    }
+}
+
+/// Displays the base36 CID form
+impl fmt::Display for IpnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_base36())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_identity::Keypair;
+
+    #[test]
+    fn test_all_forms_parse_to_the_same_name() {
+        for keypair in [Keypair::generate_ed25519(), Keypair::generate_secp256k1()] {
+            let name = IpnsName::from(PeerId::from_public_key(&keypair.public()));
+
+            let forms = [
+                name.to_base58(),
+                name.to_base36(),
+                name.to_cid().to_string(),
+                format!("/ipns/{}", name.to_base58()),
+                format!("/ipns/{name}"),
+            ];
+
+            for form in forms {
+                assert_eq!(form.parse::<IpnsName>().unwrap(), name, "{form}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_other_cids() {
+        // an /ipfs/ raw CID
+        assert!("bafkqaaa".parse::<IpnsName>().is_err());
+        assert!("not a name".parse::<IpnsName>().is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! Per-key sequence tracking, so a key never signs a sequence it has used before.
+//!
+//! Peers keep the record with the highest sequence, so a publisher which restarts, or two
+//! processes sharing a key, must never reuse or decrease it. A [SequenceTracker] persists the
+//! last sequence and last published record of each key in a [SequenceStore], refuses to sign
+//! non-increasing sequences with a [StaleSequence] error, and recovers from records seen on the network.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::name::IpnsName;
+//! use ipns_entry::sequence::{FileStore, SequenceTracker};
+//! use ipns_entry::signer::Keypair;
+//! use ipns_entry::DataBuilder;
+//!
+//! # let dir = std::env::temp_dir().join(format!("ipns-sequence-doc-{}", std::process::id()));
+//! let keypair = Keypair::generate_ed25519();
+//! let name = IpnsName::from(keypair.public().to_peer_id());
+//! let mut tracker = SequenceTracker::new(FileStore::open(&dir).unwrap());
+//!
+//! // after a restart, catch up with the records found on the DHT or pubsub
+//! # let records_from_network = Vec::new();
+//! tracker.recover(&name, records_from_network).unwrap();
+//!
+//! let sequence = tracker.next_sequence(&name).unwrap();
+//! let (data, _) = DataBuilder::new("/ipfs/bafkqaaa").sequence(sequence).build();
+//! let entry = tracker.sign(&keypair, data).unwrap();
+//!
+//! // signing the same sequence again is refused
+//! let (data, _) = DataBuilder::new("/ipfs/bafkqaab").sequence(sequence).build();
+//! assert!(tracker.sign(&keypair, data).is_err());
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+use crate::cbor::Data;
+use crate::entry::IpnsEntry;
+use crate::name::IpnsName;
+use crate::signer::{Keypair, Signer};
+use anyhow::{anyhow, Result};
+use multibase::Base;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Error for a sequence which is not greater than the last one used by the key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleSequence {
+    pub attempted: u64,
+    pub last: u64,
+}
+
+impl fmt::Display for StaleSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sequence {} is not greater than the last sequence {}",
+            self.attempted, self.last
+        )
+    }
+}
+
+impl std::error::Error for StaleSequence {}
+
+/// What is known about one key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyState {
+    /// The highest sequence signed by this key, or seen on the network
+    pub sequence: u64,
+    /// Protobuf bytes of the record with that sequence
+    pub record: Vec<u8>,
+}
+
+/// Where a [SequenceTracker] keeps its [KeyState]s
+pub trait SequenceStore {
+    fn load(&self, name: &IpnsName) -> Result<Option<KeyState>>;
+
+    /// Save `state`, failing with [StaleSequence] unless its sequence is greater than the
+    /// stored one. Must be atomic, as other processes may share the store.
+    fn store_if_newer(&mut self, name: &IpnsName, state: &KeyState) -> Result<()>;
+}
+
+fn check_newer(last: Option<u64>, attempted: u64) -> Result<()> {
+    match last {
+        Some(last) if attempted <= last => Err(StaleSequence { attempted, last }.into()),
+        _ => Ok(()),
+    }
+}
+
+/// In-memory store, forgotten when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    states: HashMap<IpnsName, KeyState>,
+}
+
+impl SequenceStore for MemoryStore {
+    fn load(&self, name: &IpnsName) -> Result<Option<KeyState>> {
+        Ok(self.states.get(name).cloned())
+    }
+
+    fn store_if_newer(&mut self, name: &IpnsName, state: &KeyState) -> Result<()> {
+        check_newer(self.states.get(name).map(|s| s.sequence), state.sequence)?;
+        self.states.insert(*name, state.clone());
+        Ok(())
+    }
+}
+
+/// JSON form of a [KeyState] file
+#[derive(Serialize, Deserialize)]
+struct JsonKeyState {
+    sequence: u64,
+    /// multibase base64 protobuf bytes
+    record: String,
+}
+
+/// Store with one JSON file per key, `<base36 name>.json`, in a directory.
+///
+/// Updates hold an exclusive lock on `<base36 name>.lock` and replace the file atomically,
+/// so processes sharing the directory never both sign the same sequence.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Use `dir`, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(FileStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, name: &IpnsName, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{extension}", name.to_base36()))
+    }
+}
+
+impl SequenceStore for FileStore {
+    fn load(&self, name: &IpnsName) -> Result<Option<KeyState>> {
+        let json = match std::fs::read_to_string(self.path(name, "json")) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let state: JsonKeyState = serde_json::from_str(&json)?;
+        Ok(Some(KeyState {
+            sequence: state.sequence,
+            record: multibase::decode(state.record)?.1,
+        }))
+    }
+
+    fn store_if_newer(&mut self, name: &IpnsName, state: &KeyState) -> Result<()> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path(name, "lock"))?;
+        lock.lock()?;
+
+        check_newer(self.load(name)?.map(|s| s.sequence), state.sequence)?;
+
+        let json = serde_json::to_string(&JsonKeyState {
+            sequence: state.sequence,
+            record: multibase::encode(Base::Base64, &state.record),
+        })?;
+
+        // write then rename, so a crash never leaves a partial file
+        let tmp = self.path(name, "json.tmp");
+        std::fs::write(&tmp, json)?;
+        File::open(&tmp)?.sync_all()?;
+        std::fs::rename(&tmp, self.path(name, "json"))?;
+
+        Ok(())
+    }
+}
+
+/// Signs records with strictly increasing sequences per key
+#[derive(Debug)]
+pub struct SequenceTracker<S> {
+    store: S,
+}
+
+impl<S: SequenceStore> SequenceTracker<S> {
+    pub fn new(store: S) -> Self {
+        SequenceTracker { store }
+    }
+
+    /// The last sequence signed or seen for `name`
+    pub fn last_sequence(&self, name: &IpnsName) -> Result<Option<u64>> {
+        Ok(self.store.load(name)?.map(|state| state.sequence))
+    }
+
+    /// The record with the last sequence
+    pub fn last_record(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        self.store
+            .load(name)?
+            .map(|state| IpnsEntry::from_bytes(&state.record).map_err(Into::into))
+            .transpose()
+    }
+
+    /// The sequence to use for the next record: 0 for a new key, otherwise the last one + 1
+    pub fn next_sequence(&self, name: &IpnsName) -> Result<u64> {
+        match self.last_sequence(name)? {
+            None => Ok(0),
+            Some(last) => last
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("Sequence of {name} is at its maximum")),
+        }
+    }
+
+    /// Sign `data` with `keypair`, refusing with [StaleSequence] unless its sequence is greater
+    /// than the last one of the key. The sequence and record are persisted before returning.
+    pub fn sign(&mut self, keypair: &Keypair, data: Data) -> Result<IpnsEntry> {
+        let name = IpnsName::from(keypair.public().to_peer_id());
+        let sequence = data.sequence;
+        check_newer(self.last_sequence(&name)?, sequence)?;
+
+        let signables = data.signables();
+        let signed = Signer::new(keypair.clone()).sign(signables)?;
+        let mut entry = IpnsEntry::new(data, signed);
+        entry.embed_public_key(&keypair.public());
+
+        let state = KeyState {
+            sequence,
+            record: entry.to_bytes(),
+        };
+        self.store.store_if_newer(&name, &state)?;
+
+        Ok(entry)
+    }
+
+    /// Note a record for `name` seen on the network. Returns whether it raised the last sequence.
+    ///
+    /// Fails if the record is not valid for `name`, so forged records cannot block the key.
+    pub fn observe(&mut self, name: &IpnsName, entry: &IpnsEntry) -> Result<bool> {
+        if !entry.is_valid_for(&name.peer_id())? {
+            return Err(anyhow!("IPNS Record is not valid for {name}"));
+        }
+
+        let sequence = entry.decode_data()?.sequence;
+        if self
+            .last_sequence(name)?
+            .is_some_and(|last| sequence <= last)
+        {
+            return Ok(false);
+        }
+
+        let state = KeyState {
+            sequence,
+            record: entry.to_bytes(),
+        };
+        match self.store.store_if_newer(name, &state) {
+            Ok(()) => Ok(true),
+            // another process stored a higher sequence meanwhile
+            Err(e) if e.is::<StaleSequence>() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Recover after a restart from the records found on the network (DHT, pubsub, delegated routing).
+    /// Invalid records are skipped. Returns the last sequence afterwards.
+    pub fn recover(
+        &mut self,
+        name: &IpnsName,
+        records: impl IntoIterator<Item = IpnsEntry>,
+    ) -> Result<Option<u64>> {
+        for entry in records {
+            // a bad record from the network must not stop recovery
+            let _ = self.observe(name, &entry);
+        }
+        self.last_sequence(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataBuilder;
+
+    fn data(sequence: u64) -> Data {
+        DataBuilder::new("/ipfs/bafkqaaa")
+            .sequence(sequence)
+            .build()
+            .0
+    }
+
+    fn name(keypair: &Keypair) -> IpnsName {
+        IpnsName::from(keypair.public().to_peer_id())
+    }
+
+    #[test]
+    fn test_refuses_non_increasing_sequences() {
+        let keypair = Keypair::generate_ed25519();
+        let mut tracker = SequenceTracker::new(MemoryStore::default());
+        assert_eq!(tracker.next_sequence(&name(&keypair)).unwrap(), 0);
+
+        tracker.sign(&keypair, data(0)).unwrap();
+        tracker.sign(&keypair, data(5)).unwrap();
+        assert_eq!(tracker.next_sequence(&name(&keypair)).unwrap(), 6);
+
+        for stale in [0, 4, 5] {
+            let err = tracker.sign(&keypair, data(stale)).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<StaleSequence>(),
+                Some(&StaleSequence {
+                    attempted: stale,
+                    last: 5
+                })
+            );
+        }
+
+        // other keys are tracked separately
+        let other = Keypair::generate_ed25519();
+        tracker.sign(&other, data(0)).unwrap();
+    }
+
+    #[test]
+    fn test_recover_from_network() {
+        let keypair = Keypair::generate_ed25519();
+        let name = name(&keypair);
+
+        // records published by another process
+        let mut elsewhere = SequenceTracker::new(MemoryStore::default());
+        let records: Vec<_> = [3, 9, 7]
+            .into_iter()
+            .map(|sequence| elsewhere.sign(&keypair, data(sequence)))
+            .filter_map(Result::ok)
+            .collect();
+        assert_eq!(records.len(), 2); // 7 < 9 was refused
+
+        // and one forged by another key
+        let forged = SequenceTracker::new(MemoryStore::default())
+            .sign(&Keypair::generate_ed25519(), data(100))
+            .unwrap();
+
+        let mut tracker = SequenceTracker::new(MemoryStore::default());
+        let last = tracker
+            .recover(&name, records.into_iter().chain([forged]))
+            .unwrap();
+        assert_eq!(last, Some(9));
+        assert_eq!(tracker.next_sequence(&name).unwrap(), 10);
+        assert_eq!(
+            tracker.last_record(&name).unwrap().unwrap().sequence,
+            Some(9)
+        );
+        assert!(tracker.sign(&keypair, data(9)).is_err());
+    }
+
+    #[test]
+    fn test_file_store_persists() {
+        let dir = std::env::temp_dir().join(format!("ipns-sequence-test-{}", std::process::id()));
+        let keypair = Keypair::generate_ed25519();
+        let name = name(&keypair);
+
+        let entry = {
+            let mut tracker = SequenceTracker::new(FileStore::open(&dir).unwrap());
+            tracker.sign(&keypair, data(2)).unwrap()
+        };
+
+        // a restarted process, or a second one sharing the directory
+        let mut tracker = SequenceTracker::new(FileStore::open(&dir).unwrap());
+        assert_eq!(tracker.last_sequence(&name).unwrap(), Some(2));
+        assert_eq!(tracker.last_record(&name).unwrap(), Some(entry));
+        assert!(tracker.sign(&keypair, data(2)).is_err());
+        tracker.sign(&keypair, data(3)).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
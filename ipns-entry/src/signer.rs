@@ -1,27 +1,60 @@
 //! Re-export of `libp2p_identity::Keypair`
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 pub use libp2p_identity::{ed25519, Keypair, PublicKey, SigningError};
 use serde_derive::{Deserialize, Serialize};
 
 /// Generate a new ed25519 keypair for signing cbor data.
+#[cfg(feature = "std")]
 pub fn generate() -> Keypair {
     Keypair::generate_ed25519()
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, Hash)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Hash)]
 pub struct Signables {
     pub v1: Vec<u8>,
     pub v2: Vec<u8>,
 }
 
+impl Signables {
+    /// Sign both signables with `sign`, such as a hardware key or a `no_std` signing library
+    ///
+    /// ```rust
+    /// use core::time::Duration;
+    /// use ed25519_dalek::{Signer, SigningKey};
+    /// use ipns_entry::DataBuilder;
+    ///
+    /// let key = SigningKey::from_bytes(&[7u8; 32]);
+    /// let now = Duration::from_secs(1_700_000_000); // from the caller's clock
+    /// let (_data, signables) = DataBuilder::new_at("/ipfs/bafkqaaa", now).build();
+    /// let signed = signables
+    ///     .sign_with(|bytes| Ok::<_, core::convert::Infallible>(key.sign(bytes).to_bytes().to_vec()))
+    ///     .unwrap();
+    /// assert_eq!(signed.v2.len(), 64);
+    /// ```
+    pub fn sign_with<E>(
+        self,
+        mut sign: impl FnMut(&[u8]) -> Result<Vec<u8>, E>,
+    ) -> Result<Signed, E> {
+        let v1 = sign(&self.v1)?;
+        let v2 = sign(&self.v2)?;
+
+        Ok(Signed { v1, v2 })
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Signed {
     pub v1: Vec<u8>,
     pub v2: Vec<u8>,
 }
 
+#[cfg(feature = "std")]
 pub struct Signer {
     keypair: Keypair,
 }
 
+#[cfg(feature = "std")]
 impl Default for Signer {
     fn default() -> Self {
         Self {
@@ -30,6 +63,7 @@ impl Default for Signer {
     }
 }
 
+#[cfg(feature = "std")]
 impl Signer {
     pub fn new(keypair: Keypair) -> Self {
         Self { keypair }
@@ -48,10 +82,12 @@ impl Signer {
 }
 
 /// Used to sign bytes created by `cbor::InputData{}.to_bytes()`
+#[cfg(feature = "std")]
 pub struct V2Signer {
     keypair: ed25519::Keypair,
 }
 
+#[cfg(feature = "std")]
 impl V2Signer {
     pub fn new(keypair: &ed25519::Keypair) -> Self {
         Self {
@@ -79,6 +115,7 @@ impl V2Signer {
 }
 
 /// *Do not use.* For backward compatibility only.
+#[cfg(feature = "std")]
 pub struct V1Signer {
     pub keypair: ed25519::Keypair,
     pub value: &'static [u8],
@@ -86,6 +123,7 @@ pub struct V1Signer {
     pub validity_type: u8,
 }
 
+#[cfg(feature = "std")]
 impl V1Signer {
     /// Creates a new V1Signer
     pub fn new(
@@ -127,7 +165,7 @@ impl V1Signer {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
     use super::*;
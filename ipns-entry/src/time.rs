@@ -0,0 +1,251 @@
+//! RFC3339 timestamps for IpnsEntry.validity, without depending on the system clock.
+//!
+//! Times are a [Duration] since the UNIX epoch, so `no_std` callers can supply the
+//! current time from whatever clock they have. With the `std` feature, [now] and
+//! [since_epoch] convert from [std::time::SystemTime].
+//!
+//! # Example
+//!
+//! ```rust
+//! use core::time::Duration;
+//! use ipns_entry::time::{format_rfc3339_nanos, parse_rfc3339};
+//!
+//! let validity = format_rfc3339_nanos(Duration::from_secs(2_000_000_000));
+//! assert_eq!(validity, "2033-05-18T03:33:20.000000000Z");
+//! assert_eq!(parse_rfc3339(validity.as_bytes()).unwrap(), Duration::from_secs(2_000_000_000));
+//! ```
+use alloc::format;
+use alloc::string::String;
+use anyhow::{anyhow, Result};
+use core::time::Duration;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Current time since the UNIX epoch
+#[cfg(feature = "std")]
+pub fn now() -> Duration {
+    since_epoch(std::time::SystemTime::now())
+}
+
+/// Convert a SystemTime to a Duration since the UNIX epoch. Earlier times are clamped to the epoch.
+#[cfg(feature = "std")]
+pub fn since_epoch(time: std::time::SystemTime) -> Duration {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Format as RFC3339 in UTC with nanoseconds, e.g. `2033-05-18T03:33:20.000000000Z`
+pub fn format_rfc3339_nanos(since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let secs_of_day = secs % SECS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Parse an RFC3339 timestamp, such as IpnsEntry.validity, into a Duration since the UNIX epoch.
+///
+/// Accepts any number of fractional digits (nanosecond precision is kept) and
+/// `Z` or numeric UTC offsets.
+pub fn parse_rfc3339(bytes: &[u8]) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid RFC3339 timestamp");
+
+    if bytes.len() < 20 {
+        return Err(invalid());
+    }
+
+    let num = |range: core::ops::Range<usize>| -> Result<u64> {
+        bytes[range].iter().try_fold(0u64, |acc, b| match b {
+            b'0'..=b'9' => Ok(acc * 10 + u64::from(b - b'0')),
+            _ => Err(invalid()),
+        })
+    };
+    let sep = |i: usize, expected: &[u8]| {
+        if expected.contains(&bytes[i]) {
+            Ok(())
+        } else {
+            Err(invalid())
+        }
+    };
+
+    let year = num(0..4)?;
+    sep(4, b"-")?;
+    let month = num(5..7)?;
+    sep(7, b"-")?;
+    let day = num(8..10)?;
+    sep(10, b"Tt ")?;
+    let hour = num(11..13)?;
+    sep(13, b":")?;
+    let minute = num(14..16)?;
+    sep(16, b":")?;
+    let second = num(17..19)?;
+
+    if !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+
+    // optional fraction, nanoseconds precision
+    let mut rest = &bytes[19..];
+    let mut nanos = 0u32;
+    if let Some((b'.', fraction)) = rest.split_first() {
+        let digits = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        for (i, b) in fraction[..digits].iter().take(9).enumerate() {
+            nanos += u32::from(b - b'0') * 10u32.pow(8 - i as u32);
+        }
+        rest = &fraction[digits..];
+    }
+
+    // UTC offset, in seconds east of UTC
+    let offset: i64 = match rest {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let digits = [*h1, *h2, *m1, *m2];
+            if !digits.iter().all(u8::is_ascii_digit) {
+                return Err(invalid());
+            }
+            let [h1, h2, m1, m2] = digits.map(|d| i64::from(d - b'0'));
+            let (hours, minutes) = (h1 * 10 + h2, m1 * 10 + m2);
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err(invalid()),
+    };
+
+    let days = days_from_civil(year as i64, month as u32, day as u32);
+    let secs = days * SECS_PER_DAY as i64 + (hour * 3600 + minute * 60 + second) as i64 - offset;
+
+    if secs < 0 {
+        return Err(anyhow!("RFC3339 timestamp is before the UNIX epoch"));
+    }
+
+    Ok(Duration::new(secs as u64, nanos))
+}
+
+fn is_leap_year(year: u64) -> bool {
+    // divisible by 4, except centuries not divisible by 400
+    matches!((year % 4, year % 100, year % 400), (0, 1.., _) | (0, 0, 0))
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+/// From <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian (year, month, day) of days since 1970-01-01.
+/// From <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format_rfc3339_nanos(Duration::ZERO),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+        assert_eq!(
+            format_rfc3339_nanos(Duration::new(1_681_752_844, 229_000_000)),
+            "2023-04-17T17:34:04.229000000Z"
+        );
+        assert_eq!(
+            format_rfc3339_nanos(Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000000000Z"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        // js-ipns, 9 fractional digits
+        assert_eq!(
+            parse_rfc3339(b"2023-04-17T17:34:04.229000000Z").unwrap(),
+            Duration::new(1_681_752_844, 229_000_000)
+        );
+        // go-ipns trims trailing zeros
+        assert_eq!(
+            parse_rfc3339(b"2023-04-17T17:34:04.229Z").unwrap(),
+            Duration::new(1_681_752_844, 229_000_000)
+        );
+        assert_eq!(
+            parse_rfc3339(b"2023-04-17T19:34:04+02:00").unwrap(),
+            Duration::from_secs(1_681_752_844)
+        );
+        assert_eq!(
+            parse_rfc3339(b"1970-01-01T00:00:00Z").unwrap(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        for invalid in [
+            &b""[..],
+            b"2023-04-17",
+            b"2023-04-17T17:34:04",
+            b"2023-02-29T00:00:00Z",
+            b"2023-13-01T00:00:00Z",
+            b"2023-04-17T24:00:00Z",
+            b"2023-04-17T17:34:04.Z",
+            b"2023-04-17T17:34:04+0200",
+            b"1969-12-31T23:59:59Z",
+            b"+023-04-17T17:34:04Z",
+        ] {
+            assert!(parse_rfc3339(invalid).is_err(), "{invalid:?}");
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Detached signing: build a record on the publishing server, sign it on an offline machine,
+//! then assemble and publish it, so the private key never touches the server.
+//!
+//! An [UnsignedRecord] holds the [Data] and its [Signables]. It is carried to the signer as JSON,
+//! where `data` (the DAG-CBOR bytes) is authoritative and `decodedData` is shown for review.
+//! A `decodedData` which does not match `data` is rejected, so the reviewed fields are the signed ones.
+//! The signatures come back as a [Signed], and [UnsignedRecord::assemble] only returns an
+//! [IpnsEntry] once they verify against the public key.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ipns_entry::DataBuilder;
+//! use ipns_entry::signer::{Keypair, Signer};
+//! use ipns_entry::unsigned::UnsignedRecord;
+//!
+//! // publishing server
+//! let unsigned = DataBuilder::new("/ipfs/bafkqaaa").sequence(1).build_unsigned();
+//! let json = unsigned.to_json();
+//!
+//! // offline machine, holding the key
+//! let keypair = Keypair::generate_ed25519();
+//! let to_sign = UnsignedRecord::from_json(&json).unwrap();
+//! let signed = Signer::new(keypair.clone()).sign(to_sign.signables().clone()).unwrap();
+//!
+//! // publishing server, with the signatures and public key
+//! let entry = unsigned.assemble(signed, &keypair.public()).unwrap();
+//! assert!(entry.is_valid_for(&keypair.public().to_peer_id()).unwrap());
+//! ```
+use crate::cbor::Data;
+use crate::entry::IpnsEntry;
+use crate::json::JsonData;
+use crate::signer::{PublicKey, Signables, Signed};
+use anyhow::{anyhow, Result};
+use libp2p_identity::PeerId;
+use multibase::Base;
+use serde_derive::{Deserialize, Serialize};
+
+/// An IPNS Record waiting for its signatures. The signables always match the data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsignedRecord {
+    data: Data,
+    signables: Signables,
+}
+
+/// JSON form of an [UnsignedRecord], bytes are multibase base64
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonUnsignedRecord {
+    data: String,
+    signable_v1: String,
+    signable_v2: String,
+    /// For review only, must match `data` when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decoded_data: Option<JsonData>,
+}
+
+impl UnsignedRecord {
+    pub fn new(data: Data) -> Self {
+        let signables = data.signables();
+        UnsignedRecord { data, signables }
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    /// The bytes to sign for IpnsEntry.signatureV1 and IpnsEntry.signatureV2
+    pub fn signables(&self) -> &Signables {
+        &self.signables
+    }
+
+    pub fn to_json(&self) -> String {
+        let json = JsonUnsignedRecord {
+            data: multibase::encode(Base::Base64, self.data.to_bytes()),
+            signable_v1: multibase::encode(Base::Base64, &self.signables.v1),
+            signable_v2: multibase::encode(Base::Base64, &self.signables.v2),
+            decoded_data: Some(JsonData::from(&self.data)),
+        };
+        serde_json::to_string_pretty(&json).expect("JSON serializes")
+    }
+
+    /// Parse the JSON form, rejecting signables or a `decodedData` which do not match the data,
+    /// so a tampered file cannot get the signer to sign something else.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let json: JsonUnsignedRecord = serde_json::from_str(json)?;
+
+        let (_, data_bytes) = multibase::decode(&json.data)?;
+        let data = Data::from_bytes(&data_bytes).map_err(|_| anyhow!("Invalid DAG-CBOR"))?;
+        if data.to_bytes() != data_bytes {
+            return Err(anyhow!("Unsigned record data is not canonical DAG-CBOR"));
+        }
+
+        if let Some(decoded_data) = &json.decoded_data {
+            if *decoded_data != JsonData::from(&data) {
+                return Err(anyhow!(
+                    "Unsigned record decodedData does not match its data"
+                ));
+            }
+        }
+
+        let record = UnsignedRecord::new(data);
+        let signables = Signables {
+            v1: multibase::decode(&json.signable_v1)?.1,
+            v2: multibase::decode(&json.signable_v2)?.1,
+        };
+        if signables != record.signables {
+            return Err(anyhow!("Unsigned record signables do not match its data"));
+        }
+
+        Ok(record)
+    }
+
+    /// Attach signatures made elsewhere by the key of `public_key`.
+    ///
+    /// Both signatures are verified first. IpnsEntry.pubKey is set when the key is not
+    /// inlined in the IPNS Name (RSA).
+    pub fn assemble(self, signed: Signed, public_key: &PublicKey) -> Result<IpnsEntry> {
+        if !public_key.verify(&self.signables.v1, &signed.v1) {
+            return Err(anyhow!(
+                "IpnsEntry.signatureV1 does not verify for the public key"
+            ));
+        }
+        if !public_key.verify(&self.signables.v2, &signed.v2) {
+            return Err(anyhow!(
+                "IpnsEntry.signatureV2 does not verify for the public key"
+            ));
+        }
+
+        let mut entry = IpnsEntry::new(self.data, signed);
+        entry.embed_public_key(public_key);
+
+        // the assembled record must validate as a peer would
+        if !entry.is_valid_for(&PeerId::from_public_key(public_key))? {
+            return Err(anyhow!("Assembled IPNS Record does not validate"));
+        }
+
+        Ok(entry)
+    }
+}
+
+impl From<Data> for UnsignedRecord {
+    fn from(data: Data) -> Self {
+        UnsignedRecord::new(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::{Keypair, Signer};
+    use crate::DataBuilder;
+
+    fn unsigned() -> UnsignedRecord {
+        DataBuilder::new("/ipfs/bafkqaaa")
+            .sequence(4)
+            .ttl(60)
+            .build_unsigned()
+    }
+
+    #[test]
+    fn test_detached_signing_roundtrip() {
+        let keypair = Keypair::generate_ed25519();
+        let unsigned = unsigned();
+
+        let offline = UnsignedRecord::from_json(&unsigned.to_json()).unwrap();
+        assert_eq!(offline, unsigned);
+
+        let signed = Signer::new(keypair.clone())
+            .sign(offline.signables().clone())
+            .unwrap();
+        let entry = unsigned
+            .clone()
+            .assemble(signed, &keypair.public())
+            .unwrap();
+
+        // the same bytes as signing in place
+        let (data, signables) = (unsigned.data().clone(), unsigned.signables().clone());
+        let in_place = IpnsEntry::new(data, Signer::new(keypair).sign(signables).unwrap());
+        assert_eq!(entry.to_bytes(), in_place.to_bytes());
+    }
+
+    #[test]
+    fn test_assemble_rejects_wrong_signatures() {
+        let keypair = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519();
+        let unsigned = unsigned();
+        let signed = Signer::new(keypair.clone())
+            .sign(unsigned.signables().clone())
+            .unwrap();
+
+        // signed by another key
+        assert!(unsigned
+            .clone()
+            .assemble(signed.clone(), &other.public())
+            .is_err());
+
+        // signatures swapped
+        let swapped = Signed {
+            v1: signed.v2.clone(),
+            v2: signed.v1.clone(),
+        };
+        assert!(unsigned
+            .clone()
+            .assemble(swapped, &keypair.public())
+            .is_err());
+
+        // signatures of other data
+        let other_data = DataBuilder::new("/ipfs/bafkqaab").build_unsigned();
+        assert!(other_data.assemble(signed, &keypair.public()).is_err());
+    }
+
+    #[test]
+    fn test_from_json_rejects_tampering() {
+        let json = unsigned().to_json();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["decodedData"]["value"], "/ipfs/bafkqaaa");
+
+        // signables of other data
+        let other = DataBuilder::new("/ipfs/bafkqaab")
+            .build_unsigned()
+            .to_json();
+        let other: serde_json::Value = serde_json::from_str(&other).unwrap();
+        value["signableV2"] = other["signableV2"].clone();
+        assert!(UnsignedRecord::from_json(&value.to_string()).is_err());
+
+        // decodedData shown for review, but different from the data
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["decodedData"]["value"] = "/ipfs/bafkqaab".into();
+        assert!(UnsignedRecord::from_json(&value.to_string()).is_err());
+
+        // decodedData is optional
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value.as_object_mut().unwrap().remove("decodedData");
+        let record = UnsignedRecord::from_json(&value.to_string()).unwrap();
+        assert_eq!(record.data().value, b"/ipfs/bafkqaaa");
+    }
+}
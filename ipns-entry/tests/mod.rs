@@ -1,3 +1,5 @@
+#![cfg(feature = "std")]
+
 mod auto_traits;
 
 mod integration {
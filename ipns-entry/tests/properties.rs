@@ -0,0 +1,116 @@
+//! Property tests: encode/decode round-trips hold and no input can panic.
+#![cfg(feature = "std")]
+use ipns_entry::car::Car;
+use ipns_entry::cbor::{Data, DataRef};
+use ipns_entry::entry::{IpnsEntry, PeerId};
+use ipns_entry::entry_ref::IpnsEntryRef;
+use ipns_entry::signer::Signer;
+use ipns_entry::time::{format_rfc3339_nanos, parse_rfc3339};
+use ipns_entry::DataBuilder;
+use proptest::prelude::*;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn bytes(max: usize) -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..max)
+}
+
+prop_compose! {
+    fn arb_entry()(
+        value in prop::option::of(bytes(128)),
+        signature_v1 in prop::option::of(bytes(128)),
+        validity_type in prop::option::of(any::<i32>()),
+        validity in prop::option::of(bytes(64)),
+        sequence in prop::option::of(any::<u64>()),
+        ttl in prop::option::of(any::<u64>()),
+        pub_key in prop::option::of(bytes(128)),
+        signature_v2 in prop::option::of(bytes(128)),
+        data in prop::option::of(bytes(256)),
+    ) -> IpnsEntry {
+        IpnsEntry { value, signature_v1, validity_type, validity, sequence, ttl, pub_key, signature_v2, data }
+    }
+}
+
+prop_compose! {
+    fn arb_data()(
+        value in bytes(128),
+        validity in bytes(64),
+        sequence in any::<u64>(),
+        ttl in any::<u64>(),
+        validity_type in any::<i32>(),
+    ) -> Data {
+        Data { value, validity, sequence, ttl, validity_type }
+    }
+}
+
+proptest! {
+    #[test]
+    fn entry_protobuf_roundtrip(entry in arb_entry()) {
+        let bytes = entry.to_bytes();
+        prop_assert_eq!(&IpnsEntry::from_bytes(&bytes).unwrap(), &entry);
+        prop_assert_eq!(IpnsEntryRef::from_bytes(&bytes).unwrap(), entry.as_entry_ref());
+    }
+
+    #[test]
+    fn entry_json_roundtrip(entry in arb_entry()) {
+        let json = serde_json::to_string(&entry).unwrap();
+        prop_assert_eq!(&serde_json::from_str::<IpnsEntry>(&json).unwrap(), &entry);
+
+        let dag_json = entry.to_dag_json();
+        prop_assert_eq!(&IpnsEntry::from_dag_json(&dag_json).unwrap(), &entry);
+    }
+
+    #[test]
+    fn data_cbor_roundtrip(data in arb_data()) {
+        let bytes = data.to_bytes();
+        prop_assert_eq!(&Data::from_bytes(&bytes).unwrap(), &data);
+        prop_assert_eq!(Data::from(DataRef::from_bytes(&bytes).unwrap()), data);
+    }
+
+    #[test]
+    fn signed_entry_is_valid(value in "/ipfs/[a-z0-9]{1,59}", sequence in any::<u64>(), ttl in any::<u64>()) {
+        let signer = Signer::default();
+        let (data, signables) = DataBuilder::new(&value).sequence(sequence).ttl(ttl).build();
+        let entry = IpnsEntry::new(data, signer.sign(signables).unwrap());
+
+        let peer_id = PeerId::from_public_key(&signer.public());
+        let bytes = entry.to_bytes();
+        prop_assert!(IpnsEntry::from_bytes(&bytes).unwrap().is_valid_for(&peer_id).unwrap());
+    }
+
+    #[test]
+    fn decoding_never_panics(input in bytes(12 * 1024)) {
+        let _ = IpnsEntry::from_bytes(&input);
+        let _ = IpnsEntryRef::from_bytes(&input);
+        let _ = Data::from_bytes(&input);
+        let _ = DataRef::from_bytes(&input);
+        let _ = Car::from_bytes(&input);
+    }
+
+    #[test]
+    fn validation_never_panics(entry in arb_entry(), data in prop::option::of(arb_data())) {
+        let signer = Signer::default();
+        let peer_id = PeerId::from_public_key(&signer.public());
+
+        let mut entry = entry;
+        if let Some(data) = data {
+            // consistent fields, so validation reaches the signature check
+            entry.data = Some(data.to_bytes());
+            entry.value = Some(data.value);
+            entry.validity = Some(data.validity);
+            entry.validity_type = Some(data.validity_type);
+            entry.sequence = Some(data.sequence);
+            entry.ttl = Some(data.ttl);
+        }
+
+        let _ = entry.is_valid_for(&peer_id);
+        let _ = serde_json::to_string(&entry);
+    }
+
+    #[test]
+    fn rfc3339_matches_humantime(secs in 0..253_402_300_800u64, nanos in 0..1_000_000_000u32) {
+        let time = Duration::new(secs, nanos);
+        let formatted = format_rfc3339_nanos(time);
+        prop_assert_eq!(&formatted, &humantime::format_rfc3339_nanos(UNIX_EPOCH + time).to_string());
+        prop_assert_eq!(parse_rfc3339(formatted.as_bytes()).unwrap(), time);
+    }
+}
@@ -0,0 +1,94 @@
+//! Runs every record in `tests/vectors/` and checks the verdict listed in `vectors.json`.
+#![cfg(feature = "std")]
+use ipns_entry::entry::{IpnsEntry, PeerId};
+use ipns_entry::entry_ref::IpnsEntryRef;
+use serde_derive::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+struct Vector {
+    file: String,
+    name: String,
+    source: String,
+    expect: Verdict,
+    description: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Verdict {
+    /// Signature and fields check out, and the record has not expired
+    Valid,
+    /// Signature and fields check out, but the validity is in the past
+    Expired,
+    /// Decodes, but does not validate for the name
+    Invalid,
+    /// Rejected before validation, e.g. larger than 10 KiB
+    Undecodable,
+}
+
+fn vectors_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors")
+}
+
+fn verdict(bytes: &[u8], name: &PeerId) -> Verdict {
+    let Ok(entry) = IpnsEntry::from_bytes(bytes) else {
+        return Verdict::Undecodable;
+    };
+
+    // the borrowed and owned paths must agree
+    let entry_ref = IpnsEntryRef::from_bytes(bytes).expect("IpnsEntry decoded");
+    let valid = entry.is_valid_for(name).unwrap_or(false);
+    assert_eq!(valid, entry_ref.is_valid_for(name).unwrap_or(false));
+
+    if !valid {
+        return Verdict::Invalid;
+    }
+
+    if entry.is_expired().expect("RFC3339 validity") {
+        Verdict::Expired
+    } else {
+        Verdict::Valid
+    }
+}
+
+#[test]
+fn test_vectors() {
+    let manifest = std::fs::read_to_string(vectors_dir().join("vectors.json")).unwrap();
+    let vectors: Vec<Vector> = serde_json::from_str(&manifest).unwrap();
+    assert!(!vectors.is_empty());
+
+    let failures: Vec<String> = vectors
+        .iter()
+        .filter_map(|v| {
+            let bytes = std::fs::read(vectors_dir().join(&v.file)).unwrap();
+            let name = PeerId::from_str(&v.name).unwrap();
+            let got = verdict(&bytes, &name);
+            (got != v.expect).then(|| {
+                format!(
+                    "{} ({}, {}): expected {:?}, got {got:?}",
+                    v.file, v.source, v.description, v.expect
+                )
+            })
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_every_vector_is_listed() {
+    let manifest = std::fs::read_to_string(vectors_dir().join("vectors.json")).unwrap();
+    let vectors: Vec<Vector> = serde_json::from_str(&manifest).unwrap();
+
+    for file in std::fs::read_dir(vectors_dir()).unwrap() {
+        let file = file.unwrap().file_name().into_string().unwrap();
+        if file.ends_with(".ipns-record") {
+            assert!(
+                vectors.iter().any(|v| v.file == file),
+                "{file} not in vectors.json"
+            );
+        }
+    }
+}
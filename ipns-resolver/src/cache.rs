@@ -0,0 +1,442 @@
+//! A cache of IPNS Records by IPNS Name, in front of a [Routing].
+//!
+//! A record is fresh for min(IpnsEntry.ttl, time left until IpnsEntry.validity). Once stale it
+//! may still be served for a grace period while it is fetched again in the background
+//! (stale-while-revalidate), but never past its validity. The cache holds at most `capacity`
+//! names, evicting the least recently used.
+//!
+//! [RecordCache] is cheap to clone and shared: the server feeds it records received over
+//! pubsub ([RecordCache::insert]) while resolvers read through a [CachingRouting].
+//!
+//! ```rust
+//! # tokio_test();
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn tokio_test() {
+//! use ipns_resolver::cache::{CachingRouting, RecordCache};
+//! use ipns_resolver::{MemoryRouting, Resolver};
+//! use std::time::Duration;
+//!
+//! let mut cache = RecordCache::new(1024);
+//! cache.stale_while_revalidate(Duration::from_secs(60));
+//!
+//! let resolver = Resolver::new(CachingRouting::new(MemoryRouting::default(), cache.clone()));
+//! # }
+//! ```
+use crate::Routing;
+use async_trait::async_trait;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::time;
+use lru::LruCache;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// How a lookup was served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheOutcome {
+    /// A fresh record
+    Hit,
+    /// A stale record, being revalidated
+    Stale,
+    /// Not cached, or past its grace period
+    Miss,
+}
+
+/// Observes cache lookups, for metrics
+pub trait CacheObserver: Send + Sync {
+    fn lookup(&self, outcome: CacheOutcome);
+
+    /// A name was evicted to stay within capacity
+    fn evicted(&self) {}
+}
+
+struct Entry {
+    record: IpnsEntry,
+    sequence: u64,
+    validity: Duration,
+    /// Fresh until, since the UNIX epoch
+    expires: Duration,
+    revalidating: bool,
+}
+
+/// Validated IPNS Records by IPNS Name, bounded by LRU. Clones share the records and observer.
+#[derive(Clone)]
+pub struct RecordCache {
+    entries: Arc<Mutex<LruCache<IpnsName, Entry>>>,
+    stale_while_revalidate: Duration,
+    observer: Arc<RwLock<Option<Arc<dyn CacheObserver>>>>,
+}
+
+impl fmt::Debug for RecordCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordCache")
+            .field("len", &self.len())
+            .field("stale_while_revalidate", &self.stale_while_revalidate)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RecordCache {
+    /// A cache holding up to `capacity` names, without stale-while-revalidate
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("capacity is not zero");
+        RecordCache {
+            entries: Arc::new(Mutex::new(LruCache::new(capacity))),
+            stale_while_revalidate: Duration::ZERO,
+            observer: Arc::default(),
+        }
+    }
+
+    /// Serve stale records for up to `grace` past their TTL while fetching them again
+    pub fn stale_while_revalidate(&mut self, grace: Duration) -> &mut Self {
+        self.stale_while_revalidate = grace;
+        self
+    }
+
+    /// Report lookups to `observer`, from this cache and all its clones
+    pub fn set_observer(&self, observer: Arc<dyn CacheObserver>) {
+        *self.observer.write().expect("not poisoned") = Some(observer);
+    }
+
+    fn observe(&self, f: impl FnOnce(&dyn CacheObserver)) {
+        if let Some(observer) = self.observer.read().expect("not poisoned").as_deref() {
+            f(observer);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cache `record` for `name` if it is valid and newer than the cached one,
+    /// returning whether it was cached
+    pub fn insert(&self, name: IpnsName, record: IpnsEntry) -> anyhow::Result<bool> {
+        self.insert_at(name, record, time::now())
+    }
+
+    /// [RecordCache::insert] at `now`, since the UNIX epoch
+    pub fn insert_at(
+        &self,
+        name: IpnsName,
+        record: IpnsEntry,
+        now: Duration,
+    ) -> anyhow::Result<bool> {
+        if !record.is_valid_for(&name.peer_id())? {
+            return Err(anyhow::anyhow!("Invalid IpnsEntry.signatureV2 for {name}"));
+        }
+        if record.is_expired_at(now)? {
+            return Ok(false);
+        }
+
+        let data = record.decode_data()?;
+        let validity = time::parse_rfc3339(&data.validity)?;
+        let ttl = Duration::from_nanos(data.ttl).min(validity.saturating_sub(now));
+
+        let mut entries = self.lock();
+        if let Some(cached) = entries.get(&name) {
+            // the same record, or a newer one, is already cached
+            if (cached.sequence, cached.validity) > (data.sequence, validity)
+                || (cached.record == record && cached.expires >= now + ttl)
+            {
+                return Ok(false);
+            }
+        }
+
+        let entry = Entry {
+            record,
+            sequence: data.sequence,
+            validity,
+            expires: now + ttl,
+            revalidating: false,
+        };
+        if let Some((evicted, _)) = entries.push(name, entry) {
+            if evicted != name {
+                self.observe(|observer| observer.evicted());
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The cached record of `name` while it is valid, fresh or not, without fetching it
+    pub fn get(&self, name: &IpnsName) -> Option<IpnsEntry> {
+        self.get_at(name, time::now())
+    }
+
+    /// [RecordCache::get] at `now`, since the UNIX epoch
+    pub fn get_at(&self, name: &IpnsName, now: Duration) -> Option<IpnsEntry> {
+        self.lock()
+            .peek(name)
+            .filter(|entry| now < entry.validity)
+            .map(|entry| entry.record.clone())
+    }
+
+    /// Drop the cached record of `name` if its sequence is lower than `sequence`,
+    /// returning whether it was dropped. For when a newer sequence is announced.
+    pub fn invalidate_older(&self, name: &IpnsName, sequence: u64) -> bool {
+        let mut entries = self.lock();
+        match entries.peek(name) {
+            Some(cached) if cached.sequence < sequence => entries.pop(name).is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn invalidate(&self, name: &IpnsName) -> bool {
+        self.lock().pop(name).is_some()
+    }
+
+    /// The cached record of `name` at `now`, and whether this lookup should revalidate it.
+    /// Records past their grace period are dropped.
+    fn lookup_at(&self, name: &IpnsName, now: Duration) -> (CacheOutcome, Option<IpnsEntry>, bool) {
+        let mut entries = self.lock();
+        let lookup = match entries.get_mut(name) {
+            Some(entry) if now < entry.expires => {
+                (CacheOutcome::Hit, Some(entry.record.clone()), false)
+            }
+            Some(entry)
+                if now < (entry.expires + self.stale_while_revalidate).min(entry.validity) =>
+            {
+                // only the first stale lookup revalidates
+                let revalidate = !entry.revalidating;
+                entry.revalidating = true;
+                (CacheOutcome::Stale, Some(entry.record.clone()), revalidate)
+            }
+            Some(_) => {
+                entries.pop(name);
+                (CacheOutcome::Miss, None, false)
+            }
+            None => (CacheOutcome::Miss, None, false),
+        };
+        drop(entries);
+
+        self.observe(|observer| observer.lookup(lookup.0));
+
+        lookup
+    }
+
+    /// A revalidation failed, let the next stale lookup try again
+    fn revalidation_failed(&self, name: &IpnsName) {
+        if let Some(entry) = self.lock().peek_mut(name) {
+            entry.revalidating = false;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<IpnsName, Entry>> {
+        self.entries.lock().expect("not poisoned")
+    }
+}
+
+/// [Routing] through a [RecordCache]: fresh records are served from the cache, stale ones
+/// are served while a background task (on the tokio runtime) fetches them again.
+#[derive(Debug)]
+pub struct CachingRouting<R> {
+    routing: Arc<R>,
+    cache: RecordCache,
+}
+
+impl<R: Routing + 'static> CachingRouting<R> {
+    pub fn new(routing: R, cache: RecordCache) -> Self {
+        CachingRouting {
+            routing: Arc::new(routing),
+            cache,
+        }
+    }
+
+    pub fn cache(&self) -> &RecordCache {
+        &self.cache
+    }
+
+    pub fn routing(&self) -> &R {
+        &self.routing
+    }
+
+    /// [Routing::get_record] at `now`, since the UNIX epoch
+    pub async fn get_record_at(
+        &self,
+        name: &IpnsName,
+        now: Duration,
+    ) -> anyhow::Result<Option<IpnsEntry>> {
+        match self.cache.lookup_at(name, now) {
+            (_, Some(record), false) => Ok(Some(record)),
+            (_, Some(record), true) => {
+                let (routing, cache, name) = (self.routing.clone(), self.cache.clone(), *name);
+                tokio::spawn(async move {
+                    let revalidated = match routing.get_record(&name).await {
+                        Ok(Some(record)) => matches!(cache.insert_at(name, record, now), Ok(true)),
+                        _ => false,
+                    };
+                    if !revalidated {
+                        cache.revalidation_failed(&name);
+                    }
+                });
+                Ok(Some(record))
+            }
+            (_, None, _) => {
+                let record = self.routing.get_record(name).await?;
+                if let Some(record) = &record {
+                    // invalid records are not cached, the resolver reports them
+                    let _ = self.cache.insert_at(*name, record.clone(), now);
+                }
+                Ok(record)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<R: Routing + 'static> Routing for CachingRouting<R> {
+    async fn get_record(&self, name: &IpnsName) -> anyhow::Result<Option<IpnsEntry>> {
+        self.get_record_at(name, time::now()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryRouting;
+    use ipns_entry::signer::{Keypair, Signer};
+    use ipns_entry::DataBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const NOW: Duration = Duration::from_secs(1_700_000_000);
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn record(keypair: &Keypair, sequence: u64, ttl: Duration, validity: Duration) -> IpnsEntry {
+        let (data, signables) = DataBuilder::new_at(&format!("/ipfs/bafkqaaa/{sequence}"), NOW)
+            .validity_at(validity)
+            .sequence(sequence)
+            .ttl(ttl.as_nanos() as u64)
+            .build();
+        IpnsEntry::new(data, Signer::new(keypair.clone()).sign(signables).unwrap())
+    }
+
+    fn key() -> (Keypair, IpnsName) {
+        let keypair = Keypair::generate_ed25519();
+        let name = IpnsName::from(keypair.public().to_peer_id());
+        (keypair, name)
+    }
+
+    #[derive(Default)]
+    struct Counts([AtomicUsize; 4]);
+
+    impl Counts {
+        fn get(&self) -> [usize; 4] {
+            self.0.each_ref().map(|count| count.load(Ordering::SeqCst))
+        }
+    }
+
+    impl CacheObserver for Counts {
+        fn lookup(&self, outcome: CacheOutcome) {
+            self.0[outcome as usize].fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn evicted(&self) {
+            self.0[3].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lifetime_is_min_ttl_validity() {
+        let (keypair, name) = key();
+        let counts = Arc::new(Counts::default());
+        let cache = RecordCache::new(8);
+        cache.set_observer(counts.clone());
+
+        let routing = MemoryRouting::default();
+        routing.put(name, record(&keypair, 1, 10 * MINUTE, NOW + 5 * MINUTE));
+        let caching = CachingRouting::new(routing, cache.clone());
+
+        let get = |now| caching.get_record_at(&name, now);
+        assert!(get(NOW).await.unwrap().is_some());
+        // served from the cache even once gone from routing
+        caching.routing().remove(&name);
+        assert!(get(NOW + 4 * MINUTE).await.unwrap().is_some());
+        // the validity ends before the TTL
+        assert!(get(NOW + 5 * MINUTE).await.unwrap().is_none());
+        assert!(cache.is_empty());
+
+        // miss, hit, miss
+        assert_eq!(counts.get(), [1, 0, 2, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_stale_while_revalidate() {
+        let (keypair, name) = key();
+        let mut cache = RecordCache::new(8);
+        cache.stale_while_revalidate(MINUTE);
+
+        let routing = MemoryRouting::default();
+        routing.put(name, record(&keypair, 1, MINUTE, NOW + 60 * MINUTE));
+        let caching = CachingRouting::new(routing, cache.clone());
+        let sequence = |record: Option<IpnsEntry>| record.unwrap().decode_data().unwrap().sequence;
+
+        assert_eq!(
+            sequence(caching.get_record_at(&name, NOW).await.unwrap()),
+            1
+        );
+
+        // stale: the old record is served, and fetched again in the background
+        let newer = record(&keypair, 2, MINUTE, NOW + 60 * MINUTE);
+        caching.routing().put(name, newer);
+        let stale = caching.get_record_at(&name, NOW + MINUTE).await.unwrap();
+        assert_eq!(sequence(stale), 1);
+        tokio::task::yield_now().await;
+
+        let revalidated = caching.get_record_at(&name, NOW + MINUTE).await.unwrap();
+        assert_eq!(sequence(revalidated), 2);
+    }
+
+    #[test]
+    fn test_newer_sequences_replace_and_invalidate() {
+        let (keypair, name) = key();
+        let cache = RecordCache::new(8);
+        let validity = NOW + 60 * MINUTE;
+
+        assert!(cache
+            .insert_at(name, record(&keypair, 2, MINUTE, validity), NOW)
+            .unwrap());
+        // older sequences are ignored
+        assert!(!cache
+            .insert_at(name, record(&keypair, 1, MINUTE, validity), NOW)
+            .unwrap());
+        assert!(cache
+            .insert_at(name, record(&keypair, 3, MINUTE, validity), NOW)
+            .unwrap());
+        // records signed by another key are rejected
+        let (other, _) = key();
+        assert!(cache
+            .insert_at(name, record(&other, 4, MINUTE, validity), NOW)
+            .is_err());
+
+        let cached = cache.get_at(&name, NOW).unwrap();
+        assert_eq!(cached.decode_data().unwrap().sequence, 3);
+        assert!(cache.get_at(&name, validity).is_none());
+
+        assert!(!cache.invalidate_older(&name, 3));
+        assert!(cache.invalidate_older(&name, 4));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_bound() {
+        let counts = Arc::new(Counts::default());
+        let cache = RecordCache::new(2);
+        cache.set_observer(counts.clone());
+
+        let keys: Vec<_> = (0..3).map(|_| key()).collect();
+        for (keypair, name) in &keys {
+            let record = record(keypair, 1, MINUTE, NOW + MINUTE);
+            cache.insert_at(*name, record, NOW).unwrap();
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.invalidate(&keys[0].1));
+        assert_eq!(counts.get()[3], 1);
+    }
+}
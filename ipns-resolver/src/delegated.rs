@@ -0,0 +1,196 @@
+//! [Delegated Routing V1 HTTP API](https://specs.ipfs.tech/routing/http-routing-v1/) client,
+//! for nodes which cannot run a DHT node: `GET` and `PUT /routing/v1/ipns/{name}`.
+//!
+//! Every record received is validated for its IPNS Name before it is returned, and every
+//! record is validated before it is sent.
+//!
+//! ```rust,no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use ipns_resolver::delegated::DelegatedRouting;
+//! use ipns_resolver::Resolver;
+//!
+//! let routing = DelegatedRouting::new("https://delegated-ipfs.dev")?;
+//! let resolved = Resolver::new(routing).resolve("/ipns/k51.../index.html").await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::Routing;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ipns_entry::entry::{IpnsEntry, MAX_RECORD_SIZE};
+use ipns_entry::name::IpnsName;
+use ipns_entry::time;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{Client, StatusCode};
+use std::time::Duration;
+
+/// The media type of a protobuf IPNS Record
+pub const IPNS_RECORD_CONTENT_TYPE: &str = "application/vnd.ipfs.ipns-record";
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// IPNS Records over a Delegated Routing V1 HTTP endpoint
+#[derive(Debug, Clone)]
+pub struct DelegatedRouting {
+    client: Client,
+    endpoint: String,
+}
+
+impl DelegatedRouting {
+    /// The endpoint is the base URL, such as `https://delegated-ipfs.dev`, without `/routing/v1`
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .user_agent(concat!("ipns-resolver/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Self::with_client(endpoint, client)
+    }
+
+    /// Use a configured [Client], for its timeouts, proxies or TLS roots
+    pub fn with_client(endpoint: &str, client: Client) -> Result<Self> {
+        let url = reqwest::Url::parse(endpoint)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!(
+                "Delegated routing endpoint must be http(s): {endpoint}"
+            ));
+        }
+
+        Ok(DelegatedRouting {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn url(&self, name: &IpnsName) -> String {
+        format!("{}/routing/v1/ipns/{}", self.endpoint, name.to_base36())
+    }
+
+    /// The record of `name`, `None` if the endpoint has none.
+    /// Records which do not validate for `name`, or have expired, are errors.
+    pub async fn get(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        let mut response = self
+            .client
+            .get(self.url(name))
+            .header(ACCEPT, IPNS_RECORD_CONTENT_TYPE)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(anyhow!("Delegated routing GET {name} failed: {status}")),
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !is_ipns_record(content_type) {
+            return Err(anyhow!(
+                "Delegated routing returned {content_type:?}, not {IPNS_RECORD_CONTENT_TYPE}"
+            ));
+        }
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_RECORD_SIZE as u64)
+        {
+            return Err(anyhow!("Delegated routing returned a record over 10 KiB"));
+        }
+
+        // without a Content-Length, stop reading once the body is too large
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > MAX_RECORD_SIZE {
+                return Err(anyhow!("Delegated routing returned a record over 10 KiB"));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let record = IpnsEntry::from_bytes(&body)?;
+        validate(name, &record)?;
+
+        Ok(Some(record))
+    }
+
+    /// Publish `record` for `name`, after validating it
+    pub async fn put(&self, name: &IpnsName, record: &IpnsEntry) -> Result<()> {
+        let bytes = record.try_to_bytes()?;
+        validate(name, record)?;
+
+        let response = self
+            .client
+            .put(self.url(name))
+            .header(CONTENT_TYPE, IPNS_RECORD_CONTENT_TYPE)
+            .body(bytes)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "Delegated routing PUT {name} failed: {status} {}",
+                body.trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Routing for DelegatedRouting {
+    async fn get_record(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        self.get(name).await
+    }
+}
+
+/// `application/vnd.ipfs.ipns-record`, with or without parameters
+fn is_ipns_record(content_type: &str) -> bool {
+    content_type.split(';').next().is_some_and(|media_type| {
+        media_type
+            .trim()
+            .eq_ignore_ascii_case(IPNS_RECORD_CONTENT_TYPE)
+    })
+}
+
+fn validate(name: &IpnsName, record: &IpnsEntry) -> Result<()> {
+    if !record.is_valid_for(&name.peer_id())? {
+        return Err(anyhow!("IPNS Record does not validate for {name}"));
+    }
+    if record.is_expired_at(time::now())? {
+        return Err(anyhow!("IPNS Record for {name} has expired"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ipns_record() {
+        assert!(is_ipns_record("application/vnd.ipfs.ipns-record"));
+        assert!(is_ipns_record(
+            "Application/Vnd.Ipfs.Ipns-Record; charset=binary"
+        ));
+        assert!(!is_ipns_record("application/octet-stream"));
+        assert!(!is_ipns_record(""));
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let name: IpnsName = "k51qzi5uqu5dlvj2baxnqndepeb86cbk3ng7n3i46uzyxzyqj2xjonzllnv0v8"
+            .parse()
+            .unwrap();
+        let routing = DelegatedRouting::new("http://127.0.0.1:8080/").unwrap();
+        assert_eq!(
+            routing.url(&name),
+            format!("http://127.0.0.1:8080/routing/v1/ipns/{name}")
+        );
+
+        assert!(DelegatedRouting::new("ftp://example.com").is_err());
+        assert!(DelegatedRouting::new("not a url").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! [DNSLink](https://dnslink.dev/): `/ipns/example.com` resolves through the
+//! `dnslink=/ipfs/...` TXT record of `_dnslink.example.com`.
+//!
+//! TXT records are fetched through a [TxtResolver]. With the default `dns` feature,
+//! [DnsTxtResolver] queries the system resolvers or configured upstream servers.
+//!
+//! ```rust,no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use ipns_resolver::dnslink::{DnsLink, DnsTxtResolver};
+//! use ipns_resolver::{MemoryRouting, Resolver};
+//!
+//! let upstream = ["1.1.1.1:53".parse()?];
+//! let mut resolver = Resolver::new(MemoryRouting::default());
+//! resolver.dnslink(DnsLink::new(DnsTxtResolver::with_upstream(&upstream)?));
+//!
+//! let resolved = resolver.resolve("/ipns/docs.ipfs.tech/index.html").await?;
+//! # Ok(())
+//! # }
+//! ```
+use async_trait::async_trait;
+use std::time::Duration;
+
+const DNSLINK_PREFIX: &str = "dnslink=";
+
+/// The TXT records of a domain
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TxtLookup {
+    /// One string per record, with the character-strings of each record joined
+    pub records: Vec<String>,
+    /// How long the records may be cached
+    pub ttl: Duration,
+}
+
+/// Looks up TXT records
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// The TXT records of the fully qualified `name`, empty if it has none or does not exist
+    async fn lookup_txt(&self, name: &str) -> anyhow::Result<TxtLookup>;
+}
+
+/// A DNSLink found for a domain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsLinkRecord {
+    /// The linked path, `/ipfs/...` or `/ipns/...`
+    pub path: String,
+    pub ttl: Duration,
+}
+
+/// DNSLink lookups over a [TxtResolver]
+pub struct DnsLink {
+    txt: Box<dyn TxtResolver>,
+}
+
+impl std::fmt::Debug for DnsLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DnsLink").finish_non_exhaustive()
+    }
+}
+
+impl DnsLink {
+    pub fn new(txt: impl TxtResolver + 'static) -> Self {
+        DnsLink { txt: Box::new(txt) }
+    }
+
+    /// The DNSLink of `domain`, from `_dnslink.<domain>`, falling back to the TXT records of
+    /// `domain` itself. With several valid `dnslink=` records the lexicographically first is used,
+    /// so every resolver picks the same one.
+    pub async fn lookup(&self, domain: &str) -> anyhow::Result<Option<DnsLinkRecord>> {
+        let domain = domain.trim_end_matches('.');
+
+        for name in [format!("_dnslink.{domain}."), format!("{domain}.")] {
+            let lookup = self.txt.lookup_txt(&name).await?;
+            if let Some(path) = dnslink_path(&lookup.records) {
+                return Ok(Some(DnsLinkRecord {
+                    path,
+                    ttl: lookup.ttl,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The first valid `dnslink=` path, in lexicographic order
+fn dnslink_path(records: &[String]) -> Option<String> {
+    records
+        .iter()
+        .filter_map(|record| record.trim().strip_prefix(DNSLINK_PREFIX))
+        .map(str::trim)
+        .filter(|path| is_content_path(path))
+        .min()
+        .map(str::to_string)
+}
+
+/// `/<namespace>/<root>...`
+fn is_content_path(path: &str) -> bool {
+    let mut segments = path.split('/');
+    segments.next() == Some("")
+        && segments
+            .next()
+            .is_some_and(|namespace| !namespace.is_empty())
+        && segments.next().is_some_and(|root| !root.is_empty())
+}
+
+/// Whether an `/ipns/` root looks like a domain name rather than a key
+pub fn is_domain(root: &str) -> bool {
+    let root = root.trim_end_matches('.');
+    root.contains('.')
+        && root.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+}
+
+#[cfg(feature = "dns")]
+pub use dns::DnsTxtResolver;
+
+#[cfg(feature = "dns")]
+mod dns {
+    use super::{TxtLookup, TxtResolver};
+    use async_trait::async_trait;
+    use std::net::SocketAddr;
+    use std::time::Instant;
+    use trust_dns_resolver::config::{
+        NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+    };
+    use trust_dns_resolver::error::ResolveErrorKind;
+    use trust_dns_resolver::TokioAsyncResolver;
+
+    /// [TxtResolver] over DNS, with the tokio runtime
+    #[derive(Clone)]
+    pub struct DnsTxtResolver {
+        resolver: TokioAsyncResolver,
+    }
+
+    impl DnsTxtResolver {
+        /// Use the system resolvers (`/etc/resolv.conf` on Unix)
+        pub fn system() -> anyhow::Result<Self> {
+            Ok(DnsTxtResolver {
+                resolver: TokioAsyncResolver::tokio_from_system_conf()?,
+            })
+        }
+
+        /// Query `upstream` name servers over UDP, falling back to TCP for large answers
+        pub fn with_upstream(upstream: &[SocketAddr]) -> anyhow::Result<Self> {
+            let servers: Vec<NameServerConfig> = upstream
+                .iter()
+                .flat_map(|addr| {
+                    [
+                        NameServerConfig::new(*addr, Protocol::Udp),
+                        NameServerConfig::new(*addr, Protocol::Tcp),
+                    ]
+                })
+                .collect();
+            let config =
+                ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from(servers));
+
+            Ok(DnsTxtResolver {
+                resolver: TokioAsyncResolver::tokio(config, ResolverOpts::default())?,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl TxtResolver for DnsTxtResolver {
+        async fn lookup_txt(&self, name: &str) -> anyhow::Result<TxtLookup> {
+            match self.resolver.txt_lookup(name).await {
+                Ok(lookup) => Ok(TxtLookup {
+                    ttl: lookup
+                        .valid_until()
+                        .saturating_duration_since(Instant::now()),
+                    records: lookup
+                        .iter()
+                        .map(|txt| {
+                            txt.txt_data()
+                                .iter()
+                                .map(|s| String::from_utf8_lossy(s))
+                                .collect()
+                        })
+                        .collect(),
+                }),
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                    Ok(TxtLookup::default())
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// TXT records by name
+    struct StaticTxt(HashMap<&'static str, Vec<&'static str>>);
+
+    #[async_trait]
+    impl TxtResolver for StaticTxt {
+        async fn lookup_txt(&self, name: &str) -> anyhow::Result<TxtLookup> {
+            Ok(TxtLookup {
+                records: self
+                    .0
+                    .get(name)
+                    .map(|r| r.iter().map(|s| s.to_string()).collect())
+                    .unwrap_or_default(),
+                ttl: Duration::from_secs(60),
+            })
+        }
+    }
+
+    fn dnslink(records: &[(&'static str, Vec<&'static str>)]) -> DnsLink {
+        DnsLink::new(StaticTxt(records.iter().cloned().collect()))
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let dnslink = dnslink(&[
+            (
+                "_dnslink.example.com.",
+                vec!["v=spf1 -all", "dnslink=/ipfs/bafkqaaa"],
+            ),
+            // only used as a fallback
+            ("example.com.", vec!["dnslink=/ipfs/bafkqaab"]),
+            ("legacy.example.com.", vec!["dnslink=/ipns/k51legacy"]),
+            (
+                "_dnslink.multi.example.com.",
+                vec![
+                    "dnslink=/ipns/k51b",
+                    "dnslink=not-a-path",
+                    "dnslink=/ipfs/bafkqaab",
+                    "dnslink=/ipfs/bafkqaaa",
+                ],
+            ),
+        ]);
+
+        let path = |domain: &'static str| {
+            let dnslink = &dnslink;
+            async move { dnslink.lookup(domain).await.unwrap().map(|r| r.path) }
+        };
+
+        assert_eq!(path("example.com").await.as_deref(), Some("/ipfs/bafkqaaa"));
+        assert_eq!(
+            path("example.com.").await.as_deref(),
+            Some("/ipfs/bafkqaaa")
+        );
+        assert_eq!(
+            path("legacy.example.com").await.as_deref(),
+            Some("/ipns/k51legacy")
+        );
+        assert_eq!(
+            path("multi.example.com").await.as_deref(),
+            Some("/ipfs/bafkqaaa")
+        );
+        assert_eq!(path("none.example.com").await, None);
+    }
+
+    #[test]
+    fn test_is_domain() {
+        assert!(is_domain("example.com"));
+        assert!(is_domain("docs.ipfs.tech."));
+        assert!(is_domain("my-site.example"));
+        assert!(!is_domain(
+            "k51qzi5uqu5dlvj2baxnqndepeb86cbk3ng7n3i46uzyxzyqj2xjonzllnv0v8"
+        ));
+        assert!(!is_domain("example..com"));
+        assert!(!is_domain("exa mple.com"));
+    }
+}
@@ -0,0 +1,457 @@
+//! # IPNS Resolver
+//!
+//! Resolve `/ipns/` paths to `/ipfs/` paths, following records which point at other
+//! IPNS Names, as in the [IPFS path resolution spec](https://specs.ipfs.tech/ipns/ipns-record/#record-value).
+//!
+//! Records are fetched through a [Routing] implementation (Kademlia, pubsub, delegated routing
+//! with the `http` feature, a local store, or [MemoryRouting] in tests) and validated before
+//! they are followed.
+//! Domain names (`/ipns/example.com`) are resolved with [DNSLink](dnslink) when configured.
+//! Put a [cache::CachingRouting] in front of the routing to avoid fetching records on every resolve.
+//! Path remainders are carried along the chain, cycles are detected, and chains longer
+//! than [DEFAULT_MAX_DEPTH] are refused.
+//!
+//! # Example
+//!
+//! ```rust
+//! # tokio_test();
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn tokio_test() {
+//! use ipns_entry::entry::IpnsEntry;
+//! use ipns_entry::name::IpnsName;
+//! use ipns_entry::signer::{Keypair, Signer};
+//! use ipns_entry::DataBuilder;
+//! use ipns_resolver::{MemoryRouting, Resolver};
+//!
+//! let keypair = Keypair::generate_ed25519();
+//! let name = IpnsName::from(keypair.public().to_peer_id());
+//! let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+//! let record = IpnsEntry::new(data, Signer::new(keypair).sign(signables).unwrap());
+//!
+//! let routing = MemoryRouting::default();
+//! routing.put(name, record);
+//!
+//! let resolved = Resolver::new(routing)
+//!     .resolve(&format!("/ipns/{name}/index.html"))
+//!     .await
+//!     .unwrap();
+//! assert_eq!(resolved.path, "/ipfs/bafkqaaa/index.html");
+//! # }
+//! ```
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "http")]
+pub mod delegated;
+pub mod dnslink;
+mod memory;
+
+pub use memory::MemoryRouting;
+
+use async_trait::async_trait;
+use dnslink::DnsLink;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::time;
+use std::fmt;
+use std::time::Duration;
+
+/// The maximum number of IPNS records followed in one resolution, as in kubo
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Fetches IPNS Records. Implementations only fetch, the [Resolver] validates.
+#[async_trait]
+pub trait Routing: Send + Sync {
+    /// The best record known for `name`, or `None` if there is none
+    async fn get_record(&self, name: &IpnsName) -> anyhow::Result<Option<IpnsEntry>>;
+}
+
+#[async_trait]
+impl<R: Routing + ?Sized> Routing for std::sync::Arc<R> {
+    async fn get_record(&self, name: &IpnsName) -> anyhow::Result<Option<IpnsEntry>> {
+        (**self).get_record(name).await
+    }
+}
+
+/// One step of a resolution, the root of an `/ipns/` path
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Hop {
+    Ipns(IpnsName),
+    /// A domain, resolved with DNSLink
+    DnsLink(String),
+}
+
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hop::Ipns(name) => write!(f, "/ipns/{name}"),
+            Hop::DnsLink(domain) => write!(f, "/ipns/{domain}"),
+        }
+    }
+}
+
+/// The end of a resolution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    /// The final `/ipfs/` path, with the remainders of every hop appended
+    pub path: String,
+    /// The IPNS Names and DNSLink domains followed, in order
+    pub chain: Vec<Hop>,
+    /// The smallest IpnsEntry.ttl (nanoseconds, per the spec) or DNS TTL along the chain,
+    /// `None` for an `/ipfs/` path
+    pub ttl: Option<Duration>,
+    /// The earliest IpnsEntry.validity along the chain, since the UNIX epoch.
+    /// `None` if no IPNS Record was followed.
+    pub validity: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    /// Not an `/ipfs/` or `/ipns/` path
+    InvalidPath(String),
+    /// The root of an `/ipns/` path is not an IPNS Name, nor a domain with DNSLink configured
+    InvalidName(String),
+    NotFound(IpnsName),
+    /// The record failed validation
+    InvalidRecord(IpnsName, anyhow::Error),
+    Expired(IpnsName),
+    /// The domain has no `dnslink=` TXT record
+    NoDnsLink(String),
+    /// The DNS lookup failed
+    Dns(String, anyhow::Error),
+    /// The chain came back to a name it had already followed
+    Cycle(Hop),
+    /// The chain is longer than the maximum depth
+    DepthExceeded(usize),
+    /// Fetching the record failed
+    Routing(IpnsName, anyhow::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::InvalidPath(path) => write!(f, "Invalid path {path}"),
+            ResolveError::InvalidName(name) => write!(f, "Invalid IPNS Name {name}"),
+            ResolveError::NotFound(name) => write!(f, "No IPNS Record found for {name}"),
+            ResolveError::InvalidRecord(name, e) => {
+                write!(f, "Invalid IPNS Record for {name}: {e}")
+            }
+            ResolveError::Expired(name) => write!(f, "IPNS Record for {name} has expired"),
+            ResolveError::NoDnsLink(domain) => write!(f, "No DNSLink found for {domain}"),
+            ResolveError::Dns(domain, e) => write!(f, "DNSLink lookup of {domain} failed: {e}"),
+            ResolveError::Cycle(hop) => write!(f, "IPNS resolution cycle at {hop}"),
+            ResolveError::DepthExceeded(depth) => {
+                write!(f, "IPNS resolution exceeded the maximum depth of {depth}")
+            }
+            ResolveError::Routing(name, e) => {
+                write!(f, "Fetching the IPNS Record of {name} failed: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Resolves paths through a [Routing]
+#[derive(Debug)]
+pub struct Resolver<R> {
+    routing: R,
+    dnslink: Option<DnsLink>,
+    max_depth: usize,
+}
+
+impl<R: Routing> Resolver<R> {
+    pub fn new(routing: R) -> Self {
+        Resolver {
+            routing,
+            dnslink: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Resolve domain names with DNSLink. Without it, `/ipns/<domain>` is an [ResolveError::InvalidName].
+    pub fn dnslink(&mut self, dnslink: DnsLink) -> &mut Self {
+        self.dnslink = Some(dnslink);
+        self
+    }
+
+    /// The maximum number of IPNS records and DNSLinks followed, [DEFAULT_MAX_DEPTH] by default
+    pub fn max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn routing(&self) -> &R {
+        &self.routing
+    }
+
+    /// Resolve `path` (`/ipns/<name>/...`, `/ipfs/<cid>/...` or a bare IPNS Name) to an `/ipfs/` path
+    pub async fn resolve(&self, path: &str) -> Result<Resolved, ResolveError> {
+        self.resolve_at(path, time::now()).await
+    }
+
+    /// Like [Resolver::resolve], checking record expiry against `now`, a time since the UNIX epoch
+    pub async fn resolve_at(&self, path: &str, now: Duration) -> Result<Resolved, ResolveError> {
+        let mut path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            format!("/ipns/{path}")
+        };
+        let mut resolved = Resolved {
+            path: String::new(),
+            chain: Vec::new(),
+            ttl: None,
+            validity: None,
+        };
+
+        loop {
+            let (namespace, root, remainder) = split_path(&path)?;
+            match namespace {
+                "ipfs" => {
+                    resolved.path = path;
+                    return Ok(resolved);
+                }
+                "ipns" => {}
+                _ => return Err(ResolveError::InvalidPath(path)),
+            }
+
+            let hop = match root.parse::<IpnsName>() {
+                Ok(name) => Hop::Ipns(name),
+                Err(_) if self.dnslink.is_some() && dnslink::is_domain(root) => {
+                    Hop::DnsLink(root.trim_end_matches('.').to_ascii_lowercase())
+                }
+                Err(_) => return Err(ResolveError::InvalidName(root.to_string())),
+            };
+            if resolved.chain.contains(&hop) {
+                return Err(ResolveError::Cycle(hop));
+            }
+            if resolved.chain.len() >= self.max_depth {
+                return Err(ResolveError::DepthExceeded(self.max_depth));
+            }
+            resolved.chain.push(hop.clone());
+
+            let (value, ttl) = match hop {
+                Hop::Ipns(name) => {
+                    let (value, ttl, validity) = self.fetch(&name, now).await?;
+                    resolved.validity =
+                        Some(resolved.validity.map_or(validity, |v| v.min(validity)));
+                    (value, ttl)
+                }
+                Hop::DnsLink(domain) => self.lookup_dnslink(&domain).await?,
+            };
+            resolved.ttl = Some(resolved.ttl.map_or(ttl, |min| min.min(ttl)));
+
+            path = join_path(&value, remainder);
+        }
+    }
+
+    /// Fetch and validate the record of `name`, returning its value, TTL and validity
+    async fn fetch(
+        &self,
+        name: &IpnsName,
+        now: Duration,
+    ) -> Result<(String, Duration, Duration), ResolveError> {
+        let record = self
+            .routing
+            .get_record(name)
+            .await
+            .map_err(|e| ResolveError::Routing(*name, e))?
+            .ok_or(ResolveError::NotFound(*name))?;
+
+        let invalid = |e| ResolveError::InvalidRecord(*name, e);
+        if !record.is_valid_for(&name.peer_id()).map_err(invalid)? {
+            return Err(invalid(anyhow::anyhow!("Invalid IpnsEntry.signatureV2")));
+        }
+        if record.is_expired_at(now).map_err(invalid)? {
+            return Err(ResolveError::Expired(*name));
+        }
+
+        let data = record.decode_data().map_err(invalid)?;
+        let validity = time::parse_rfc3339(&data.validity).map_err(invalid)?;
+        let value = String::from_utf8(data.value)
+            .map_err(|_| invalid(anyhow::anyhow!("IpnsEntry.value is not UTF-8")))?;
+
+        Ok((value, Duration::from_nanos(data.ttl), validity))
+    }
+
+    /// The DNSLink path of `domain` and its DNS TTL
+    async fn lookup_dnslink(&self, domain: &str) -> Result<(String, Duration), ResolveError> {
+        let dnslink = self.dnslink.as_ref().expect("only domains with DNSLink");
+        let record = dnslink
+            .lookup(domain)
+            .await
+            .map_err(|e| ResolveError::Dns(domain.to_string(), e))?
+            .ok_or_else(|| ResolveError::NoDnsLink(domain.to_string()))?;
+
+        Ok((record.path, record.ttl))
+    }
+}
+
+/// Split `/<namespace>/<root><remainder>`, the remainder keeping its leading `/`
+fn split_path(path: &str) -> Result<(&str, &str, &str), ResolveError> {
+    let invalid = || ResolveError::InvalidPath(path.to_string());
+
+    let rest = path.strip_prefix('/').ok_or_else(invalid)?;
+    let (namespace, rest) = rest.split_once('/').ok_or_else(invalid)?;
+    let (root, remainder) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    if root.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok((namespace, root, remainder))
+}
+
+/// Append a remainder to a record value. Legacy values holding a bare CID are `/ipfs/` paths.
+fn join_path(value: &str, remainder: &str) -> String {
+    let value = value.trim_end_matches('/');
+    if value.starts_with('/') {
+        format!("{value}{remainder}")
+    } else {
+        format!("/ipfs/{value}{remainder}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipns_entry::signer::{Keypair, Signer};
+    use ipns_entry::DataBuilder;
+
+    const NOW: Duration = Duration::from_secs(1_700_000_000);
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    /// A key and its IPNS Name
+    fn key() -> (Keypair, IpnsName) {
+        let keypair = Keypair::generate_ed25519();
+        let name = IpnsName::from(keypair.public().to_peer_id());
+        (keypair, name)
+    }
+
+    fn record(keypair: &Keypair, value: &str, ttl: Duration, validity: Duration) -> IpnsEntry {
+        let (data, signables) = DataBuilder::new_at(value, NOW)
+            .validity_at(validity)
+            .ttl(ttl.as_nanos() as u64)
+            .build();
+        IpnsEntry::new(data, Signer::new(keypair.clone()).sign(signables).unwrap())
+    }
+
+    /// Publish `value` under a new key, returning its name
+    fn publish(routing: &MemoryRouting, value: &str, ttl: Duration) -> IpnsName {
+        let (keypair, name) = key();
+        routing.put(name, record(&keypair, value, ttl, NOW + HOUR));
+        name
+    }
+
+    #[tokio::test]
+    async fn test_resolves_ipfs_paths_as_is() {
+        let resolver = Resolver::new(MemoryRouting::default());
+        let resolved = resolver.resolve_at("/ipfs/bafkqaaa/a", NOW).await.unwrap();
+        assert_eq!(resolved.path, "/ipfs/bafkqaaa/a");
+        assert!(resolved.chain.is_empty());
+        assert_eq!(resolved.ttl, None);
+    }
+
+    #[tokio::test]
+    async fn test_follows_chains_with_remainders_and_min_ttl() {
+        let routing = MemoryRouting::default();
+        let last = publish(&routing, "/ipfs/bafkqaaa/docs", 5 * HOUR);
+        let middle = publish(&routing, &format!("/ipns/{last}/v1"), HOUR);
+        let first = publish(&routing, &format!("/ipns/{}", middle.to_base58()), 2 * HOUR);
+
+        let resolver = Resolver::new(routing);
+        let resolved = resolver
+            .resolve_at(&format!("/ipns/{first}/index.html"), NOW)
+            .await
+            .unwrap();
+
+        assert_eq!(resolved.path, "/ipfs/bafkqaaa/docs/v1/index.html");
+        assert_eq!(
+            resolved.chain,
+            vec![Hop::Ipns(first), Hop::Ipns(middle), Hop::Ipns(last)]
+        );
+        assert_eq!(resolved.ttl, Some(HOUR));
+        assert_eq!(resolved.validity, Some(NOW + HOUR));
+
+        // a bare name
+        let resolved = resolver.resolve_at(&first.to_string(), NOW).await.unwrap();
+        assert_eq!(resolved.path, "/ipfs/bafkqaaa/docs/v1");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_bare_cid_values() {
+        let routing = MemoryRouting::default();
+        let name = publish(&routing, "bafkqaaa", HOUR);
+
+        let resolved = Resolver::new(routing)
+            .resolve_at(&format!("/ipns/{name}/a"), NOW)
+            .await
+            .unwrap();
+        assert_eq!(resolved.path, "/ipfs/bafkqaaa/a");
+    }
+
+    #[tokio::test]
+    async fn test_detects_cycles() {
+        let routing = MemoryRouting::default();
+        let (a_key, a) = key();
+        let (b_key, b) = key();
+        routing.put(a, record(&a_key, &format!("/ipns/{b}"), HOUR, NOW + HOUR));
+        routing.put(b, record(&b_key, &format!("/ipns/{a}/x"), HOUR, NOW + HOUR));
+
+        let err = Resolver::new(routing)
+            .resolve_at(&format!("/ipns/{a}"), NOW)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ResolveError::Cycle(Hop::Ipns(name)) if name == a));
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit() {
+        let routing = MemoryRouting::default();
+        let mut name = publish(&routing, "/ipfs/bafkqaaa", HOUR);
+        for _ in 0..4 {
+            name = publish(&routing, &format!("/ipns/{name}"), HOUR);
+        }
+        let path = format!("/ipns/{name}");
+
+        let mut resolver = Resolver::new(routing);
+        assert!(resolver.resolve_at(&path, NOW).await.is_ok());
+
+        resolver.max_depth(4);
+        let err = resolver.resolve_at(&path, NOW).await.unwrap_err();
+        assert!(matches!(err, ResolveError::DepthExceeded(4)));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_records() {
+        let routing = MemoryRouting::default();
+        let (keypair, name) = key();
+        let (_, forged) = key();
+        let (_, missing) = key();
+
+        routing.put(name, record(&keypair, "/ipfs/bafkqaaa", HOUR, NOW - HOUR));
+        // a record signed by another key, stored under this name
+        routing.put(forged, record(&keypair, "/ipfs/bafkqaaa", HOUR, NOW + HOUR));
+
+        let resolver = Resolver::new(routing);
+        let resolve = |name: IpnsName| {
+            let resolver = &resolver;
+            async move { resolver.resolve_at(&format!("/ipns/{name}"), NOW).await }
+        };
+
+        assert!(matches!(resolve(name).await, Err(ResolveError::Expired(_))));
+        assert!(matches!(
+            resolve(forged).await,
+            Err(ResolveError::InvalidRecord(..))
+        ));
+        assert!(matches!(
+            resolve(missing).await,
+            Err(ResolveError::NotFound(_))
+        ));
+
+        for path in ["/ipns/", "/foo/bar", "/ipns/not-a-name"] {
+            assert!(resolver.resolve_at(path, NOW).await.is_err(), "{path}");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! In-memory [Routing], for tests and as a local record store
+use crate::Routing;
+use async_trait::async_trait;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Records held in memory, one per IPNS Name. Records are returned as stored, unvalidated.
+#[derive(Debug, Default)]
+pub struct MemoryRouting {
+    records: RwLock<HashMap<IpnsName, IpnsEntry>>,
+}
+
+impl MemoryRouting {
+    /// Store `record` for `name`, replacing any previous one
+    pub fn put(&self, name: IpnsName, record: IpnsEntry) {
+        self.records
+            .write()
+            .expect("not poisoned")
+            .insert(name, record);
+    }
+
+    pub fn remove(&self, name: &IpnsName) -> Option<IpnsEntry> {
+        self.records.write().expect("not poisoned").remove(name)
+    }
+}
+
+#[async_trait]
+impl Routing for MemoryRouting {
+    async fn get_record(&self, name: &IpnsName) -> anyhow::Result<Option<IpnsEntry>> {
+        Ok(self
+            .records
+            .read()
+            .expect("not poisoned")
+            .get(name)
+            .cloned())
+    }
+}
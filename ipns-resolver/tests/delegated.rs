@@ -0,0 +1,192 @@
+//! Delegated Routing V1 against a local mock HTTP server
+#![cfg(feature = "http")]
+
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::signer::{Keypair, Signer};
+use ipns_entry::DataBuilder;
+use ipns_resolver::delegated::{DelegatedRouting, IPNS_RECORD_CONTENT_TYPE};
+use ipns_resolver::Resolver;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// Records by request path
+type Records = Arc<Mutex<HashMap<String, (String, Vec<u8>)>>>;
+
+/// Serves `GET` from `records` (content type and body), and stores `PUT` records
+async fn mock_server(records: Records) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let records = records.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((key, value)) => {
+                            headers.insert(key.to_ascii_lowercase(), value.trim().to_string())
+                        }
+                        None => break,
+                    };
+                }
+                let len = headers
+                    .get("content-length")
+                    .map_or(0, |len| len.parse().unwrap());
+                let mut body = vec![0; len];
+                stream.read_exact(&mut body).await.unwrap();
+
+                let (status, content_type, body) = match method {
+                    "GET"
+                        if headers.get("accept").map(String::as_str)
+                            == Some(IPNS_RECORD_CONTENT_TYPE) =>
+                    {
+                        match records.lock().unwrap().get(path) {
+                            Some((content_type, body)) => {
+                                ("200 OK", content_type.clone(), body.clone())
+                            }
+                            None => ("404 Not Found", "text/plain".into(), vec![]),
+                        }
+                    }
+                    "PUT"
+                        if headers.get("content-type").map(String::as_str)
+                            == Some(IPNS_RECORD_CONTENT_TYPE) =>
+                    {
+                        let record = (IPNS_RECORD_CONTENT_TYPE.to_string(), body);
+                        records.lock().unwrap().insert(path.to_string(), record);
+                        ("200 OK", "text/plain".into(), vec![])
+                    }
+                    _ => (
+                        "400 Bad Request",
+                        "text/plain".into(),
+                        b"bad request".to_vec(),
+                    ),
+                };
+
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let stream = stream.get_mut();
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(&body).await.unwrap();
+                stream.shutdown().await.unwrap();
+            });
+        }
+    });
+
+    format!("http://{addr}")
+}
+
+/// Answers every request with an IPNS Record content type and `len` bytes, without a
+/// Content-Length, so the client only finds the size by reading the body
+async fn unsized_server(len: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {IPNS_RECORD_CONTENT_TYPE}\r\nConnection: close\r\n\r\n"
+                );
+                // errors once the client hangs up
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&vec![0; len]).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    format!("http://{addr}")
+}
+
+fn key() -> (Keypair, IpnsName) {
+    let keypair = Keypair::generate_ed25519();
+    let name = IpnsName::from(keypair.public().to_peer_id());
+    (keypair, name)
+}
+
+fn record(keypair: &Keypair, value: &str) -> IpnsEntry {
+    let (data, signables) = DataBuilder::new(value).build();
+    IpnsEntry::new(data, Signer::new(keypair.clone()).sign(signables).unwrap())
+}
+
+fn path(name: &IpnsName) -> String {
+    format!("/routing/v1/ipns/{name}")
+}
+
+#[tokio::test]
+async fn test_put_then_get_and_resolve() {
+    let records = Records::default();
+    let routing = DelegatedRouting::new(&mock_server(records.clone()).await).unwrap();
+    let (keypair, name) = key();
+
+    assert!(routing.get(&name).await.unwrap().is_none());
+
+    let record = record(&keypair, "/ipfs/bafkqaaa");
+    routing.put(&name, &record).await.unwrap();
+    assert_eq!(records.lock().unwrap()[&path(&name)].1, record.to_bytes());
+    assert_eq!(routing.get(&name).await.unwrap(), Some(record));
+
+    let resolved = Resolver::new(routing)
+        .resolve(&format!("/ipns/{name}/a"))
+        .await
+        .unwrap();
+    assert_eq!(resolved.path, "/ipfs/bafkqaaa/a");
+}
+
+#[tokio::test]
+async fn test_rejects_invalid_responses() {
+    let records = Records::default();
+    let routing = DelegatedRouting::new(&mock_server(records.clone()).await).unwrap();
+    let (keypair, name) = key();
+    let (other, other_name) = key();
+
+    // a record signed by another key, served for `name`
+    let wrong_key = record(&other, "/ipfs/bafkqaaa").to_bytes();
+    records.lock().unwrap().insert(
+        path(&name),
+        (IPNS_RECORD_CONTENT_TYPE.to_string(), wrong_key),
+    );
+    assert!(routing.get(&name).await.is_err());
+
+    // not an IPNS Record
+    records.lock().unwrap().insert(
+        path(&other_name),
+        ("application/json".to_string(), b"{}".to_vec()),
+    );
+    assert!(routing.get(&other_name).await.is_err());
+
+    // records which do not validate are not sent
+    let mismatched = record(&keypair, "/ipfs/bafkqaaa");
+    assert!(routing.put(&other_name, &mismatched).await.is_err());
+
+    // nor records over 10 KiB
+    let oversized = record(&keypair, &format!("/ipfs/{}", "a".repeat(6 * 1024)));
+    let served = records.lock().unwrap()[&path(&name)].clone();
+    assert!(routing.put(&name, &oversized).await.is_err());
+    assert_eq!(records.lock().unwrap()[&path(&name)], served);
+}
+
+#[tokio::test]
+async fn test_rejects_oversized_body_without_content_length() {
+    let routing = DelegatedRouting::new(&unsized_server(1024 * 1024).await).unwrap();
+    let (_, name) = key();
+
+    let err = routing.get(&name).await.unwrap_err();
+    assert!(err.to_string().contains("10 KiB"), "{err}");
+}
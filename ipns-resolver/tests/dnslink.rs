@@ -0,0 +1,159 @@
+//! DNSLink resolution against a local stub DNS server
+#![cfg(feature = "dns")]
+
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::signer::{Keypair, Signer};
+use ipns_entry::DataBuilder;
+use ipns_resolver::dnslink::{DnsLink, DnsTxtResolver};
+use ipns_resolver::{Hop, MemoryRouting, ResolveError, Resolver};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const TTL: u32 = 300;
+
+/// Answers TXT queries from `zone` (character-strings by lowercase FQDN), NXDOMAIN otherwise
+async fn stub_dns(zone: HashMap<String, Vec<Vec<String>>>) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = [0; 512];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            if let Some(response) = answer(&buf[..len], &zone) {
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        }
+    });
+
+    addr
+}
+
+fn answer(query: &[u8], zone: &HashMap<String, Vec<Vec<String>>>) -> Option<Vec<u8>> {
+    // header, then a single question: labels, type, class
+    let mut pos = 12;
+    let mut labels = vec![];
+    loop {
+        let len = *query.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(query.get(pos..pos + len)?).to_ascii_lowercase());
+        pos += len;
+    }
+    let qtype = u16::from_be_bytes([*query.get(pos)?, *query.get(pos + 1)?]);
+    let question_end = pos + 4;
+    let name = format!("{}.", labels.join("."));
+
+    let records = zone.get(&name).filter(|_| qtype == 16);
+    let rcode = if zone.contains_key(&name) { 0 } else { 3 };
+    let answers = records.map_or(0, Vec::len) as u16;
+
+    let mut response = query[..2].to_vec();
+    response.extend_from_slice(&[0x81, 0x80 | rcode, 0, 1]);
+    response.extend_from_slice(&answers.to_be_bytes());
+    response.extend_from_slice(&[0, 0, 0, 0]);
+    response.extend_from_slice(query.get(12..question_end)?);
+    for strings in records.into_iter().flatten() {
+        let rdata: Vec<u8> = strings
+            .iter()
+            .flat_map(|s| std::iter::once(s.len() as u8).chain(s.bytes()))
+            .collect();
+        response.extend_from_slice(&[0xc0, 0x0c, 0, 16, 0, 1]);
+        response.extend_from_slice(&TTL.to_be_bytes());
+        response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        response.extend_from_slice(&rdata);
+    }
+
+    Some(response)
+}
+
+fn publish(routing: &MemoryRouting, value: &str) -> IpnsName {
+    let keypair = Keypair::generate_ed25519();
+    let name = IpnsName::from(keypair.public().to_peer_id());
+    let (data, signables) = DataBuilder::new(value)
+        .ttl(Duration::from_secs(3600).as_nanos() as u64)
+        .build();
+    let record = IpnsEntry::new(data, Signer::new(keypair).sign(signables).unwrap());
+    routing.put(name, record);
+    name
+}
+
+#[tokio::test]
+async fn test_resolves_dnslink_over_dns() {
+    let routing = MemoryRouting::default();
+    let name = publish(&routing, "/ipfs/bafkqaaa/site");
+    let name_string = name.to_string();
+    let zone = [
+        // a record split into several character-strings, next to an unrelated record
+        (
+            "_dnslink.example.com.",
+            vec![
+                vec!["v=spf1 -all"],
+                vec!["dnslink=/ipns/", name_string.as_str()],
+            ],
+        ),
+        ("example.com.", vec![]),
+        // no _dnslink. subdomain, the domain itself holds the record
+        (
+            "legacy.example.com.",
+            vec![vec!["dnslink=/ipfs/", "bafkqaab"]],
+        ),
+        (
+            "_dnslink.multi.example.com.",
+            vec![
+                vec!["dnslink=/ipfs/bafkqaac"],
+                vec!["dnslink=/ipfs/bafkqaab"],
+            ],
+        ),
+        (
+            "_dnslink.loop.example.com.",
+            vec![vec!["dnslink=/ipns/loop.example.com"]],
+        ),
+    ]
+    .into_iter()
+    .map(|(name, records): (&str, Vec<Vec<&str>>)| {
+        let records = records
+            .into_iter()
+            .map(|strings| strings.into_iter().map(str::to_string).collect())
+            .collect();
+        (name.to_string(), records)
+    })
+    .collect();
+    let upstream = stub_dns(zone).await;
+
+    let mut resolver = Resolver::new(routing);
+    resolver.dnslink(DnsLink::new(
+        DnsTxtResolver::with_upstream(&[upstream]).unwrap(),
+    ));
+
+    let resolved = resolver.resolve("/ipns/Example.com/a").await.unwrap();
+    assert_eq!(resolved.path, "/ipfs/bafkqaaa/site/a");
+    assert_eq!(
+        resolved.chain,
+        vec![Hop::DnsLink("example.com".to_string()), Hop::Ipns(name)]
+    );
+    // the DNS TTL is shorter than the record's
+    assert!(resolved.ttl.unwrap() <= Duration::from_secs(TTL as u64));
+    assert!(resolved.validity.is_some());
+
+    let resolved = resolver.resolve("/ipns/legacy.example.com").await.unwrap();
+    assert_eq!(resolved.path, "/ipfs/bafkqaab");
+    assert_eq!(resolved.validity, None);
+
+    let resolved = resolver.resolve("/ipns/multi.example.com/").await.unwrap();
+    assert_eq!(resolved.path, "/ipfs/bafkqaab/");
+
+    assert!(matches!(
+        resolver.resolve("/ipns/missing.example.com").await,
+        Err(ResolveError::NoDnsLink(domain)) if domain == "missing.example.com"
+    ));
+    assert!(matches!(
+        resolver.resolve("/ipns/loop.example.com").await,
+        Err(ResolveError::Cycle(Hop::DnsLink(_)))
+    ));
+}
@@ -1,17 +1,22 @@
 use super::config::topic::topic; // TODO: Make a behaviour config module instead of crate wide config
+use crate::fetch::{FetchCodec, FetchProtocol, FetchRequest, FetchResponse};
 use libp2p::autonat;
 use libp2p::gossipsub;
 use libp2p::identify;
 use libp2p::identity::Keypair;
 use libp2p::kad;
-use libp2p::kad::{record::store::MemoryStore, Kademlia, KademliaConfig, KademliaEvent};
+use libp2p::kad::{
+    record::store::MemoryStore, Kademlia, KademliaConfig, KademliaEvent, KademliaStoreInserts,
+};
 use libp2p::relay;
+use libp2p::request_response::{self, ProtocolSupport};
 use libp2p::swarm::{behaviour::toggle::Toggle, keep_alive, NetworkBehaviour};
 use libp2p::{Multiaddr, PeerId};
 use log::debug;
 use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::iter;
 use std::str::FromStr;
 use std::time::Duration;
 use void::Void;
@@ -31,7 +36,9 @@ pub struct Behaviour {
     pub kademlia: Toggle<Kademlia<MemoryStore>>,
     keep_alive: keep_alive::Behaviour,
     relay: relay::Behaviour,
-    autonat: Toggle<autonat::Behaviour>,
+    pub autonat: Toggle<autonat::Behaviour>,
+    /// `/libp2p/fetch/0.0.1`, for IPNS over PubSub
+    pub fetch: request_response::Behaviour<FetchCodec>,
 }
 
 impl Behaviour {
@@ -61,11 +68,15 @@ impl BehaviourBuilder {
     }
 
     /// Optionally enable autonat
-    /// ⚠️ Do not enable for WebRTC-Broswer facing connections, as autonat may break WebRTC Transport in browsers
+    /// ⚠️ Probing browsers may break WebRTC Transport in browsers, so connected peers are not used as
+    /// servers: the network adds the peers connected over TCP or QUIC.
     pub fn with_autonat(mut self) -> Self {
         self.autonat = Some(autonat::Behaviour::new(
             PeerId::from(self.id_keys.public()),
-            Default::default(),
+            autonat::Config {
+                use_connected: false,
+                ..Default::default()
+            },
         ));
         self
     }
@@ -78,6 +89,8 @@ impl BehaviourBuilder {
         if let Some(proto) = protocol_name {
             cfg.set_protocol_names(vec![Cow::Owned(proto.to_vec())]);
         }
+        // the network stores the records of other peers unless private
+        cfg.set_record_filtering(KademliaStoreInserts::FilterBoth);
         let store = MemoryStore::new(PeerId::from(self.id_keys.public()));
         let mut kademlia = Kademlia::with_config(PeerId::from(self.id_keys.public()), store, cfg);
 
@@ -138,9 +151,16 @@ impl BehaviourBuilder {
                 .with_agent_version(format!("rust-libp2p-server/{}", env!("CARGO_PKG_VERSION"))),
         );
 
+        let fetch = request_response::Behaviour::new(
+            FetchCodec,
+            iter::once((FetchProtocol, ProtocolSupport::Full)),
+            Default::default(),
+        );
+
         Behaviour {
             gossipsub,
             identify,
+            fetch,
             autonat: self.autonat.into(),
             kademlia: self.kademlia.into(),
             keep_alive: keep_alive::Behaviour::default(),
@@ -167,6 +187,7 @@ pub enum ComposedEvent {
     Autonat(autonat::Event),
     Kademlia(KademliaEvent),
     Relay(relay::Event),
+    Fetch(request_response::Event<FetchRequest, FetchResponse>),
 }
 
 impl From<gossipsub::Event> for ComposedEvent {
@@ -199,6 +220,12 @@ impl From<relay::Event> for ComposedEvent {
     }
 }
 
+impl From<request_response::Event<FetchRequest, FetchResponse>> for ComposedEvent {
+    fn from(event: request_response::Event<FetchRequest, FetchResponse>) -> Self {
+        ComposedEvent::Fetch(event)
+    }
+}
+
 // void too
 impl From<Void> for ComposedEvent {
     fn from(event: Void) -> Self {
@@ -1,42 +1,48 @@
-use anyhow::Result;
-use ipns_server::Message;
+use anyhow::{anyhow, Result};
+use ipns_server::network::NetworkEvent;
 use ipns_server::Server;
-use ipns_server::ServerResponse;
-use tokio::sync::{mpsc, oneshot};
+use std::collections::HashSet;
+use tokio::sync::broadcast::error::RecvError;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let (sendr, recvr) = mpsc::channel::<Message<ServerResponse>>(8);
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    let _handle = tokio::spawn(async {
-        let _ = Server::new()
-            .enable_kademlia()
-            .start_with_tokio_executor(recvr)
-            .await;
-    });
+    let handle = Server::new()
+        .enable_kademlia()
+        .enable_autonat()
+        .start()
+        .await
+        .map_err(|e| anyhow!("{e}"))?;
+
+    let mut events = handle.events();
+    let mut printed = HashSet::new();
+    let mut print_address = move |address: String| {
+        if printed.insert(address.clone()) {
+            // Rust doesn't support octal character escape sequence
+            // For colors, use hexadecimal escape instead, plus a series of semicolon-separated parameters.
+            println!("Connect with: \n\x1b[30;1;42m{address}\x1b[0m");
+        }
+    };
+
+    for address in handle.listen_addresses().await {
+        print_address(address.to_string());
+    }
 
     tokio::spawn(async move {
         loop {
-            let (reply_sender, reply_rcvr) = oneshot::channel();
-
-            let _result = sendr
-                .send(Message::<ServerResponse> {
-                    reply: reply_sender,
-                })
-                .await;
-
-            if let Ok(reply) = reply_rcvr.await {
-                let s: String = std::str::from_utf8(&reply.address).unwrap().into();
-                // Rust doesn't support octal character escape sequence
-                // For colors, use hexadecimal escape instead, plus a series of semicolon-separated parameters.
-                println!("Connect with: \n\x1b[30;1;42m{s}\x1b[0m");
+            match events.recv().await {
+                Ok(NetworkEvent::NewListenAddr { address }) => print_address(address.to_string()),
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
             }
         }
     });
 
     println!("\n*** To Shutdown, use Ctrl + C ***\n");
 
-    match tokio::signal::ctrl_c().await {
+    match shutdown_signal().await {
         Ok(()) => {}
         Err(err) => {
             eprintln!("Unable to listen for shutdown signal: {err}");
@@ -44,5 +50,23 @@ async fn main() -> Result<()> {
         }
     };
 
-    Ok(())
+    println!("Shutting down...");
+    handle.shutdown().await.map_err(|e| anyhow!("{e}"))
+}
+
+/// Wait for SIGINT (Ctrl + C) or, on unix, SIGTERM
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
 }
@@ -0,0 +1,828 @@
+//! The WebRTC certificate, whose hash is part of the `/webrtc-direct/certhash/...` addresses, and
+//! its scheduled rotation.
+//!
+//! A certificate is used until it expires. Within `overlap` of its expiry the successor is
+//! generated, saved next to it, and served on ports of its own along with the current one, so
+//! peers learn the new certhash before the switch. Expiries are kept in files of their own, so
+//! the certificates stay plain PEM.
+
+use anyhow::{anyhow, Context, Result};
+use futures::FutureExt;
+use futures_timer::Delay;
+use libp2p::core::transport::{ListenerId, TransportError, TransportEvent};
+use libp2p::identity;
+use libp2p::multiaddr::multihash::Multihash;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, Transport};
+use libp2p_webrtc::tokio::{self as webrtc, Certificate};
+use log::{info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+const LOCAL_CERT_PATH: &str = "./cert.pem";
+
+const CERTIFICATE_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const CERTIFICATE_OVERLAP: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// How long to wait before trying again when a rotation fails
+const ROTATION_RETRY: Duration = Duration::from_secs(60);
+
+/// Added to the path of a certificate for the file holding its expiry, in seconds since the epoch
+const EXPIRES_SUFFIX: &str = ".expires";
+
+/// The certificate to use, and its successor once within the overlap
+type Certificates = (ExpiringCertificate, Option<ExpiringCertificate>);
+
+/// Where the WebRTC certificate is kept, and how long it is used.
+/// Defaults to `./cert.pem`, valid for 30 days, with the successor served 3 days ahead.
+#[derive(Debug, Clone)]
+pub struct CertificateConfig {
+    path: PathBuf,
+    lifetime: Duration,
+    overlap: Duration,
+}
+
+impl Default for CertificateConfig {
+    fn default() -> Self {
+        CertificateConfig {
+            path: PathBuf::from(LOCAL_CERT_PATH),
+            lifetime: CERTIFICATE_LIFETIME,
+            overlap: CERTIFICATE_OVERLAP,
+        }
+    }
+}
+
+impl CertificateConfig {
+    /// Keep the certificate at `path`, its expiry at `path` with an `.expires` suffix, and its
+    /// successor at `path` with a `.next` suffix
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CertificateConfig {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// How long a certificate is used
+    pub fn lifetime(&mut self, lifetime: Duration) -> &mut Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// How long before the switch the successor is served
+    pub fn overlap(&mut self, overlap: Duration) -> &mut Self {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn next_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".next")
+    }
+
+    /// The certificate to use at `now`, and its successor once within the overlap window.
+    /// An expired certificate is replaced by its successor, or by a new one.
+    fn load(&self, now: SystemTime) -> Result<Certificates> {
+        if self.overlap >= self.lifetime {
+            return Err(anyhow!(
+                "The certificate overlap must be shorter than its lifetime"
+            ));
+        }
+
+        let next_path = self.next_path();
+        let mut next = self.read(&next_path, now)?;
+        let mut current = match self.read(&self.path, now)? {
+            Some(current) => current,
+            None => self.generate(&self.path, now + self.lifetime)?,
+        };
+
+        if current.expires <= now {
+            current = match next.take() {
+                Some(next) if next.expires > now => {
+                    rename(&next_path, &self.path)?;
+                    info!("Switched to the certificate from {}", next_path.display());
+                    next
+                }
+                _ => self.generate(&self.path, now + self.lifetime)?,
+            };
+            remove(&next_path)?;
+        }
+
+        if next.is_none() && now + self.overlap >= current.expires {
+            next = Some(self.generate(&next_path, current.expires + self.lifetime)?);
+        }
+
+        Ok((current, next))
+    }
+
+    /// When [Self::load] has something new to do: serve the successor, or switch to it
+    fn next_rotation(&self, current: &ExpiringCertificate, next_served: bool) -> SystemTime {
+        if next_served {
+            current.expires
+        } else {
+            current.expires - self.overlap
+        }
+    }
+
+    fn read(&self, path: &Path, now: SystemTime) -> Result<Option<ExpiringCertificate>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let certificate = Certificate::from_pem(&fs::read_to_string(path)?)?;
+        info!("Using existing certificate from {}", path.display());
+
+        let expires_path = expires_path(path);
+        let certificate = if expires_path.exists() {
+            let expires = parse_expiry(&fs::read_to_string(&expires_path)?)
+                .with_context(|| format!("Failed to read {}", expires_path.display()))?;
+            ExpiringCertificate {
+                certificate,
+                expires,
+            }
+        } else {
+            // saved before certificates expired, keep it for a lifetime from now
+            let certificate = ExpiringCertificate {
+                certificate,
+                expires: now + self.lifetime,
+            };
+            certificate.write_expiry(path)?;
+            certificate
+        };
+        Ok(Some(certificate))
+    }
+
+    fn generate(&self, path: &Path, expires: SystemTime) -> Result<ExpiringCertificate> {
+        let certificate = ExpiringCertificate {
+            certificate: Certificate::generate(&mut rand::thread_rng())?,
+            expires,
+        };
+        certificate.write(path)?;
+
+        info!(
+            "Generated new certificate and wrote it to {}",
+            path.display()
+        );
+
+        Ok(certificate)
+    }
+}
+
+struct ExpiringCertificate {
+    certificate: Certificate,
+    expires: SystemTime,
+}
+
+impl ExpiringCertificate {
+    fn certhash(&self) -> Multihash {
+        self.certificate.fingerprint().to_multihash()
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.certificate.serialize_pem())?;
+        self.write_expiry(path)
+    }
+
+    fn write_expiry(&self, path: &Path) -> Result<()> {
+        let expires = self.expires.duration_since(UNIX_EPOCH)?.as_secs();
+        fs::write(expires_path(path), format!("{expires}\n"))?;
+        Ok(())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(suffix);
+    path.into()
+}
+
+fn expires_path(path: &Path) -> PathBuf {
+    with_suffix(path, EXPIRES_SUFFIX)
+}
+
+fn parse_expiry(saved: &str) -> Result<SystemTime> {
+    Ok(UNIX_EPOCH + Duration::from_secs(saved.trim().parse()?))
+}
+
+/// Move a saved certificate, then its expiry
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to)?;
+    fs::rename(expires_path(from), expires_path(to))?;
+    Ok(())
+}
+
+/// Remove a saved certificate and its expiry, if any
+fn remove(path: &Path) -> Result<()> {
+    for path in [path.to_path_buf(), expires_path(path)] {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// `address` with its UDP port replaced by `port`
+fn with_udp_port(address: &Multiaddr, port: u16) -> Multiaddr {
+    address
+        .iter()
+        .map(|protocol| match protocol {
+            Protocol::Udp(_) => Protocol::Udp(port),
+            protocol => protocol,
+        })
+        .collect()
+}
+
+fn certhash_of(address: &Multiaddr) -> Option<Multihash> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Certhash(certhash) => Some(certhash),
+        _ => None,
+    })
+}
+
+fn udp_port_of(address: &Multiaddr) -> Option<u16> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Udp(port) => Some(port),
+        _ => None,
+    })
+}
+
+type Event = TransportEvent<
+    <webrtc::Transport as Transport>::ListenerUpgrade,
+    <webrtc::Transport as Transport>::Error,
+>;
+
+/// A WebRTC transport serving one certificate
+struct Inner {
+    certhash: Multihash,
+    transport: webrtc::Transport,
+    /// Our listener ids, by the ids of this transport
+    listener_ids: HashMap<ListenerId, ListenerId>,
+}
+
+impl Inner {
+    fn new(keypair: &identity::Keypair, certificate: ExpiringCertificate) -> Self {
+        Inner {
+            certhash: certificate.certhash(),
+            transport: webrtc::Transport::new(keypair.clone(), certificate.certificate),
+            listener_ids: Default::default(),
+        }
+    }
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        address: Multiaddr,
+    ) -> Result<(), TransportError<<webrtc::Transport as Transport>::Error>> {
+        let inner_id = self.transport.listen_on(address)?;
+        self.listener_ids.insert(inner_id, id);
+        Ok(())
+    }
+
+    fn serves(&self, id: ListenerId) -> bool {
+        self.listener_ids.values().any(|outer_id| *outer_id == id)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let inner_ids: Vec<_> = self
+            .listener_ids
+            .iter()
+            .filter(|(_, outer_id)| **outer_id == id)
+            .map(|(inner_id, _)| *inner_id)
+            .collect();
+        let mut removed = false;
+        for inner_id in inner_ids {
+            removed |= self.transport.remove_listener(inner_id);
+        }
+        removed
+    }
+
+    /// Remove the listeners for `id`, ignoring their events from now on
+    fn close(&mut self, id: ListenerId) {
+        self.remove_listener(id);
+        self.listener_ids.retain(|_, outer_id| *outer_id != id);
+    }
+}
+
+struct Listener {
+    /// The address asked for, with the port bound if it was 0, to listen again after a switch
+    address: Multiaddr,
+    /// The addresses reported, with the current or the successor's certhash
+    addresses: Vec<Multiaddr>,
+}
+
+/// The WebRTC transport, rotating its certificate as scheduled by a [CertificateConfig].
+///
+/// Within the overlap the successor is served by a second transport, on another port of the
+/// same interfaces. Switching certificates moves the listeners to the successor and drops the
+/// previous transport, which closes the WebRTC connections made with the previous certificate.
+pub struct RotatingTransport {
+    keypair: identity::Keypair,
+    config: CertificateConfig,
+    current: Inner,
+    /// The successor, once within the overlap
+    next: Option<Inner>,
+    rotation: Delay,
+    /// The certificates being loaded for a rotation, and when
+    loading: Option<JoinHandle<(SystemTime, Result<Certificates>)>>,
+    listeners: HashMap<ListenerId, Listener>,
+    pending_events: VecDeque<Event>,
+}
+
+impl RotatingTransport {
+    pub fn new(keypair: identity::Keypair, config: CertificateConfig) -> Result<Self> {
+        Self::new_at(keypair, config, SystemTime::now())
+    }
+
+    fn new_at(
+        keypair: identity::Keypair,
+        config: CertificateConfig,
+        now: SystemTime,
+    ) -> Result<Self> {
+        let (current, next) = config.load(now).context("Failed to read certificate")?;
+
+        Ok(RotatingTransport {
+            rotation: delay_until(config.next_rotation(&current, next.is_some()), now),
+            current: Inner::new(&keypair, current),
+            next: next.map(|next| Inner::new(&keypair, next)),
+            keypair,
+            config,
+            loading: None,
+            listeners: Default::default(),
+            pending_events: Default::default(),
+        })
+    }
+
+    /// Load the certificates off the event loop, as it reads and writes files
+    fn spawn_load(&mut self) {
+        let config = self.config.clone();
+        self.loading = Some(tokio::task::spawn_blocking(move || {
+            let now = SystemTime::now();
+            (now, config.load(now))
+        }));
+    }
+
+    /// Serve the successor or switch to it, whichever is due at `now`
+    fn rotate(&mut self, now: SystemTime, loaded: Result<Certificates>) {
+        let (current, next) = match loaded {
+            Ok(certificates) => certificates,
+            Err(e) => {
+                warn!("Failed to rotate the WebRTC certificate: {e:#}");
+                self.rotation = Delay::new(ROTATION_RETRY);
+                return;
+            }
+        };
+        self.rotation = delay_until(self.config.next_rotation(&current, next.is_some()), now);
+
+        if current.certhash() != self.current.certhash {
+            self.switch(current);
+        }
+
+        let certhash = next.as_ref().map(ExpiringCertificate::certhash);
+        if certhash != self.next.as_ref().map(|next| next.certhash) {
+            self.next = next.map(|next| {
+                info!("Serving the next WebRTC certificate");
+                let mut successor = Inner::new(&self.keypair, next);
+                for (id, listener) in &self.listeners {
+                    // the current certificate keeps the port
+                    if let Err(e) = successor.listen_on(*id, with_udp_port(&listener.address, 0)) {
+                        warn!(
+                            "Failed to listen on {} with the next certificate: {e}",
+                            listener.address
+                        );
+                    }
+                }
+                successor
+            });
+        }
+    }
+
+    /// Listen again with `current`, expiring the addresses of other certificates
+    fn switch(&mut self, current: ExpiringCertificate) {
+        info!("Switching to the next WebRTC certificate");
+        // drop the previous listeners first, releasing their ports
+        self.current = match self.next.take() {
+            Some(next) if next.certhash == current.certhash() => next,
+            _ => Inner::new(&self.keypair, current),
+        };
+
+        let ids: Vec<_> = self.listeners.keys().copied().collect();
+        for id in ids {
+            let Some(listener) = self.listeners.get_mut(&id) else {
+                continue;
+            };
+
+            let (kept, expired): (Vec<_>, Vec<_>) = listener
+                .addresses
+                .drain(..)
+                .partition(|address| certhash_of(address) == Some(self.current.certhash));
+            listener.addresses = kept;
+            for address in expired {
+                self.pending_events
+                    .push_back(TransportEvent::AddressExpired {
+                        listener_id: id,
+                        listen_addr: address,
+                    });
+            }
+
+            let address = listener.address.clone();
+            if let Err(e) = self.current.listen_on(id, address.clone()) {
+                warn!("Failed to listen on {address} again: {e}");
+                // still served on the port taken within the overlap
+                if self.current.serves(id) {
+                    continue;
+                }
+                self.listeners.remove(&id);
+                self.pending_events
+                    .push_back(TransportEvent::ListenerClosed {
+                        listener_id: id,
+                        reason: match e {
+                            TransportError::Other(e) => Err(e),
+                            TransportError::MultiaddrNotSupported(_) => Ok(()),
+                        },
+                    });
+            }
+        }
+    }
+
+    /// An event of the current transport, or of the successor, with our listener id
+    fn map_event(&mut self, event: Event, from_next: bool) -> Option<Event> {
+        let inner = if from_next {
+            self.next.as_mut()?
+        } else {
+            &mut self.current
+        };
+        let inner_id = match &event {
+            TransportEvent::Incoming { listener_id, .. }
+            | TransportEvent::NewAddress { listener_id, .. }
+            | TransportEvent::AddressExpired { listener_id, .. }
+            | TransportEvent::ListenerClosed { listener_id, .. }
+            | TransportEvent::ListenerError { listener_id, .. } => *listener_id,
+        };
+        let id = *inner.listener_ids.get(&inner_id)?;
+
+        Some(match event {
+            TransportEvent::Incoming {
+                upgrade,
+                local_addr,
+                send_back_addr,
+                ..
+            } => TransportEvent::Incoming {
+                listener_id: id,
+                upgrade,
+                local_addr,
+                send_back_addr,
+            },
+            TransportEvent::NewAddress { listen_addr, .. } => {
+                if let Some(listener) = self.listeners.get_mut(&id) {
+                    if !from_next && udp_port_of(&listener.address) == Some(0) {
+                        if let Some(port) = udp_port_of(&listen_addr) {
+                            listener.address = with_udp_port(&listener.address, port);
+                        }
+                    }
+                    if !listener.addresses.contains(&listen_addr) {
+                        listener.addresses.push(listen_addr.clone());
+                    }
+                }
+                TransportEvent::NewAddress {
+                    listener_id: id,
+                    listen_addr,
+                }
+            }
+            TransportEvent::AddressExpired { listen_addr, .. } => {
+                if let Some(listener) = self.listeners.get_mut(&id) {
+                    listener.addresses.retain(|address| *address != listen_addr);
+                }
+                TransportEvent::AddressExpired {
+                    listener_id: id,
+                    listen_addr,
+                }
+            }
+            TransportEvent::ListenerClosed { reason, .. } => {
+                inner.listener_ids.remove(&inner_id);
+                // closed once neither certificate listens for it
+                let served =
+                    self.current.serves(id) || self.next.iter().any(|next| next.serves(id));
+                if served && reason.is_ok() {
+                    return None;
+                }
+                self.current.close(id);
+                if let Some(next) = &mut self.next {
+                    next.close(id);
+                }
+                self.listeners.remove(&id);
+                TransportEvent::ListenerClosed {
+                    listener_id: id,
+                    reason,
+                }
+            }
+            TransportEvent::ListenerError { error, .. } => TransportEvent::ListenerError {
+                listener_id: id,
+                error,
+            },
+        })
+    }
+}
+
+fn delay_until(at: SystemTime, now: SystemTime) -> Delay {
+    Delay::new(at.duration_since(now).unwrap_or_default())
+}
+
+impl Transport for RotatingTransport {
+    type Output = <webrtc::Transport as Transport>::Output;
+    type Error = <webrtc::Transport as Transport>::Error;
+    type ListenerUpgrade = <webrtc::Transport as Transport>::ListenerUpgrade;
+    type Dial = <webrtc::Transport as Transport>::Dial;
+
+    fn listen_on(&mut self, addr: Multiaddr) -> Result<ListenerId, TransportError<Self::Error>> {
+        let id = ListenerId::new();
+        self.current.listen_on(id, addr.clone())?;
+        if let Some(next) = &mut self.next {
+            if let Err(e) = next.listen_on(id, with_udp_port(&addr, 0)) {
+                warn!("Failed to listen on {addr} with the next certificate: {e}");
+            }
+        }
+        self.listeners.insert(
+            id,
+            Listener {
+                address: addr,
+                addresses: vec![],
+            },
+        );
+        Ok(id)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let removed = self.current.remove_listener(id);
+        match &mut self.next {
+            Some(next) => next.remove_listener(id) || removed,
+            None => removed,
+        }
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.current.transport.dial(addr)
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.current.transport.dial_as_listener(addr)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        loop {
+            if let Some(loading) = &mut self.loading {
+                let Poll::Ready(loaded) = loading.poll_unpin(cx) else {
+                    break;
+                };
+                self.loading = None;
+                match loaded {
+                    Ok((now, loaded)) => self.rotate(now, loaded),
+                    Err(e) => {
+                        warn!("Failed to rotate the WebRTC certificate: {e}");
+                        self.rotation = Delay::new(ROTATION_RETRY);
+                    }
+                }
+            }
+            if self.rotation.poll_unpin(cx).is_pending() {
+                break;
+            }
+            self.spawn_load();
+        }
+
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            let (event, from_next) = match Pin::new(&mut self.current.transport).poll(cx) {
+                Poll::Ready(event) => (event, false),
+                Poll::Pending => match &mut self.next {
+                    Some(next) => match Pin::new(&mut next.transport).poll(cx) {
+                        Poll::Ready(event) => (event, true),
+                        Poll::Pending => return Poll::Pending,
+                    },
+                    None => return Poll::Pending,
+                },
+            };
+            if let Some(event) = self.map_event(event, from_next) {
+                return Poll::Ready(event);
+            }
+        }
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.current.transport.address_translation(listen, observed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expiry() {
+        assert_eq!(
+            parse_expiry("1700000000\n").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert!(parse_expiry("soon").is_err());
+        assert_eq!(
+            expires_path(Path::new("./cert.pem")),
+            Path::new("./cert.pem.expires")
+        );
+    }
+
+    #[test]
+    fn test_with_udp_port() {
+        let address: Multiaddr = "/ip4/1.2.3.4/udp/9090/webrtc-direct/certhash/uEiDDq4_xNyDorZBH3TlGazyJdOWSwvo4PUo5YHFMrvDE8g"
+            .parse()
+            .unwrap();
+
+        let moved = with_udp_port(&address, 0);
+        assert_eq!(udp_port_of(&moved), Some(0));
+        assert_eq!(certhash_of(&moved), certhash_of(&address));
+        assert_eq!(with_udp_port(&moved, 9090), address);
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    /// An empty directory for the test `name`
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ipns-server-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load() {
+        let config = CertificateConfig::new(temp_dir("load").join("cert.pem"));
+
+        // first start
+        let (current, next) = config.load(start()).unwrap();
+        assert_eq!(current.expires, start() + CERTIFICATE_LIFETIME);
+        assert!(next.is_none());
+        assert!(!config.next_path().exists());
+        // the expiry is kept aside, the certificate stays plain PEM
+        assert!(fs::read_to_string(config.path())
+            .unwrap()
+            .starts_with("-----BEGIN"));
+        assert_eq!(
+            parse_expiry(&fs::read_to_string(expires_path(config.path())).unwrap()).unwrap(),
+            current.expires
+        );
+        assert_eq!(
+            config.next_rotation(&current, false),
+            current.expires - CERTIFICATE_OVERLAP
+        );
+
+        let (same, next) = config.load(start() + 26 * DAY).unwrap();
+        assert_eq!(same.certhash(), current.certhash());
+        assert!(next.is_none());
+
+        // entering the overlap
+        let overlap = current.expires - CERTIFICATE_OVERLAP;
+        let (same, next) = config.load(overlap).unwrap();
+        let next = next.unwrap();
+        assert_eq!(same.certhash(), current.certhash());
+        assert_ne!(next.certhash(), current.certhash());
+        assert_eq!(next.expires, current.expires + CERTIFICATE_LIFETIME);
+        assert!(config.next_path().exists());
+        assert_eq!(config.next_rotation(&same, true), current.expires);
+
+        // restart with `.next` present
+        let (same, same_next) = config.load(overlap + DAY).unwrap();
+        assert_eq!(same.certhash(), current.certhash());
+        assert_eq!(same_next.unwrap().certhash(), next.certhash());
+
+        // switching at expiry
+        let (switched, after) = config.load(current.expires).unwrap();
+        assert_eq!(switched.certhash(), next.certhash());
+        assert_eq!(switched.expires, next.expires);
+        assert!(after.is_none());
+        assert!(!config.next_path().exists());
+        assert!(!expires_path(&config.next_path()).exists());
+
+        let (same, _) = config.load(current.expires + DAY).unwrap();
+        assert_eq!(same.certhash(), next.certhash());
+    }
+
+    #[test]
+    fn test_load_expired_and_legacy() {
+        let mut config = CertificateConfig::new(temp_dir("expired").join("cert.pem"));
+        let (current, _) = config.load(start()).unwrap();
+        let next = config.load(current.expires - DAY).unwrap().1.unwrap();
+
+        // both expired while stopped
+        let now = next.expires + DAY;
+        let (fresh, after) = config.load(now).unwrap();
+        assert_ne!(fresh.certhash(), current.certhash());
+        assert_ne!(fresh.certhash(), next.certhash());
+        assert_eq!(fresh.expires, now + CERTIFICATE_LIFETIME);
+        assert!(after.is_none());
+        assert!(!config.next_path().exists());
+
+        // saved without an expiry
+        let pem = fs::read_to_string(config.path()).unwrap();
+        fs::remove_file(expires_path(config.path())).unwrap();
+        let (legacy, _) = config.load(start()).unwrap();
+        assert_eq!(legacy.certhash(), fresh.certhash());
+        assert_eq!(legacy.expires, start() + CERTIFICATE_LIFETIME);
+        assert!(expires_path(config.path()).exists());
+        assert_eq!(fs::read_to_string(config.path()).unwrap(), pem);
+
+        config.overlap(CERTIFICATE_LIFETIME);
+        assert!(config.load(start()).is_err());
+    }
+
+    fn rotate_at(transport: &mut RotatingTransport, now: SystemTime) {
+        let loaded = transport.config.load(now);
+        transport.rotate(now, loaded);
+    }
+
+    async fn next_event(transport: &mut RotatingTransport) -> Event {
+        let event = futures::future::poll_fn(|cx| Pin::new(&mut *transport).poll(cx));
+        tokio::time::timeout(Duration::from_secs(10), event)
+            .await
+            .expect("event")
+    }
+
+    async fn new_address(transport: &mut RotatingTransport, id: ListenerId) -> Multiaddr {
+        match next_event(transport).await {
+            TransportEvent::NewAddress {
+                listener_id,
+                listen_addr,
+            } if listener_id == id => listen_addr,
+            _ => panic!("expected a new address"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let config = CertificateConfig::new(temp_dir("rotate").join("cert.pem"));
+        let mut transport =
+            RotatingTransport::new_at(keypair.clone(), config.clone(), start()).unwrap();
+
+        let id = transport
+            .listen_on("/ip4/127.0.0.1/udp/0/webrtc-direct".parse().unwrap())
+            .unwrap();
+        let address = new_address(&mut transport, id).await;
+        let port = udp_port_of(&address).unwrap();
+        assert_eq!(certhash_of(&address), Some(transport.current.certhash));
+
+        // entering the overlap, the successor is served on a port of its own
+        let expires = start() + CERTIFICATE_LIFETIME;
+        rotate_at(&mut transport, expires - CERTIFICATE_OVERLAP);
+        let next = transport.next.as_ref().unwrap().certhash;
+        let announced = new_address(&mut transport, id).await;
+        let announced_port = udp_port_of(&announced).unwrap();
+        assert_eq!(certhash_of(&announced), Some(next));
+        assert_ne!(announced_port, port);
+        assert!(std::net::UdpSocket::bind(("127.0.0.1", announced_port)).is_err());
+
+        // restart with `.next` present
+        let restarted = RotatingTransport::new_at(keypair, config, expires - DAY).unwrap();
+        assert_eq!(
+            restarted.next.as_ref().map(|next| next.certhash),
+            Some(next)
+        );
+        drop(restarted);
+
+        // switching at expiry, the successor takes the port over
+        rotate_at(&mut transport, expires);
+        assert_eq!(transport.current.certhash, next);
+        assert!(transport.next.is_none());
+        match next_event(&mut transport).await {
+            TransportEvent::AddressExpired {
+                listener_id,
+                listen_addr,
+            } => assert_eq!((listener_id, listen_addr), (id, address)),
+            _ => panic!("expected the previous address to expire"),
+        }
+        let switched = new_address(&mut transport, id).await;
+        assert_eq!(udp_port_of(&switched), Some(port));
+        assert_eq!(certhash_of(&switched), Some(next));
+        assert!(std::net::UdpSocket::bind(("127.0.0.1", announced_port)).is_err());
+        assert_eq!(
+            transport.listeners[&id].addresses,
+            vec![announced, switched]
+        );
+    }
+}
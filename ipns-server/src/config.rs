@@ -12,13 +12,45 @@ pub const KADEMLIA_PROTOCOL_NAME: &[u8] = b"/universal-connectivity/lan/kad/1.0.
 pub const LOCAL_KEY_PATH: &str = "./local_keypair";
 
 pub mod topic {
-    use libp2p::gossipsub::IdentTopic;
+    use crate::routing::ipns_key;
+    use base64::engine::fast_portable::{self, FastPortable};
+    use ipns_entry::name::IpnsName;
+    use libp2p::gossipsub::{IdentTopic, TopicHash};
+    use libp2p::PeerId;
 
     const IPNS_DEMO: &str = "universal-connectivity";
 
+    /// IPNS over PubSub topics are `/record/` followed by the unpadded base64url
+    /// of `/ipns/` and the binary peer id
+    const IPNS_RECORD_PREFIX: &str = "/record/";
+    const IPNS_KEY_PREFIX: &[u8] = b"/ipns/";
+
+    const BASE64URL: FastPortable =
+        FastPortable::from(&base64::alphabet::URL_SAFE, fast_portable::NO_PAD);
+
     pub fn topic() -> IdentTopic {
         IdentTopic::new(IPNS_DEMO)
     }
+
+    /// The IPNS over PubSub topic of `name`
+    pub fn ipns(name: &IpnsName) -> IdentTopic {
+        IdentTopic::new(format!(
+            "{IPNS_RECORD_PREFIX}{}",
+            base64::encode_engine(ipns_key(name), &BASE64URL)
+        ))
+    }
+
+    /// The IPNS Name of an IPNS over PubSub topic, `None` for other topics
+    pub fn ipns_name(topic: &TopicHash) -> Option<IpnsName> {
+        let encoded = topic.as_str().strip_prefix(IPNS_RECORD_PREFIX)?;
+        ipns_name_of_key(&base64::decode_engine(encoded, &BASE64URL).ok()?)
+    }
+
+    /// The IPNS Name of an IPNS Record key, `/ipns/` followed by the binary peer id
+    pub fn ipns_name_of_key(key: &[u8]) -> Option<IpnsName> {
+        let peer_id = PeerId::from_bytes(key.strip_prefix(IPNS_KEY_PREFIX)?).ok()?;
+        peer_id.to_base58().parse().ok()
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Default)]
@@ -0,0 +1,173 @@
+//! The [libp2p fetch protocol](https://github.com/libp2p/specs/tree/master/fetch), used by
+//! IPNS over PubSub so a new subscriber can ask a peer for the current record of a name
+//! instead of waiting for the next publish.
+//!
+//! Requests and responses are protobuf messages prefixed with their unsigned varint length:
+//!
+//! ```protobuf
+//! message FetchRequest { bytes identifier = 1; }
+//! message FetchResponse {
+//!     enum StatusCode { OK = 0; NOT_FOUND = 1; ERROR = 2; }
+//!     StatusCode status = 1;
+//!     bytes data = 2;
+//! }
+//! ```
+//!
+//! For IPNS the identifier is the record key, `/ipns/` followed by the binary peer id, so it
+//! is declared as bytes rather than a UTF-8 string.
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use ipns_entry::entry::MAX_RECORD_SIZE;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::request_response;
+use prost::Message;
+use std::io;
+
+pub const PROTOCOL_NAME: &[u8] = b"/libp2p/fetch/0.0.1";
+
+/// Records are at most 10 KiB, plus the protobuf framing
+const MAX_MESSAGE_SIZE: usize = MAX_RECORD_SIZE + 64;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FetchRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub identifier: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum FetchStatus {
+    Ok = 0,
+    NotFound = 1,
+    Error = 2,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FetchResponse {
+    /// A [FetchStatus], read with [FetchStatus::from_i32] as unknown codes are errors
+    #[prost(enumeration = "FetchStatus", tag = "1")]
+    pub status: i32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+impl FetchResponse {
+    pub fn ok(data: Vec<u8>) -> Self {
+        FetchResponse {
+            status: FetchStatus::Ok as i32,
+            data,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        FetchResponse {
+            status: FetchStatus::NotFound as i32,
+            data: vec![],
+        }
+    }
+}
+
+fn decode<M: Message + Default>(buf: &[u8]) -> io::Result<M> {
+    M::decode(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchProtocol;
+
+impl ProtocolName for FetchProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FetchCodec;
+
+#[async_trait]
+impl request_response::Codec for FetchCodec {
+    type Protocol = FetchProtocol;
+    type Request = FetchRequest;
+    type Response = FetchResponse;
+
+    async fn read_request<T>(&mut self, _: &FetchProtocol, io: &mut T) -> io::Result<FetchRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        decode(&read_length_prefixed(io, MAX_MESSAGE_SIZE).await?)
+    }
+
+    async fn read_response<T>(&mut self, _: &FetchProtocol, io: &mut T) -> io::Result<FetchResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        decode(&read_length_prefixed(io, MAX_MESSAGE_SIZE).await?)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &FetchProtocol,
+        io: &mut T,
+        request: FetchRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, request.encode_to_vec()).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &FetchProtocol,
+        io: &mut T,
+        response: FetchResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, response.encode_to_vec()).await?;
+        io.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_roundtrip() {
+        // not UTF-8, as IPNS record keys are not
+        let request = FetchRequest {
+            identifier: b"/ipns/\x00\x24\x08\x01\x12\x20\xff".to_vec(),
+        };
+        let encoded = request.encode_to_vec();
+        assert_eq!(&encoded[..2], &[0x0a, 13]);
+        assert_eq!(decode::<FetchRequest>(&encoded).unwrap(), request);
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let ok = FetchResponse::ok(vec![7; 300]);
+        let encoded = ok.encode_to_vec();
+        // status OK is the default and left out, the length is a two byte varint
+        assert_eq!(&encoded[..3], &[0x12, 0xac, 0x02]);
+        assert_eq!(decode::<FetchResponse>(&encoded).unwrap(), ok);
+
+        let not_found = FetchResponse::not_found();
+        assert_eq!(not_found.encode_to_vec(), vec![0x08, 0x01]);
+        assert_eq!(decode::<FetchResponse>(&[0x08, 0x01]).unwrap(), not_found);
+
+        let unknown = decode::<FetchResponse>(&[0x08, 0x09]).unwrap();
+        assert_eq!(FetchStatus::from_i32(unknown.status), None);
+    }
+
+    #[test]
+    fn test_decode_skips_unknown_fields_and_rejects_truncation() {
+        // field 3 varint, field 4 fixed32, then the identifier
+        let buf = [0x18, 0x01, 0x25, 0, 0, 0, 0, 0x0a, 0x01, b'a'];
+        assert_eq!(decode::<FetchRequest>(&buf).unwrap().identifier, b"a");
+
+        assert!(decode::<FetchRequest>(&[0x0a, 0x05, b'a']).is_err());
+        assert!(decode::<FetchResponse>(&[0x08]).is_err());
+    }
+}
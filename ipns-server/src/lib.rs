@@ -1,36 +1,36 @@
 use crate::config::{KADEMLIA_PROTOCOL_NAME, LOCAL_KEY_PATH};
 
-use anyhow::Result;
-use bytes::Bytes;
-use libp2p::multiaddr::{Multiaddr, Protocol};
+use ipns_resolver::cache::RecordCache;
+use libp2p::multiaddr::Multiaddr;
+use libp2p::PeerId;
 use log::warn;
+use network::{Client, NetworkEvent};
+use prometheus_client::registry::Registry;
 use std::error::Error;
-use std::net::Ipv6Addr;
 use std::path::Path;
 use std::path::PathBuf;
-use tokio::sync::{mpsc, oneshot};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use transport::TransportConfig;
 
 pub mod behaviour;
+pub mod certificate;
 pub mod config;
+pub mod fetch;
 pub mod network;
+pub mod reachability;
+pub mod routing;
 pub mod transport;
 
 mod metric_server;
 
-const PORT_WEBRTC: u16 = 9090;
-const PORT_QUIC: u16 = 9091;
-const PORT_TCP: u16 = 9092;
+const RECORD_CACHE_CAPACITY: usize = 1024;
+const RECORD_CACHE_STALE_WHILE_REVALIDATE: Duration = Duration::from_secs(60);
 
-type Responder<T> = oneshot::Sender<T>;
-
-#[derive(Debug, Clone)]
-pub struct ServerResponse {
-    pub address: Bytes,
-}
-
-pub struct Message<T> {
-    pub reply: Responder<T>,
-}
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Default)]
 pub struct Server {
@@ -43,7 +43,7 @@ pub struct Server {
     /// Whether to run the libp2p Kademlia protocol and join the IPFS DHT.
     enable_kademlia: bool,
 
-    /// Whether to run the libp2p Autonat protocol.
+    /// Whether to run the libp2p Autonat protocol, over TCP and QUIC.
     enable_autonat: bool,
 
     /// Address to listen on
@@ -51,6 +51,15 @@ pub struct Server {
 
     /// Address of a remote peer to connect to
     remote_address: Option<Multiaddr>,
+
+    /// IPNS Records by name, shared with resolvers
+    record_cache: Option<RecordCache>,
+
+    /// How long a graceful shutdown may take
+    shutdown_timeout: Duration,
+
+    /// Transports to listen and dial on
+    transport_config: TransportConfig,
 }
 
 impl Server {
@@ -62,6 +71,9 @@ impl Server {
             enable_autonat: false,
             listen_address: None,
             remote_address: None,
+            record_cache: None,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            transport_config: TransportConfig::default(),
         }
     }
 
@@ -75,6 +87,10 @@ impl Server {
         self
     }
 
+    /// Find whether the server is publicly reachable, asking peers connected over TCP or QUIC to
+    /// dial it back. Browsers are never asked. A private server stops announcing the addresses
+    /// peers observe and stops storing the Kademlia records other peers put. It still answers
+    /// Kademlia queries, as libp2p-kad 0.43 has no client mode.
     pub fn enable_autonat(&mut self) -> &mut Server {
         self.enable_autonat = true;
         self
@@ -92,13 +108,29 @@ impl Server {
         self
     }
 
-    /// An example WebRTC peer that will accept connections
-    pub async fn start_with_tokio_executor(
-        &mut self,
-        mut request_recvr: mpsc::Receiver<Message<ServerResponse>>,
-    ) -> Result<(), Box<dyn Error>> {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    /// Share `record_cache` with the server, which adds the IPNS Records it receives over pubsub.
+    /// Defaults to a cache of 1024 names, serving stale records for a minute while revalidating.
+    pub fn with_record_cache(&mut self, record_cache: RecordCache) -> &mut Server {
+        self.record_cache = Some(record_cache);
+        self
+    }
 
+    /// Choose the transports, security and multiplexers.
+    /// Defaults to WebRTC, QUIC and TCP, with noise and yamux.
+    pub fn with_transport_config(&mut self, transport_config: TransportConfig) -> &mut Server {
+        self.transport_config = transport_config;
+        self
+    }
+
+    /// Bound [ServerHandle::shutdown] to `timeout`, 10 seconds by default
+    pub fn with_shutdown_timeout(&mut self, timeout: Duration) -> &mut Server {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Start an example WebRTC peer that will accept connections, in the background of the
+    /// current tokio runtime. Logging is left to the application.
+    pub async fn start(&mut self) -> Result<ServerHandle, Box<dyn Error>> {
         if self.config.is_none() {
             // check if local key exists
             match config::Config::from_file(Path::new(LOCAL_KEY_PATH)) {
@@ -113,7 +145,7 @@ impl Server {
 
         let local_keypair = config::Config::load_keypair(&self.config).await?;
 
-        let transport = transport::create(local_keypair.clone()).await?;
+        let transport = transport::create(local_keypair.clone(), &self.transport_config).await?;
 
         let mut behaviour_builder = behaviour::BehaviourBuilder::new(local_keypair.clone());
 
@@ -121,57 +153,117 @@ impl Server {
             behaviour_builder.with_kademlia(Some(KADEMLIA_PROTOCOL_NAME));
         };
 
+        if self.enable_autonat {
+            if self.transport_config.has_native() {
+                behaviour_builder = behaviour_builder.with_autonat();
+            } else {
+                warn!("AutoNAT needs TCP or QUIC, not enabled");
+            }
+        }
+
         let behaviour = behaviour_builder.build();
 
+        let mut registry = Registry::default();
+        let record_cache = self.record_cache.get_or_insert_with(|| {
+            let mut cache = RecordCache::new(RECORD_CACHE_CAPACITY);
+            cache.stale_while_revalidate(RECORD_CACHE_STALE_WHILE_REVALIDATE);
+            cache
+        });
+        record_cache.set_observer(Arc::new(metric_server::ResolveCacheMetrics::register(
+            &mut registry,
+        )));
+        let reachability_metrics = metric_server::ReachabilityMetrics::register(&mut registry);
+
+        let metrics_path = self.metrics_path.clone();
+        let metrics_handle = async_std::task::spawn(async move {
+            if let Err(e) = metric_server::run(registry, metrics_path).await {
+                warn!("Metrics server failed: {e}");
+            }
+        });
+
         // Create networks with behaviours, transports, and PeerId
         // Each network is isolated by the Kad::protocol_name in the behaviour
         // TODO: Each network operator can manage the pubsub topics too
 
-        let (mut network_client, mut network_events, network_event_loop) =
-            network::new(transport, behaviour, local_keypair.public().into()).await?;
+        let peer_id = local_keypair.public().to_peer_id();
+        let (mut network_client, network_event_loop) =
+            network::new(transport, behaviour, peer_id, record_cache.clone()).await?;
 
-        // Spawn the network task for it to run in the background.
-        let network_handle = tokio::spawn(async move { network_event_loop.run().await });
-
-        // Handle any network events
+        let mut events = network_client.events();
         tokio::spawn(async move {
             loop {
-                match network_events.recv().await {
-                    Some(network::NetworkEvent::NewListenAddr { address }) => {
-                        // padd message up to main
-                        if let Some(message) = request_recvr.recv().await {
-                            let _ = message.reply.send(ServerResponse {
-                                address: Bytes::from(address.to_string()),
-                            });
-                        }
-                    }
-                    evt => {
-                        eprintln!("Network event: {:?}", evt);
-                    }
+                match events.recv().await {
+                    Ok(event) => reachability_metrics.record(&event),
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
                 }
             }
         });
 
-        let address_webrtc = Multiaddr::from(Ipv6Addr::UNSPECIFIED)
-            .with(Protocol::Udp(PORT_WEBRTC))
-            .with(Protocol::WebRTCDirect);
-
-        let address_quic = Multiaddr::from(Ipv6Addr::UNSPECIFIED)
-            .with(Protocol::Udp(PORT_QUIC))
-            .with(Protocol::QuicV1);
-
-        let address_tcp = Multiaddr::from(Ipv6Addr::UNSPECIFIED).with(Protocol::Tcp(PORT_TCP));
+        // Spawn the network task for it to run in the background.
+        let network_handle = tokio::spawn(async move { network_event_loop.run().await });
 
-        for addr in [address_webrtc, address_quic, address_tcp] {
+        for addr in self.transport_config.listen_addresses() {
             network_client
                 .start_listening(addr)
                 .await
-                .expect("Listening not to fail.");
+                .map_err(|e| e as Box<dyn Error>)?;
         }
 
-        network_handle.await?;
-        println!("EOF");
+        Ok(ServerHandle {
+            client: network_client,
+            peer_id,
+            network_handle,
+            metrics_handle,
+            shutdown_timeout: self.shutdown_timeout,
+        })
+    }
+}
+
+/// A running [Server], from [Server::start]
+pub struct ServerHandle {
+    client: Client,
+    peer_id: PeerId,
+    network_handle: JoinHandle<()>,
+    metrics_handle: async_std::task::JoinHandle<()>,
+    shutdown_timeout: Duration,
+}
+
+impl ServerHandle {
+    /// The network client, to publish, resolve or listen on more addresses
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// The network events from now on
+    pub fn events(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.client.events()
+    }
+
+    /// The addresses the server is listening on, with its peer id.
+    /// Empty if the network has stopped.
+    pub async fn listen_addresses(&self) -> Vec<Multiaddr> {
+        self.client
+            .clone()
+            .listen_addresses()
+            .await
+            .unwrap_or_default()
+    }
+
+    pub fn local_peer_id(&self) -> PeerId {
+        self.peer_id
+    }
 
+    /// Shut down gracefully, within the timeout set by [Server::with_shutdown_timeout]:
+    /// stop listening, finish the lookups in flight, unsubscribe from all topics and close all
+    /// connections, then stop the metrics server.
+    ///
+    /// The record cache and the Kademlia record store are in memory, so there is nothing to
+    /// flush to disk.
+    pub async fn shutdown(mut self) -> Result<(), Box<dyn Error>> {
+        self.client.shutdown(self.shutdown_timeout).await;
+        self.network_handle.await?;
+        self.metrics_handle.cancel().await;
         Ok(())
     }
 }
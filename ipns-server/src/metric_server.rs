@@ -1,4 +1,10 @@
+use crate::network::NetworkEvent;
+use crate::reachability::Reachability;
+use ipns_resolver::cache::{CacheObserver, CacheOutcome};
 use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 
 use std::sync::{Arc, Mutex};
@@ -31,3 +37,96 @@ pub async fn run(registry: Registry, path: String) -> std::result::Result<(), st
 struct State {
     registry: Arc<Mutex<Registry>>,
 }
+
+/// IPNS Record cache lookups, by outcome
+#[derive(Default)]
+pub struct ResolveCacheMetrics {
+    hits: Counter,
+    stale_hits: Counter,
+    misses: Counter,
+    evictions: Counter,
+}
+
+impl ResolveCacheMetrics {
+    pub fn register(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+        let sub_registry = registry.sub_registry_with_prefix("ipns_resolve_cache");
+        sub_registry.register(
+            "hits",
+            "Lookups served a fresh record",
+            metrics.hits.clone(),
+        );
+        sub_registry.register(
+            "stale_hits",
+            "Lookups served a stale record while it was revalidated",
+            metrics.stale_hits.clone(),
+        );
+        sub_registry.register(
+            "misses",
+            "Lookups fetched from routing",
+            metrics.misses.clone(),
+        );
+        sub_registry.register(
+            "evictions",
+            "Records evicted to stay within capacity",
+            metrics.evictions.clone(),
+        );
+        metrics
+    }
+}
+
+impl CacheObserver for ResolveCacheMetrics {
+    fn lookup(&self, outcome: CacheOutcome) {
+        match outcome {
+            CacheOutcome::Hit => self.hits.inc(),
+            CacheOutcome::Stale => self.stale_hits.inc(),
+            CacheOutcome::Miss => self.misses.inc(),
+        };
+    }
+
+    fn evicted(&self) {
+        self.evictions.inc();
+    }
+}
+
+/// The reachability found by AutoNAT, and whether it lets Kademlia store remote records
+#[derive(Default)]
+pub struct ReachabilityMetrics {
+    status: Family<Vec<(String, String)>, Gauge>,
+    kademlia_stores_remote_records: Gauge,
+}
+
+impl ReachabilityMetrics {
+    pub fn register(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+        registry.register(
+            "ipns_reachability",
+            "1 for the current reachability status, unknown, public or private",
+            metrics.status.clone(),
+        );
+        registry.register(
+            "ipns_kademlia_stores_remote_records",
+            "1 when Kademlia stores the records other peers put, 0 when private",
+            metrics.kademlia_stores_remote_records.clone(),
+        );
+        metrics.set(&Reachability::default());
+        metrics
+    }
+
+    pub fn record(&self, event: &NetworkEvent) {
+        if let NetworkEvent::ReachabilityChanged { reachability } = event {
+            self.set(reachability);
+        }
+    }
+
+    fn set(&self, reachability: &Reachability) {
+        let current = reachability.to_string();
+        for status in ["unknown", "public", "private"] {
+            self.status
+                .get_or_create(&vec![("status".to_string(), status.to_string())])
+                .set((status == current) as i64);
+        }
+        self.kademlia_stores_remote_records
+            .set(reachability.stores_remote_records() as i64);
+    }
+}
@@ -1,75 +1,263 @@
 use crate::behaviour::{Behaviour, ComposedEvent};
 use crate::config::topic;
+use crate::fetch::{FetchRequest, FetchResponse, FetchStatus};
+use crate::reachability::Reachability;
+use crate::routing::{ipns_key, RoutedRecord, RouterSource};
+use crate::transport::TransportKind;
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::time;
+use ipns_resolver::cache::RecordCache;
 use libp2p::core::ConnectedPoint;
 // use config::Config;
-use libp2p::core::{muxing::StreamMuxerBox, transport::Boxed};
+use libp2p::core::{muxing::StreamMuxerBox, transport::Boxed, transport::ListenerId};
+use libp2p::gossipsub::TopicHash;
+use libp2p::kad::record::Key;
+use libp2p::kad::store::RecordStore;
+use libp2p::kad::{
+    GetRecordOk, GetRecordResult, InboundRequest, KademliaEvent, PeerRecord, PutRecordResult,
+    QueryId, QueryResult, Quorum, Record,
+};
+use libp2p::multiaddr::multihash::Multihash;
 use libp2p::multiaddr::Protocol;
-use libp2p::swarm::{AddressRecord, AddressScore, Swarm, SwarmBuilder, SwarmEvent};
-use libp2p::{identify, Multiaddr, PeerId};
+use libp2p::request_response::{self, RequestId};
+use libp2p::swarm::{
+    AddAddressResult, AddressRecord, AddressScore, Swarm, SwarmBuilder, SwarmEvent, THandlerErr,
+};
+use libp2p::{autonat, identify, Multiaddr, PeerId};
 use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::error::Error;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc::{self, Receiver};
-use tokio::sync::oneshot;
+use std::{fmt, io};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio_stream::StreamExt;
 
 mod config;
-mod types;
 
 const TICK_INTERVAL: Duration = Duration::from_secs(15);
 
+/// Events kept for subscribers which fall behind
+const EVENT_CAPACITY: usize = 64;
+
+/// Time for unsubscriptions to reach peers before connections are closed
+const UNSUBSCRIBE_GRACE: Duration = Duration::from_millis(500);
+
 /// Interact with the network:
 /// Enables users to spawn a thread on separate networks within the same add_explicit_peer
 ///
 /// - Network Client: Interact with the netowrk by sending
 /// - Network Event Loop: Start the network event loop
+///
+/// IPNS Records received over pubsub are added to `record_cache`.
+/// [NetworkEvent]s are broadcast to every receiver from [Client::events].
 pub async fn new(
     transport: Boxed<(PeerId, StreamMuxerBox)>,
     behaviour: Behaviour,
     peer_id: PeerId,
-) -> Result<(Client, Receiver<NetworkEvent>, EventLoop), Box<dyn Error>> {
+    record_cache: RecordCache,
+) -> Result<(Client, EventLoop), Box<dyn Error>> {
     let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, peer_id).build();
 
     let (command_sender, command_receiver) = mpsc::channel(8);
-    let (event_sender, event_receiver) = mpsc::channel(8);
+    let (event_sender, _) = broadcast::channel(EVENT_CAPACITY);
 
     Ok((
         Client {
             sender: command_sender,
+            events: event_sender.clone(),
         },
-        event_receiver,
-        EventLoop::new(swarm, command_receiver, event_sender),
+        EventLoop::new(swarm, command_receiver, event_sender, record_cache),
     ))
 }
 
 #[derive(Clone)]
 pub struct Client {
     sender: mpsc::Sender<Command>,
+    events: broadcast::Sender<NetworkEvent>,
+}
+
+/// Returned by the [Client] methods once the network event loop has stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkShutDown;
+
+impl fmt::Display for NetworkShutDown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Network shut down")
+    }
 }
 
+impl Error for NetworkShutDown {}
+
 impl Client {
+    /// Receive the network events from now on. A receiver which falls more than 64 events
+    /// behind skips the oldest, and is told how many with `RecvError::Lagged`.
+    pub fn events(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.events.subscribe()
+    }
+
+    /// The addresses the network is listening on, with the local peer id
+    pub async fn listen_addresses(&mut self) -> Result<Vec<Multiaddr>, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        self.request(Command::ListenAddresses { sender }, receiver)
+            .await
+    }
+
+    /// Send `command` to the event loop and wait for its answer on `receiver`.
+    /// Fails with [NetworkShutDown] once the event loop has stopped.
+    async fn request<T>(
+        &mut self,
+        command: Command,
+        receiver: oneshot::Receiver<T>,
+    ) -> Result<T, Box<dyn Error + Send>> {
+        let shut_down = || Box::new(NetworkShutDown) as Box<dyn Error + Send>;
+        self.sender.send(command).await.map_err(|_| shut_down())?;
+        receiver.await.map_err(|_| shut_down())
+    }
+
+    /// Stop the network event loop, see [EventLoop::shutdown]
+    pub(crate) async fn shutdown(&mut self, timeout: Duration) {
+        // the event loop may already have stopped
+        let _ = self.sender.send(Command::Shutdown { timeout }).await;
+    }
+
     /// Listen for incoming connections on the given address.
     pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
-        self.sender
-            .send(Command::StartListening { addr, sender })
-            .await
-            .expect("Command receiver not to be dropped.");
-        receiver.await.expect("Sender not to be dropped.")
+        self.request(Command::StartListening { addr, sender }, receiver)
+            .await?
+    }
+
+    /// The values of the Kademlia records found for `key`, empty if there are none.
+    /// Fails if Kademlia is not enabled.
+    pub async fn get_record(
+        &mut self,
+        key: Vec<u8>,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        self.request(Command::GetRecord { key, sender }, receiver)
+            .await?
+    }
+
+    /// Publish `record` for `name` over pubsub, and put it in the DHT when Kademlia is enabled.
+    /// Completes once the DHT put has reached a peer. The record is also served to fetch requests.
+    pub async fn publish_ipns(
+        &mut self,
+        name: IpnsName,
+        record: IpnsEntry,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        let command = Command::PublishIpns {
+            name,
+            record,
+            sender,
+        };
+        self.request(command, receiver).await?
+    }
+
+    /// Subscribe to the IPNS over PubSub topic of `name`, returning whether it is a new
+    /// subscription. The current record is then fetched from peers already subscribed.
+    pub async fn subscribe_ipns(&mut self, name: IpnsName) -> Result<bool, Box<dyn Error + Send>> {
+        let (sender, receiver) = oneshot::channel();
+        self.request(Command::SubscribeIpns { name, sender }, receiver)
+            .await?
     }
 }
 
+type RecordsSender = oneshot::Sender<Result<Vec<Vec<u8>>, Box<dyn Error + Send>>>;
+type PublishSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+
 #[derive(Debug)]
 enum Command {
     StartListening {
         addr: Multiaddr,
         sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
     },
+    GetRecord {
+        key: Vec<u8>,
+        sender: RecordsSender,
+    },
+    SubscribeIpns {
+        name: IpnsName,
+        sender: oneshot::Sender<Result<bool, Box<dyn Error + Send>>>,
+    },
+    PublishIpns {
+        name: IpnsName,
+        record: IpnsEntry,
+        sender: PublishSender,
+    },
+    ListenAddresses {
+        sender: oneshot::Sender<Vec<Multiaddr>>,
+    },
+    Shutdown {
+        timeout: Duration,
+    },
 }
 
-#[derive(Debug)]
+/// Events from the network, broadcast to every receiver from [Client::events]
+#[derive(Debug, Clone)]
 pub enum NetworkEvent {
+    /// A public address the network listens on, with the local peer id
     NewListenAddr { address: Multiaddr },
+    /// The first connection to a peer was established
+    PeerConnected {
+        peer_id: PeerId,
+        address: Multiaddr,
+        transport: TransportKind,
+    },
+    /// The last connection to a peer was closed
+    PeerDisconnected {
+        peer_id: PeerId,
+        transport: TransportKind,
+        cause: Option<String>,
+    },
+    /// An IPNS Record was received from `peer`, and what was made of it.
+    /// The sequence is `None` if the record could not be decoded.
+    RecordReceived {
+        source: RouterSource,
+        peer: Option<PeerId>,
+        name: IpnsName,
+        sequence: Option<u64>,
+        verdict: RecordVerdict,
+    },
+    /// A [Client::publish_ipns] finished
+    PublishCompleted {
+        name: IpnsName,
+        sequence: u64,
+        error: Option<String>,
+    },
+    /// A [Client::get_record] DHT lookup finished. IPNS keys decode with
+    /// [topic::ipns_name_of_key](crate::config::topic::ipns_name_of_key).
+    ResolveCompleted {
+        key: Vec<u8>,
+        records: usize,
+        error: Option<String>,
+    },
+    /// A peer subscribed to a pubsub topic
+    Subscribed { peer_id: PeerId, topic: TopicHash },
+    /// A peer unsubscribed from a pubsub topic
+    Unsubscribed { peer_id: PeerId, topic: TopicHash },
+    /// An address was added to the external addresses announced to peers
+    ExternalAddrAdded { address: Multiaddr },
+    /// An external address expired
+    ExternalAddrRemoved { address: Multiaddr },
+    /// The WebRTC certhashes in the listen addresses changed: the successor of the certificate
+    /// was announced, or the certificate was rotated
+    CerthashesChanged { certhashes: Vec<Multihash> },
+    /// AutoNAT found the server to be reachable, or not, which decides the Kademlia mode and the
+    /// external addresses announced
+    ReachabilityChanged { reachability: Reachability },
+}
+
+/// What was made of a received IPNS Record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordVerdict {
+    /// Valid, and newer than the record held, if any
+    Accepted,
+    /// Valid, but no newer than the record held
+    Outdated,
+    /// Not a valid IPNS Record for the name, or expired
+    Invalid(String),
 }
 
 pub struct EventLoop {
@@ -77,14 +265,29 @@ pub struct EventLoop {
     now: Instant,
     swarm: Swarm<Behaviour>,
     command_receiver: mpsc::Receiver<Command>,
-    event_sender: mpsc::Sender<NetworkEvent>,
+    event_sender: broadcast::Sender<NetworkEvent>,
+    record_cache: RecordCache,
+    /// The key and Kademlia record values found so far, by query
+    pending_get_record: HashMap<QueryId, (Vec<u8>, Vec<Vec<u8>>, RecordsSender)>,
+    /// Kademlia puts of published records, by query
+    pending_publish: HashMap<QueryId, (IpnsName, u64, PublishSender)>,
+    /// Outbound fetch requests, by the IPNS Name asked for
+    pending_fetch: HashMap<RequestId, IpnsName>,
+    listeners: Vec<ListenerId>,
+    /// The WebRTC certhashes in the listen addresses
+    certhashes: Vec<Multihash>,
+    reachability: Reachability,
+    /// The external addresses added as peers observed or AutoNAT confirmed them, withdrawn when
+    /// private
+    observed_addresses: Vec<Multiaddr>,
 }
 
 impl EventLoop {
     fn new(
         swarm: Swarm<Behaviour>,
         command_receiver: mpsc::Receiver<Command>,
-        event_sender: mpsc::Sender<NetworkEvent>,
+        event_sender: broadcast::Sender<NetworkEvent>,
+        record_cache: RecordCache,
     ) -> Self {
         Self {
             tick: futures_timer::Delay::new(TICK_INTERVAL),
@@ -92,6 +295,14 @@ impl EventLoop {
             swarm,
             command_receiver,
             event_sender,
+            record_cache,
+            pending_get_record: Default::default(),
+            pending_publish: Default::default(),
+            pending_fetch: Default::default(),
+            listeners: Default::default(),
+            certhashes: Default::default(),
+            reachability: Default::default(),
+            observed_addresses: Default::default(),
         }
     }
 
@@ -102,6 +313,7 @@ impl EventLoop {
             tokio::select! {
                 event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite.")).await,
                 command = self.command_receiver.recv() => match command {
+                    Some(Command::Shutdown { timeout }) => return self.shutdown(timeout).await,
                     Some(c) => self.handle_command(c).await,
                     // Command channel closed, thus shutting down the network event loop.
                     None=>  return,
@@ -112,6 +324,90 @@ impl EventLoop {
         }
     }
 
+    /// Shut down gracefully within `timeout`:
+    /// - stop listening, so no new connections are accepted
+    /// - finish the commands already sent, and the lookups, publishes and fetches in flight
+    /// - unsubscribe from all pubsub topics
+    /// - close all connections
+    ///
+    /// Whatever is still pending at the deadline fails.
+    async fn shutdown(&mut self, timeout: Duration) {
+        info!("Shutting down the network within {timeout:?}");
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+
+        self.stop_listening();
+
+        self.command_receiver.close();
+        let mut commands_done = false;
+        while !commands_done
+            || !self.pending_get_record.is_empty()
+            || !self.pending_publish.is_empty()
+            || !self.pending_fetch.is_empty()
+        {
+            tokio::select! {
+                event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite.")).await,
+                command = self.command_receiver.recv(), if !commands_done => match command {
+                    Some(c) => self.handle_command(c).await,
+                    None => commands_done = true,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+        // in case one of those commands started listening
+        self.stop_listening();
+
+        let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+        let topics: Vec<_> = gossipsub.topics().cloned().collect();
+        for topic in topics {
+            // all our topics are identity hashed
+            if let Err(e) =
+                gossipsub.unsubscribe(&libp2p::gossipsub::IdentTopic::new(topic.as_str()))
+            {
+                debug!("Failed to unsubscribe from {topic}: {e}");
+            }
+        }
+        let grace = tokio::time::sleep(UNSUBSCRIBE_GRACE);
+        tokio::pin!(grace);
+        loop {
+            tokio::select! {
+                event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite.")).await,
+                _ = &mut grace => break,
+                _ = &mut deadline => break,
+            }
+        }
+
+        let peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        for peer in peers {
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        while self.swarm.connected_peers().next().is_some() {
+            tokio::select! {
+                event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite.")).await,
+                _ = &mut deadline => break,
+            }
+        }
+
+        let shut_down = || Box::new(NetworkShutDown);
+        for (_, (_, _, sender)) in self.pending_get_record.drain() {
+            let _ = sender.send(Err(shut_down()));
+        }
+        for (_, (_, _, sender)) in self.pending_publish.drain() {
+            let _ = sender.send(Err(shut_down()));
+        }
+        if !self.pending_fetch.is_empty() || self.swarm.connected_peers().next().is_some() {
+            warn!("Network shut down at the deadline, abandoning pending requests and connections");
+        }
+        self.pending_fetch.clear();
+        info!("Network shut down");
+    }
+
+    fn stop_listening(&mut self) {
+        for listener in self.listeners.drain(..) {
+            self.swarm.remove_listener(listener);
+        }
+    }
+
     async fn handle_tick(&mut self) {
         eprintln!("🕒 Ticking at {:?}", self.now.elapsed());
         self.tick.reset(TICK_INTERVAL);
@@ -148,7 +444,7 @@ impl EventLoop {
         }
     }
 
-    async fn handle_event(&mut self, event: SwarmEvent<ComposedEvent, types::ComposedErr>) {
+    async fn handle_event(&mut self, event: SwarmEvent<ComposedEvent, THandlerErr<Behaviour>>) {
         match event {
             SwarmEvent::NewListenAddr { address, .. } => {
                 let mut addr_handler = || {
@@ -157,37 +453,63 @@ impl EventLoop {
                         .with(Protocol::P2p((*self.swarm.local_peer_id()).into()));
 
                     info!("Listen p2p address: {p2p_addr:?}");
-                    self.swarm
-                        .add_external_address(p2p_addr.clone(), AddressScore::Infinite);
+                    self.add_external_address(p2p_addr.clone());
 
                     // pass the address back to the other task, for display, etc.
-                    self.event_sender
-                        .send(NetworkEvent::NewListenAddr { address: p2p_addr })
+                    self.emit(NetworkEvent::NewListenAddr { address: p2p_addr });
                 };
                 // Protocol::Ip is the first item in the address vector
                 match address.iter().next().unwrap() {
                     Protocol::Ip6(ip6) => {
                         // Only add our globally available IPv6 addresses to the external addresses list.
                         if !ip6.is_loopback() && !ip6.is_unspecified() {
-                            addr_handler().await.expect("Receiver not to be dropped.");
+                            addr_handler();
                         }
                     }
                     Protocol::Ip4(ip4) => {
                         if !ip4.is_loopback() && !ip4.is_unspecified() {
-                            addr_handler().await.expect("Receiver not to be dropped.");
+                            addr_handler();
                         }
                     }
                     _ => {}
                 }
+                self.update_certhashes();
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                let p2p_addr = address.with(Protocol::P2p((*self.swarm.local_peer_id()).into()));
+                info!("Expired listen address: {p2p_addr}");
+                if self.swarm.remove_external_address(&p2p_addr) {
+                    self.emit(NetworkEvent::ExternalAddrRemoved { address: p2p_addr });
+                }
+                self.update_certhashes();
             }
             SwarmEvent::ConnectionEstablished {
                 peer_id,
-                endpoint: ConnectedPoint::Listener { send_back_addr, .. },
+                endpoint,
+                num_established,
                 established_in,
                 ..
             } => {
-                eprintln!("✔️  Connection Established to {peer_id} in {established_in:?} on {send_back_addr}");
+                let address = endpoint.get_remote_address().clone();
+                if let ConnectedPoint::Listener { send_back_addr, .. } = &endpoint {
+                    eprintln!("✔️  Connection Established to {peer_id} in {established_in:?} on {send_back_addr}");
+                }
                 info!("Connected to {peer_id}");
+
+                if num_established.get() == 1 {
+                    let transport = TransportKind::of(&address);
+                    // only native peers are asked to dial us back, probing browsers breaks WebRTC
+                    if transport.is_native() {
+                        if let Some(autonat) = self.swarm.behaviour_mut().autonat.as_mut() {
+                            autonat.add_server(peer_id, None);
+                        }
+                    }
+                    self.emit(NetworkEvent::PeerConnected {
+                        peer_id,
+                        transport,
+                        address,
+                    });
+                }
             }
 
             SwarmEvent::OutgoingConnectionError { peer_id, error } => {
@@ -214,8 +536,24 @@ impl EventLoop {
                     }
                 }
             }
-            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                num_established,
+                cause,
+            } => {
                 warn!("Connection to {peer_id} closed: {cause:?}");
+
+                if num_established == 0 {
+                    if let Some(autonat) = self.swarm.behaviour_mut().autonat.as_mut() {
+                        autonat.remove_server(&peer_id);
+                    }
+                    self.emit(NetworkEvent::PeerDisconnected {
+                        peer_id,
+                        transport: TransportKind::of(endpoint.get_remote_address()),
+                        cause: cause.map(|cause| cause.to_string()),
+                    });
+                }
             }
             SwarmEvent::Behaviour(ComposedEvent::Relay(e)) => {
                 debug!("{:?}", e);
@@ -223,16 +561,22 @@ impl EventLoop {
             SwarmEvent::Behaviour(ComposedEvent::Gossipsub(
                 libp2p::gossipsub::Event::Message {
                     message_id: _,
-                    propagation_source: _,
+                    propagation_source,
                     message,
                 },
-            )) => {
-                eprintln!(
+            )) => match topic::ipns_name(&message.topic) {
+                Some(name) => self.handle_ipns_record(
+                    name,
+                    &message.data,
+                    RouterSource::PubSub,
+                    propagation_source,
+                ),
+                None => eprintln!(
                     "📨 Received message from {:?}: {}",
                     message.source,
-                    String::from_utf8(message.data).unwrap()
-                );
-            }
+                    String::from_utf8_lossy(&message.data)
+                ),
+            },
             SwarmEvent::Behaviour(ComposedEvent::Gossipsub(
                 libp2p::gossipsub::Event::Subscribed { peer_id, topic },
             )) => {
@@ -241,6 +585,24 @@ impl EventLoop {
                     .behaviour_mut()
                     .gossipsub
                     .add_explicit_peer(&peer_id);
+                self.emit(NetworkEvent::Subscribed {
+                    peer_id,
+                    topic: topic.clone(),
+                });
+
+                if let Some(name) = topic::ipns_name(&topic) {
+                    // a peer joined a name we follow before we have its record
+                    let subscribed = self
+                        .swarm
+                        .behaviour()
+                        .gossipsub
+                        .topics()
+                        .any(|t| *t == topic);
+                    if subscribed && self.record_cache.get(&name).is_none() {
+                        self.fetch_ipns_record(peer_id, name);
+                    }
+                    return;
+                }
 
                 // publish a message
                 // get the last 4 chars of the peer_id as slice:
@@ -259,6 +621,12 @@ impl EventLoop {
                     error!("Failed to publish periodic message: {err}")
                 }
             }
+            SwarmEvent::Behaviour(ComposedEvent::Gossipsub(
+                libp2p::gossipsub::Event::Unsubscribed { peer_id, topic },
+            )) => {
+                debug!("{peer_id} unsubscribed from {topic}");
+                self.emit(NetworkEvent::Unsubscribed { peer_id, topic });
+            }
             SwarmEvent::Behaviour(ComposedEvent::Identify(e)) => {
                 debug!("ComposedEvent::Identify {:?}", e);
 
@@ -294,8 +662,7 @@ impl EventLoop {
                 {
                     debug!("identify::Event::Received observed_addr: {}", observed_addr);
 
-                    self.swarm
-                        .add_external_address(observed_addr, AddressScore::Infinite);
+                    self.handle_observed_address(observed_addr);
 
                     // TODO: This needs to be improved to only add the address tot he matching protocol name , assuming there is more than one per kad (which there shouldn't be, but there could be)
                     if protocols.iter().any(|p| {
@@ -334,9 +701,31 @@ impl EventLoop {
                     }
                 }
             }
+            SwarmEvent::Behaviour(ComposedEvent::Autonat(autonat::Event::StatusChanged {
+                new,
+                ..
+            })) => self.set_reachability(new.into()),
+            SwarmEvent::Behaviour(ComposedEvent::Fetch(event)) => self.handle_fetch(event),
             SwarmEvent::Behaviour(ComposedEvent::Kademlia(event)) => {
                 debug!("Kademlia event: {:?}", event);
                 // metrics.record(&event);
+                match event {
+                    KademliaEvent::OutboundQueryProgressed {
+                        id,
+                        result: QueryResult::GetRecord(result),
+                        step,
+                        ..
+                    } => self.handle_get_record(id, result, step.last),
+                    KademliaEvent::OutboundQueryProgressed {
+                        id,
+                        result: QueryResult::PutRecord(result),
+                        ..
+                    } => self.handle_put_record(id, result),
+                    KademliaEvent::InboundRequest { request } => {
+                        self.handle_inbound_kademlia(request)
+                    }
+                    _ => {}
+                }
             }
             event => {
                 debug!("Other type of event: {:?}", event);
@@ -345,11 +734,423 @@ impl EventLoop {
         }
     }
 
+    /// Collect the records found by a Kademlia query, replying once it is done
+    fn handle_get_record(&mut self, id: QueryId, result: GetRecordResult, last: bool) {
+        let Some((_, records, _)) = self.pending_get_record.get_mut(&id) else {
+            return;
+        };
+
+        let error = match result {
+            Ok(GetRecordOk::FoundRecord(PeerRecord { peer, record })) => {
+                records.push(record.value.clone());
+                if let Some(name) = topic::ipns_name_of_key(record.key.as_ref()) {
+                    self.handle_kademlia_record(name, &record.value, peer);
+                }
+                if !last {
+                    return;
+                }
+                None
+            }
+            Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => None,
+            Err(libp2p::kad::GetRecordError::NotFound { .. }) => None,
+            // records found before a timeout are still returned
+            Err(e) if records.is_empty() => Some(e),
+            Err(e) => {
+                debug!("Kademlia query {id:?} ended: {e}");
+                None
+            }
+        };
+
+        let (key, records, sender) = self.pending_get_record.remove(&id).expect("pending query");
+        self.emit(NetworkEvent::ResolveCompleted {
+            key,
+            records: records.len(),
+            error: error.as_ref().map(|e| e.to_string()),
+        });
+        let _ = match error {
+            Some(e) => sender.send(Err(Box::new(e))),
+            None => sender.send(Ok(records)),
+        };
+    }
+
+    /// Report a record found in the DHT. It is validated and compared with the cached record,
+    /// but not cached, the cache holds records received over pubsub.
+    fn handle_kademlia_record(&self, name: IpnsName, data: &[u8], peer: Option<PeerId>) {
+        let now = time::now();
+        let (sequence, verdict) = match IpnsEntry::from_bytes(data) {
+            Ok(record) => {
+                match RoutedRecord::validate(&name, record, RouterSource::Kademlia, now) {
+                    Ok(routed) => {
+                        let held = self.record_cache.get_at(&name, now).and_then(|held| {
+                            RoutedRecord::validate(&name, held, RouterSource::Local, now).ok()
+                        });
+                        let verdict = match held {
+                            Some(held) if !routed.is_better_than(&held) => RecordVerdict::Outdated,
+                            _ => RecordVerdict::Accepted,
+                        };
+                        (Some(routed.sequence), verdict)
+                    }
+                    Err(e) => (None, RecordVerdict::Invalid(e.to_string())),
+                }
+            }
+            Err(e) => (None, RecordVerdict::Invalid(e.to_string())),
+        };
+        self.emit(NetworkEvent::RecordReceived {
+            source: RouterSource::Kademlia,
+            peer,
+            name,
+            sequence,
+            verdict,
+        });
+    }
+
+    fn handle_put_record(&mut self, id: QueryId, result: PutRecordResult) {
+        if let Some((name, sequence, sender)) = self.pending_publish.remove(&id) {
+            let error = result.err().map(|e| Box::new(e) as Box<dyn Error + Send>);
+            self.publish_completed(name, sequence, sender, error);
+        }
+    }
+
+    /// Cache `record` so it is served to fetch requests, publish it over pubsub, and put it in
+    /// the DHT when Kademlia is enabled
+    fn publish_ipns(&mut self, name: IpnsName, record: IpnsEntry, sender: PublishSender) {
+        // checks the size, then validates the record
+        let validated = record.try_to_bytes().and_then(|data| {
+            self.record_cache.insert(name, record.clone())?;
+            Ok((data, record.decode_data()?.sequence))
+        });
+        let (data, sequence) = match validated {
+            Ok(validated) => validated,
+            Err(e) => {
+                let e = io::Error::new(io::ErrorKind::InvalidInput, e.to_string());
+                let _ = sender.send(Err(Box::new(e)));
+                return;
+            }
+        };
+
+        if let Err(e) = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic::ipns(&name), data.clone())
+        {
+            // peers subscribing later fetch the record
+            debug!("Failed to publish {name} over pubsub: {e}");
+        }
+
+        let Some(kademlia) = self.swarm.behaviour_mut().kademlia.as_mut() else {
+            return self.publish_completed(name, sequence, sender, None);
+        };
+        match kademlia.put_record(Record::new(Key::new(&ipns_key(&name)), data), Quorum::One) {
+            Ok(id) => {
+                self.pending_publish.insert(id, (name, sequence, sender));
+            }
+            Err(e) => self.publish_completed(name, sequence, sender, Some(Box::new(e))),
+        }
+    }
+
+    fn publish_completed(
+        &mut self,
+        name: IpnsName,
+        sequence: u64,
+        sender: PublishSender,
+        error: Option<Box<dyn Error + Send>>,
+    ) {
+        self.emit(NetworkEvent::PublishCompleted {
+            name,
+            sequence,
+            error: error.as_ref().map(|e| e.to_string()),
+        });
+        let _ = sender.send(error.map_or(Ok(()), Err));
+    }
+
+    /// Serve fetch requests from the record cache, and cache fetched records
+    fn handle_fetch(&mut self, event: request_response::Event<FetchRequest, FetchResponse>) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let response = topic::ipns_name_of_key(&request.identifier)
+                    .and_then(|name| self.record_cache.get(&name))
+                    .and_then(|record| record.try_to_bytes().ok())
+                    .map_or_else(FetchResponse::not_found, FetchResponse::ok);
+                debug!("Fetch request from {peer}: {:?}", response.status());
+
+                if self
+                    .swarm
+                    .behaviour_mut()
+                    .fetch
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    debug!("Fetch request from {peer} closed before the response");
+                }
+            }
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    },
+            } => {
+                let Some(name) = self.pending_fetch.remove(&request_id) else {
+                    return;
+                };
+                // unknown status codes are errors
+                match FetchStatus::from_i32(response.status).unwrap_or(FetchStatus::Error) {
+                    FetchStatus::Ok => {
+                        self.handle_ipns_record(name, &response.data, RouterSource::Fetch, peer)
+                    }
+                    status => debug!("Fetch of {name} from {peer}: {status:?}"),
+                }
+            }
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                if let Some(name) = self.pending_fetch.remove(&request_id) {
+                    debug!("Fetch of {name} from {peer} failed: {error}");
+                }
+            }
+            event => debug!("Fetch event: {event:?}"),
+        }
+    }
+
+    /// Ask `peer` for its record of `name`
+    fn fetch_ipns_record(&mut self, peer: PeerId, name: IpnsName) {
+        let request = FetchRequest {
+            identifier: ipns_key(&name),
+        };
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .fetch
+            .send_request(&peer, request);
+        self.pending_fetch.insert(request_id, name);
+    }
+
+    /// Cache an IPNS Record received over pubsub or fetched from a pubsub peer,
+    /// replacing older sequences
+    fn handle_ipns_record(
+        &mut self,
+        name: IpnsName,
+        data: &[u8],
+        source: RouterSource,
+        peer: PeerId,
+    ) {
+        let (sequence, verdict) = match IpnsEntry::from_bytes(data) {
+            Ok(record) => {
+                let sequence = record.decode_data().ok().map(|data| data.sequence);
+                match self.record_cache.insert(name, record) {
+                    Ok(true) => (sequence, RecordVerdict::Accepted),
+                    Ok(false) => (sequence, RecordVerdict::Outdated),
+                    Err(e) => (sequence, RecordVerdict::Invalid(e.to_string())),
+                }
+            }
+            Err(e) => (None, RecordVerdict::Invalid(e.to_string())),
+        };
+
+        match &verdict {
+            RecordVerdict::Accepted => debug!("Cached newer IPNS Record for {name}"),
+            RecordVerdict::Outdated => debug!("Ignored stale IPNS Record for {name}"),
+            RecordVerdict::Invalid(e) => warn!("Invalid IPNS Record received for {name}: {e}"),
+        }
+        self.emit(NetworkEvent::RecordReceived {
+            source,
+            peer: Some(peer),
+            name,
+            sequence,
+            verdict,
+        });
+    }
+
+    /// Add an external address, reporting it and the addresses it expired
+    fn add_external_address(&mut self, address: Multiaddr) {
+        let expired = match self
+            .swarm
+            .add_external_address(address.clone(), AddressScore::Infinite)
+        {
+            AddAddressResult::Inserted { expired } => {
+                self.emit(NetworkEvent::ExternalAddrAdded { address });
+                expired
+            }
+            AddAddressResult::Updated { expired } => expired,
+        };
+        for record in expired {
+            self.emit(NetworkEvent::ExternalAddrRemoved {
+                address: record.addr,
+            });
+        }
+    }
+
+    /// Announce an address a peer observed us on, or probe it with AutoNAT when private
+    fn handle_observed_address(&mut self, address: Multiaddr) {
+        if self.reachability.announce_observed() {
+            if !self.observed_addresses.contains(&address) {
+                self.observed_addresses.push(address.clone());
+            }
+            self.add_external_address(address);
+        } else if let Some(autonat) = self.swarm.behaviour_mut().autonat.as_mut() {
+            autonat.probe_address(address);
+        }
+    }
+
+    /// Move to the reachability AutoNAT found:
+    /// - public: announce the address we were dialed back on
+    /// - private: withdraw the observed addresses, and stop storing records for other peers
+    fn set_reachability(&mut self, reachability: Reachability) {
+        if reachability == self.reachability {
+            return;
+        }
+        info!("Reachability is now {reachability}");
+
+        match &reachability {
+            Reachability::Public(address) => {
+                let mut address = address.clone();
+                if !matches!(address.iter().last(), Some(Protocol::P2p(_))) {
+                    address.push(Protocol::P2p((*self.swarm.local_peer_id()).into()));
+                }
+                if !self.observed_addresses.contains(&address) {
+                    self.observed_addresses.push(address.clone());
+                }
+                self.add_external_address(address);
+            }
+            Reachability::Private => {
+                for address in std::mem::take(&mut self.observed_addresses) {
+                    if self.swarm.remove_external_address(&address) {
+                        self.emit(NetworkEvent::ExternalAddrRemoved { address });
+                    }
+                }
+            }
+            Reachability::Unknown => {}
+        }
+
+        let stores = reachability.stores_remote_records();
+        if stores != self.reachability.stores_remote_records() {
+            if stores {
+                info!("Storing the Kademlia records other peers put again");
+            } else {
+                info!("No longer storing the Kademlia records other peers put");
+            }
+        }
+
+        self.reachability = reachability.clone();
+        self.emit(NetworkEvent::ReachabilityChanged { reachability });
+    }
+
+    /// Store the records and provider records other peers put, unless private.
+    /// See [Reachability::stores_remote_records].
+    fn handle_inbound_kademlia(&mut self, request: InboundRequest) {
+        if !self.reachability.stores_remote_records() {
+            return;
+        }
+        let Some(kademlia) = self.swarm.behaviour_mut().kademlia.as_mut() else {
+            return;
+        };
+
+        let stored = match request {
+            InboundRequest::PutRecord {
+                record: Some(record),
+                ..
+            } => kademlia.store_mut().put(record),
+            InboundRequest::AddProvider {
+                record: Some(record),
+            } => kademlia.store_mut().add_provider(record),
+            _ => Ok(()),
+        };
+        if let Err(e) = stored {
+            warn!("Failed to store Kademlia record: {e}");
+        }
+    }
+
+    /// Report a change of the certhashes in the listen addresses
+    fn update_certhashes(&mut self) {
+        let mut certhashes = vec![];
+        for protocol in self.swarm.listeners().flat_map(|address| address.iter()) {
+            if let Protocol::Certhash(certhash) = protocol {
+                if !certhashes.contains(&certhash) {
+                    certhashes.push(certhash);
+                }
+            }
+        }
+
+        if certhashes != self.certhashes {
+            self.certhashes = certhashes.clone();
+            self.emit(NetworkEvent::CerthashesChanged { certhashes });
+        }
+    }
+
+    fn emit(&self, event: NetworkEvent) {
+        // there may be no subscriber
+        let _ = self.event_sender.send(event);
+    }
+
     async fn handle_command(&mut self, command: Command) {
         match command {
+            Command::PublishIpns {
+                name,
+                record,
+                sender,
+            } => self.publish_ipns(name, record, sender),
+            Command::GetRecord { key, sender } => {
+                match self.swarm.behaviour_mut().kademlia.as_mut() {
+                    Some(kademlia) => {
+                        let id = kademlia.get_record(Key::new(&key));
+                        self.pending_get_record.insert(id, (key, vec![], sender));
+                    }
+                    None => {
+                        let e = io::Error::new(io::ErrorKind::Other, "Kademlia is not enabled");
+                        let _ = sender.send(Err(Box::new(e)));
+                    }
+                }
+            }
+            Command::SubscribeIpns { name, sender } => {
+                let topic = topic::ipns(&name);
+                let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+                let subscribed = match gossipsub.subscribe(&topic) {
+                    Ok(subscribed) => subscribed,
+                    Err(e) => {
+                        let _ = sender.send(Err(Box::new(e)));
+                        return;
+                    }
+                };
+
+                if subscribed {
+                    let hash = topic.hash();
+                    let peers: Vec<PeerId> = gossipsub
+                        .all_peers()
+                        .filter(|(_, topics)| topics.contains(&&hash))
+                        .map(|(peer, _)| *peer)
+                        .collect();
+                    for peer in peers {
+                        self.fetch_ipns_record(peer, name);
+                    }
+                }
+                let _ = sender.send(Ok(subscribed));
+            }
+            Command::ListenAddresses { sender } => {
+                let peer_id = *self.swarm.local_peer_id();
+                let addresses = self
+                    .swarm
+                    .listeners()
+                    .map(|addr| addr.clone().with(Protocol::P2p(peer_id.into())))
+                    .collect();
+                let _ = sender.send(addresses);
+            }
+            // handled by `run`, and ignored once shutting down
+            Command::Shutdown { .. } => {}
             Command::StartListening { addr, sender } => {
                 let _ = match self.swarm.listen_on(addr) {
-                    Ok(_) => sender.send(Ok(())),
+                    Ok(listener) => {
+                        self.listeners.push(listener);
+                        sender.send(Ok(()))
+                    }
                     Err(e) => sender.send(Err(Box::new(e))),
                 };
             } // if let Some(listen_address) = &self.listen_address {
@@ -378,3 +1179,54 @@ impl EventLoop {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipns_entry::signer::Signer;
+    use ipns_entry::DataBuilder;
+
+    fn client() -> (Client, mpsc::Receiver<Command>) {
+        let (sender, receiver) = mpsc::channel(1);
+        let (events, _) = broadcast::channel(1);
+        (Client { sender, events }, receiver)
+    }
+
+    fn is_shut_down(e: Box<dyn Error + Send>) -> bool {
+        e.downcast_ref::<NetworkShutDown>().is_some()
+    }
+
+    #[tokio::test]
+    async fn test_client_after_shutdown() {
+        let (mut client, receiver) = client();
+        drop(receiver);
+
+        let signer = Signer::default();
+        let name = IpnsName::from(signer.public().to_peer_id());
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa").build();
+        let record = IpnsEntry::new(data, signer.sign(signables).unwrap());
+
+        assert!(is_shut_down(client.listen_addresses().await.unwrap_err()));
+        let addr = "/memory/0".parse().unwrap();
+        assert!(is_shut_down(
+            client.start_listening(addr).await.unwrap_err()
+        ));
+        assert!(is_shut_down(client.get_record(vec![1]).await.unwrap_err()));
+        assert!(is_shut_down(
+            client.publish_ipns(name, record).await.unwrap_err()
+        ));
+        assert!(is_shut_down(client.subscribe_ipns(name).await.unwrap_err()));
+
+        // does not wait for the stopped event loop
+        client.shutdown(Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_command_dropped() {
+        let (mut client, mut receiver) = client();
+        // the event loop stops with the command unanswered
+        tokio::spawn(async move { drop(receiver.recv().await) });
+
+        assert!(is_shut_down(client.get_record(vec![1]).await.unwrap_err()));
+    }
+}
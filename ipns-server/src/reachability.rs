@@ -0,0 +1,79 @@
+use libp2p::autonat::NatStatus;
+use libp2p::Multiaddr;
+use std::fmt;
+
+/// Whether the server can be dialed from the public internet, as found by AutoNAT.
+/// Stays unknown when AutoNAT is not enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Reachability {
+    #[default]
+    Unknown,
+    /// Dialed back on this address
+    Public(Multiaddr),
+    /// Not dialed back on any address
+    Private,
+}
+
+impl Reachability {
+    /// Whether to store the Kademlia records and provider records other peers put. Peers can't
+    /// dial back a private server to get them, so it doesn't. An unknown reachability keeps
+    /// storing them, as without AutoNAT.
+    ///
+    /// This is not a Kademlia client mode, which libp2p-kad 0.43 lacks: a private server still
+    /// advertises the Kademlia protocol, stays in the routing tables of its peers, and answers
+    /// their queries from what it already stores.
+    pub fn stores_remote_records(&self) -> bool {
+        !matches!(self, Reachability::Private)
+    }
+
+    /// Whether to announce the addresses peers observe us on. A private server probes them
+    /// with AutoNAT instead.
+    pub fn announce_observed(&self) -> bool {
+        !matches!(self, Reachability::Private)
+    }
+}
+
+impl From<NatStatus> for Reachability {
+    fn from(status: NatStatus) -> Self {
+        match status {
+            NatStatus::Public(address) => Reachability::Public(address),
+            NatStatus::Private => Reachability::Private,
+            NatStatus::Unknown => Reachability::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Reachability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reachability::Unknown => "unknown",
+            Reachability::Public(_) => "public",
+            Reachability::Private => "private",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reachability() {
+        let address: Multiaddr = "/ip4/1.2.3.4/tcp/9092".parse().unwrap();
+
+        let public = Reachability::from(NatStatus::Public(address.clone()));
+        assert_eq!(public, Reachability::Public(address));
+        assert!(public.stores_remote_records());
+        assert!(public.announce_observed());
+
+        let private = Reachability::from(NatStatus::Private);
+        assert!(!private.stores_remote_records());
+        assert!(!private.announce_observed());
+        assert_eq!(private.to_string(), "private");
+
+        let unknown = Reachability::from(NatStatus::Unknown);
+        assert_eq!(unknown, Reachability::default());
+        assert!(unknown.stores_remote_records());
+        assert!(unknown.announce_observed());
+    }
+}
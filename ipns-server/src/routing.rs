@@ -0,0 +1,412 @@
+//! Resolve IPNS Records from several routers at once, as kubo does.
+//!
+//! A [MultiRouter] asks every configured router in parallel, each within its own timeout, and
+//! keeps the best valid record: the highest IpnsEntry.sequence, then the latest validity.
+//! With a quorum it returns as soon as that many routers have answered with a valid record.
+//!
+//! ```rust,ignore
+//! let mut routers = MultiRouter::new();
+//! routers
+//!     .router(RouterSource::PubSub, PubsubRouting::new(record_cache, client.clone()), Duration::from_millis(50))
+//!     .router(RouterSource::Kademlia, KademliaRouting::new(client), Duration::from_secs(60))
+//!     .router(RouterSource::Delegated, DelegatedRouting::new("https://delegated-ipfs.dev")?, Duration::from_secs(10))
+//!     .quorum(2);
+//!
+//! let resolver = Resolver::new(routers);
+//! ```
+use crate::network::Client;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use ipns_entry::entry::IpnsEntry;
+use ipns_entry::name::IpnsName;
+use ipns_entry::time;
+use ipns_resolver::cache::RecordCache;
+use ipns_resolver::Routing;
+use log::debug;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where a record came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouterSource {
+    Kademlia,
+    /// Records received over IPNS over PubSub
+    PubSub,
+    /// Records fetched from pubsub peers with the IPNS over PubSub fetch protocol
+    Fetch,
+    /// A Delegated Routing V1 HTTP endpoint
+    Delegated,
+    /// Records held by this node
+    Local,
+}
+
+impl fmt::Display for RouterSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RouterSource::Kademlia => "kademlia",
+            RouterSource::PubSub => "pubsub",
+            RouterSource::Fetch => "fetch",
+            RouterSource::Delegated => "delegated",
+            RouterSource::Local => "local",
+        })
+    }
+}
+
+/// A valid record, and the router which supplied it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedRecord {
+    pub record: IpnsEntry,
+    pub source: RouterSource,
+    pub sequence: u64,
+    /// IpnsEntry.validity, since the UNIX epoch
+    pub validity: Duration,
+}
+
+impl RoutedRecord {
+    /// `record` if it is valid for `name` at `now`
+    pub(crate) fn validate(
+        name: &IpnsName,
+        record: IpnsEntry,
+        source: RouterSource,
+        now: Duration,
+    ) -> Result<Self> {
+        if !record.is_valid_for(&name.peer_id())? {
+            return Err(anyhow!("IPNS Record does not validate for {name}"));
+        }
+        if record.is_expired_at(now)? {
+            return Err(anyhow!("IPNS Record for {name} has expired"));
+        }
+
+        let data = record.decode_data()?;
+        Ok(RoutedRecord {
+            validity: time::parse_rfc3339(&data.validity)?,
+            sequence: data.sequence,
+            record,
+            source,
+        })
+    }
+
+    /// Higher sequence wins, then the later validity
+    pub fn is_better_than(&self, other: &RoutedRecord) -> bool {
+        (self.sequence, self.validity) > (other.sequence, other.validity)
+    }
+}
+
+struct Router {
+    source: RouterSource,
+    routing: Arc<dyn Routing>,
+    timeout: Duration,
+}
+
+/// Fans record lookups out to several [Routing]s
+#[derive(Default)]
+pub struct MultiRouter {
+    routers: Vec<Router>,
+    quorum: Option<usize>,
+}
+
+impl fmt::Debug for MultiRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiRouter")
+            .field(
+                "routers",
+                &self
+                    .routers
+                    .iter()
+                    .map(|r| (r.source, r.timeout))
+                    .collect::<Vec<_>>(),
+            )
+            .field("quorum", &self.quorum)
+            .finish()
+    }
+}
+
+impl MultiRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask `routing` for records, giving up on it after `timeout`
+    pub fn router(
+        &mut self,
+        source: RouterSource,
+        routing: impl Routing + 'static,
+        timeout: Duration,
+    ) -> &mut Self {
+        self.routers.push(Router {
+            source,
+            routing: Arc::new(routing),
+            timeout,
+        });
+        self
+    }
+
+    /// Return once `quorum` routers have answered with a valid record, instead of waiting for all
+    pub fn quorum(&mut self, quorum: usize) -> &mut Self {
+        self.quorum = Some(quorum.max(1));
+        self
+    }
+
+    /// The best valid record of `name` across all routers, `None` if no router has one.
+    /// Fails only if every router failed.
+    pub async fn get_routed(&self, name: &IpnsName) -> Result<Option<RoutedRecord>> {
+        let now = time::now();
+        let mut lookups: FuturesUnordered<_> = self
+            .routers
+            .iter()
+            .map(|router| async move {
+                let result = tokio::time::timeout(router.timeout, router.routing.get_record(name))
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", router.timeout)));
+                (router.source, result)
+            })
+            .collect();
+
+        let mut best: Option<RoutedRecord> = None;
+        let (mut answers, mut failures) = (0, vec![]);
+        while let Some((source, result)) = lookups.next().await {
+            let record = match result {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("{source} router failed for {name}: {e}");
+                    failures.push(format!("{source}: {e}"));
+                    continue;
+                }
+            };
+
+            match RoutedRecord::validate(name, record, source, now) {
+                Ok(routed) => {
+                    answers += 1;
+                    if best.as_ref().is_none_or(|best| routed.is_better_than(best)) {
+                        best = Some(routed);
+                    }
+                }
+                Err(e) => {
+                    debug!("{source} router returned an invalid record for {name}: {e}");
+                    failures.push(format!("{source}: {e}"));
+                }
+            }
+
+            if self.quorum.is_some_and(|quorum| answers >= quorum) {
+                break;
+            }
+        }
+
+        if best.is_none() && !self.routers.is_empty() && failures.len() == self.routers.len() {
+            return Err(anyhow!(
+                "All routers failed for {name}: {}",
+                failures.join(", ")
+            ));
+        }
+
+        Ok(best)
+    }
+}
+
+#[async_trait]
+impl Routing for MultiRouter {
+    async fn get_record(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        Ok(self.get_routed(name).await?.map(|routed| routed.record))
+    }
+}
+
+/// Records from the Kademlia DHT, through the network [Client]
+#[derive(Clone)]
+pub struct KademliaRouting {
+    client: Client,
+}
+
+impl KademliaRouting {
+    pub fn new(client: Client) -> Self {
+        KademliaRouting { client }
+    }
+}
+
+/// The Kademlia key of an IPNS Record: `/ipns/` followed by the binary peer id
+pub fn ipns_key(name: &IpnsName) -> Vec<u8> {
+    [b"/ipns/".as_slice(), &name.peer_id().to_bytes()].concat()
+}
+
+#[async_trait]
+impl Routing for KademliaRouting {
+    async fn get_record(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        let values = self
+            .client
+            .clone()
+            .get_record(ipns_key(name))
+            .await
+            .map_err(|e| anyhow!("{e}"))?;
+
+        // peers may hold different records, keep the best valid one
+        let now = time::now();
+        Ok(values
+            .iter()
+            .filter_map(|value| IpnsEntry::from_bytes(value).ok())
+            .filter_map(|record| {
+                RoutedRecord::validate(name, record, RouterSource::Kademlia, now).ok()
+            })
+            .reduce(|best, routed| {
+                if routed.is_better_than(&best) {
+                    routed
+                } else {
+                    best
+                }
+            })
+            .map(|routed| routed.record))
+    }
+}
+
+/// Records received over IPNS over PubSub, from the [RecordCache] the network fills.
+///
+/// A name without a cached record is subscribed to, which fetches the current record from
+/// peers already on its topic, so a later lookup finds it.
+#[derive(Clone)]
+pub struct PubsubRouting {
+    cache: RecordCache,
+    client: Client,
+}
+
+impl PubsubRouting {
+    pub fn new(cache: RecordCache, client: Client) -> Self {
+        PubsubRouting { cache, client }
+    }
+}
+
+#[async_trait]
+impl Routing for PubsubRouting {
+    async fn get_record(&self, name: &IpnsName) -> Result<Option<IpnsEntry>> {
+        if let Some(record) = self.cache.get(name) {
+            return Ok(Some(record));
+        }
+
+        self.client
+            .clone()
+            .subscribe_ipns(*name)
+            .await
+            .map_err(|e| anyhow!("{e}"))?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipns_entry::signer::{Keypair, Signer};
+    use ipns_entry::DataBuilder;
+    use ipns_resolver::MemoryRouting;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn record(keypair: &Keypair, sequence: u64, validity: Duration) -> IpnsEntry {
+        let (data, signables) = DataBuilder::new("/ipfs/bafkqaaa")
+            .validity_at(time::now() + validity)
+            .sequence(sequence)
+            .build();
+        IpnsEntry::new(data, Signer::new(keypair.clone()).sign(signables).unwrap())
+    }
+
+    /// Never answers
+    struct Stalled;
+
+    #[async_trait]
+    impl Routing for Stalled {
+        async fn get_record(&self, _name: &IpnsName) -> Result<Option<IpnsEntry>> {
+            futures::future::pending().await
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl Routing for Failing {
+        async fn get_record(&self, _name: &IpnsName) -> Result<Option<IpnsEntry>> {
+            Err(anyhow!("unreachable"))
+        }
+    }
+
+    fn routing(name: IpnsName, record: IpnsEntry) -> MemoryRouting {
+        let routing = MemoryRouting::default();
+        routing.put(name, record);
+        routing
+    }
+
+    #[tokio::test]
+    async fn test_picks_best_record_with_its_source() {
+        let keypair = Keypair::generate_ed25519();
+        let name = IpnsName::from(keypair.public().to_peer_id());
+        let other = Keypair::generate_ed25519();
+        let timeout = Duration::from_millis(100);
+
+        let mut routers = MultiRouter::new();
+        routers
+            .router(
+                RouterSource::Local,
+                routing(name, record(&keypair, 1, 2 * HOUR)),
+                timeout,
+            )
+            .router(
+                RouterSource::PubSub,
+                routing(name, record(&keypair, 2, HOUR)),
+                timeout,
+            )
+            // same sequence, later validity
+            .router(
+                RouterSource::Delegated,
+                routing(name, record(&keypair, 2, 3 * HOUR)),
+                timeout,
+            )
+            // a higher sequence, but signed by another key
+            .router(
+                RouterSource::Kademlia,
+                routing(name, record(&other, 9, HOUR)),
+                timeout,
+            )
+            .router(RouterSource::Kademlia, Stalled, timeout);
+
+        let best = routers.get_routed(&name).await.unwrap().unwrap();
+        assert_eq!(best.source, RouterSource::Delegated);
+        assert_eq!(best.sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn test_quorum_returns_early() {
+        let keypair = Keypair::generate_ed25519();
+        let name = IpnsName::from(keypair.public().to_peer_id());
+
+        let mut routers = MultiRouter::new();
+        routers
+            .router(RouterSource::Kademlia, Stalled, Duration::from_secs(3600))
+            .router(
+                RouterSource::Local,
+                routing(name, record(&keypair, 1, HOUR)),
+                HOUR,
+            )
+            .quorum(1);
+
+        let best = tokio::time::timeout(Duration::from_secs(5), routers.get_routed(&name))
+            .await
+            .expect("quorum reached without waiting for Kademlia")
+            .unwrap()
+            .unwrap();
+        assert_eq!(best.source, RouterSource::Local);
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let keypair = Keypair::generate_ed25519();
+        let name = IpnsName::from(keypair.public().to_peer_id());
+
+        let mut routers = MultiRouter::new();
+        routers
+            .router(RouterSource::Delegated, Failing, HOUR)
+            .router(RouterSource::Kademlia, Stalled, Duration::from_millis(10));
+        assert!(routers.get_routed(&name).await.is_err());
+
+        // a router without the record is an answer
+        routers.router(RouterSource::Local, MemoryRouting::default(), HOUR);
+        assert_eq!(routers.get_routed(&name).await.unwrap(), None);
+    }
+}
@@ -1,94 +1,553 @@
-use anyhow::{Context, Result};
+use crate::certificate::{CertificateConfig, RotatingTransport};
+use anyhow::{anyhow, Context, Result};
 use futures::future::Either;
-use libp2p::core::upgrade;
-use libp2p::core::{muxing::StreamMuxerBox, transport::Boxed};
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{upgrade::Authenticated, Boxed, MemoryTransport};
+use libp2p::core::upgrade::{self, MapInboundUpgrade, MapOutboundUpgrade, SelectUpgrade};
 use libp2p::dns;
 use libp2p::identity;
+use libp2p::mplex;
+use libp2p::multiaddr::Protocol;
 use libp2p::noise;
 use libp2p::tcp;
+use libp2p::tls;
+use libp2p::websocket;
 use libp2p::yamux;
-use libp2p::PeerId;
 use libp2p::Transport;
+use libp2p::{Multiaddr, PeerId};
 use libp2p_quic as quic;
-use libp2p_webrtc as webrtc;
-use libp2p_webrtc::tokio::Certificate;
 use log::info;
+use std::fmt;
 use std::io;
-use std::path::Path;
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
 use std::time::Duration;
 use tokio::fs;
 
-const LOCAL_CERT_PATH: &str = "./cert.pem";
+const PORT_WEBRTC: u16 = 9090;
+const PORT_QUIC: u16 = 9091;
+const PORT_TCP: u16 = 9092;
+const PORT_WEBSOCKET: u16 = 9093;
 
-/// Create a new Transport that supports WebRTC, QUIC, and TCP.
-pub async fn create(local_keypair: identity::Keypair) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
-    let authentication_config = {
+/// Time for a TCP, WebSocket or memory connection to be secured and multiplexed
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The transport of a connection, told from its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    WebRtc,
+    Quic,
+    Tcp,
+    WebSocket,
+    Memory,
+    /// Through a circuit relay
+    Relay,
+    Other,
+}
+
+impl TransportKind {
+    pub fn of(address: &Multiaddr) -> Self {
+        let mut kind = TransportKind::Other;
+        for protocol in address.iter() {
+            kind = match protocol {
+                // relayed connections run over another transport to the relay
+                Protocol::P2pCircuit => return TransportKind::Relay,
+                Protocol::WebRTCDirect => TransportKind::WebRtc,
+                Protocol::Quic | Protocol::QuicV1 => TransportKind::Quic,
+                // WebSockets run over TCP
+                Protocol::Ws(_) | Protocol::Wss(_) => TransportKind::WebSocket,
+                Protocol::Tcp(_) => TransportKind::Tcp,
+                Protocol::Memory(_) => TransportKind::Memory,
+                _ => kind,
+            };
+        }
+        kind
+    }
+
+    /// Whether browsers can't dial the transport, unlike WebRTC and WebSocket
+    pub fn is_native(self) -> bool {
+        matches!(self, TransportKind::Tcp | TransportKind::Quic)
+    }
+}
+
+impl fmt::Display for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransportKind::WebRtc => "webrtc",
+            TransportKind::Quic => "quic",
+            TransportKind::Tcp => "tcp",
+            TransportKind::WebSocket => "websocket",
+            TransportKind::Memory => "memory",
+            TransportKind::Relay => "relay",
+            TransportKind::Other => "other",
+        })
+    }
+}
+
+/// Security protocols of TCP, WebSocket and memory connections.
+/// QUIC and WebRTC connections are secured by the transport itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Security {
+    #[default]
+    Noise,
+    /// Noise, or TLS for peers which only speak TLS
+    NoiseAndTls,
+}
+
+/// Stream multiplexers of TCP, WebSocket and memory connections.
+/// QUIC and WebRTC connections are multiplexed by the transport itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Multiplexing {
+    #[default]
+    Yamux,
+    Mplex,
+    /// Yamux, or mplex for peers without yamux
+    YamuxAndMplex,
+}
+
+/// A PEM certificate chain and its PEM private key, PKCS#8 or RSA
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// The transports to run, and how their connections are secured and multiplexed.
+/// Defaults to WebRTC, QUIC (with draft-29) and TCP, with noise and yamux.
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    webrtc: bool,
+    webrtc_certificate: CertificateConfig,
+    quic: bool,
+    quic_draft_29: bool,
+    tcp: bool,
+    websocket: bool,
+    /// Secure WebSockets (`/wss`) when set
+    websocket_tls: Option<TlsFiles>,
+    memory: bool,
+    security: Security,
+    multiplexing: Multiplexing,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        TransportConfig {
+            webrtc: true,
+            webrtc_certificate: CertificateConfig::default(),
+            quic: true,
+            quic_draft_29: true,
+            tcp: true,
+            websocket: false,
+            websocket_tls: None,
+            memory: false,
+            security: Security::default(),
+            multiplexing: Multiplexing::default(),
+        }
+    }
+}
+
+impl TransportConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn webrtc(&mut self, enable: bool) -> &mut Self {
+        self.webrtc = enable;
+        self
+    }
+
+    /// Where the WebRTC certificate is kept, and how often it is rotated
+    pub fn webrtc_certificate(&mut self, certificate: CertificateConfig) -> &mut Self {
+        self.webrtc_certificate = certificate;
+        self
+    }
+
+    pub fn quic(&mut self, enable: bool) -> &mut Self {
+        self.quic = enable;
+        self
+    }
+
+    /// Also accept QUIC draft-29, which go-libp2p and kubo still dial
+    pub fn quic_draft_29(&mut self, enable: bool) -> &mut Self {
+        self.quic_draft_29 = enable;
+        self
+    }
+
+    pub fn tcp(&mut self, enable: bool) -> &mut Self {
+        self.tcp = enable;
+        self
+    }
+
+    pub fn websocket(&mut self, enable: bool) -> &mut Self {
+        self.websocket = enable;
+        self
+    }
+
+    /// Serve secure WebSockets with the certificate and key in these PEM files.
+    /// Enables WebSocket.
+    pub fn websocket_tls(
+        &mut self,
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> &mut Self {
+        self.websocket = true;
+        self.websocket_tls = Some(TlsFiles {
+            cert: cert.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// An in-process transport, for tests
+    pub fn memory(&mut self, enable: bool) -> &mut Self {
+        self.memory = enable;
+        self
+    }
+
+    pub fn security(&mut self, security: Security) -> &mut Self {
+        self.security = security;
+        self
+    }
+
+    pub fn multiplexing(&mut self, multiplexing: Multiplexing) -> &mut Self {
+        self.multiplexing = multiplexing;
+        self
+    }
+
+    /// Whether a transport browsers can't dial is enabled, to run AutoNAT on
+    pub(crate) fn has_native(&self) -> bool {
+        self.tcp || self.quic
+    }
+
+    /// The addresses to listen on, one per enabled transport, on all interfaces
+    pub fn listen_addresses(&self) -> Vec<Multiaddr> {
+        let any = || Multiaddr::from(Ipv6Addr::UNSPECIFIED);
+        let mut addresses = vec![];
+
+        if self.webrtc {
+            addresses.push(
+                any()
+                    .with(Protocol::Udp(PORT_WEBRTC))
+                    .with(Protocol::WebRTCDirect),
+            );
+        }
+        if self.quic {
+            addresses.push(any().with(Protocol::Udp(PORT_QUIC)).with(Protocol::QuicV1));
+        }
+        if self.tcp {
+            addresses.push(any().with(Protocol::Tcp(PORT_TCP)));
+        }
+        if self.websocket {
+            let websocket = any().with(Protocol::Tcp(PORT_WEBSOCKET));
+            addresses.push(match self.websocket_tls {
+                Some(_) => websocket.with(Protocol::Wss("/".into())),
+                None => websocket.with(Protocol::Ws("/".into())),
+            });
+        }
+        if self.memory {
+            // a random port
+            addresses.push(Multiaddr::empty().with(Protocol::Memory(0)));
+        }
+
+        addresses
+    }
+}
+
+/// Create a new Transport with the transports, security and multiplexers of `config`
+pub async fn create(
+    local_keypair: identity::Keypair,
+    config: &TransportConfig,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    // transports dialing DNS addresses
+    let mut resolved = vec![];
+
+    if config.tcp {
+        let tcp = tcp::tokio::Transport::new(tcp::Config::new().port_reuse(true).nodelay(true));
+        resolved.push(upgrade(tcp, &local_keypair, config)?);
+    }
+
+    if config.websocket {
+        let tcp = tcp::tokio::Transport::new(tcp::Config::new().nodelay(true));
+        let mut websocket = websocket::WsConfig::new(tcp);
+        if let Some(files) = &config.websocket_tls {
+            websocket.set_tls_config(read_tls_config(files).await?);
+        }
+        resolved.push(upgrade(websocket, &local_keypair, config)?);
+    }
+
+    if config.quic {
+        let mut quic_config = quic::Config::new(&local_keypair);
+        quic_config.support_draft_29 = config.quic_draft_29;
+        resolved.push(
+            quic::tokio::Transport::new(quic_config)
+                .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+                .boxed(),
+        );
+    }
+
+    let mut transports = vec![];
+    if let Some(resolved) = or_transports(resolved) {
+        transports.push(dns::TokioDnsConfig::system(resolved)?.boxed());
+    }
+
+    if config.webrtc {
+        transports.push(
+            RotatingTransport::new(local_keypair.clone(), config.webrtc_certificate.clone())?
+                .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+                .boxed(),
+        );
+    }
+
+    if config.memory {
+        transports.push(upgrade(MemoryTransport::default(), &local_keypair, config)?);
+    }
+
+    or_transports(transports).ok_or_else(|| anyhow!("No transport enabled"))
+}
+
+/// A transport dialing and listening with the first of `transports` supporting an address
+fn or_transports(
+    transports: Vec<Boxed<(PeerId, StreamMuxerBox)>>,
+) -> Option<Boxed<(PeerId, StreamMuxerBox)>> {
+    transports.into_iter().reduce(|first, second| {
+        first
+            .or_transport(second)
+            .map(|either, _| match either {
+                Either::Left(output) | Either::Right(output) => output,
+            })
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+            .boxed()
+    })
+}
+
+/// Secure and multiplex the connections of `transport`
+fn upgrade<T>(
+    transport: T,
+    local_keypair: &identity::Keypair,
+    config: &TransportConfig,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let noise = {
         let noise_keypair_spec = noise::Keypair::<noise::X25519Spec>::new()
-            .into_authentic(&local_keypair)
+            .into_authentic(local_keypair)
             .context("Failed to create noise keypair")?;
 
         noise::NoiseConfig::xx(noise_keypair_spec).into_authenticated()
     };
+    let transport = transport.upgrade(upgrade::Version::V1);
+
+    Ok(match config.security {
+        Security::Noise => multiplex(transport.authenticate(noise), config.multiplexing),
+        Security::NoiseAndTls => {
+            let tls = tls::Config::new(local_keypair).context("Failed to create TLS config")?;
+            // both authenticate the remote peer id, over a connection of either type
+            let noise_or_tls = MapInboundUpgrade::new(
+                MapOutboundUpgrade::new(SelectUpgrade::new(noise, tls), either_connection),
+                either_connection,
+            );
+            multiplex(transport.authenticate(noise_or_tls), config.multiplexing)
+        }
+    })
+}
 
+fn either_connection<A, B>(output: Either<(PeerId, A), (PeerId, B)>) -> (PeerId, Either<A, B>) {
+    match output {
+        Either::Left((peer_id, connection)) => (peer_id, Either::Left(connection)),
+        Either::Right((peer_id, connection)) => (peer_id, Either::Right(connection)),
+    }
+}
+
+fn multiplex<T, C>(
+    transport: Authenticated<T>,
+    multiplexing: Multiplexing,
+) -> Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport<Output = (PeerId, C)> + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     let mut yamux_config = yamux::YamuxConfig::default();
     // Enable proper flow-control: window updates are only sent when
     // buffered data has been consumed.
     yamux_config.set_window_update_mode(yamux::WindowUpdateMode::on_read());
 
-    let tcp_transport =
-        tcp::tokio::Transport::new(tcp::Config::new().port_reuse(true).nodelay(true))
-            .upgrade(upgrade::Version::V1)
-            .authenticate(authentication_config)
+    match multiplexing {
+        Multiplexing::Yamux => transport
             .multiplex(yamux_config)
-            .timeout(Duration::from_secs(20));
+            .timeout(UPGRADE_TIMEOUT)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+        Multiplexing::Mplex => transport
+            .multiplex(mplex::MplexConfig::new())
+            .timeout(UPGRADE_TIMEOUT)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+        Multiplexing::YamuxAndMplex => transport
+            .multiplex(SelectUpgrade::new(yamux_config, mplex::MplexConfig::new()))
+            .timeout(UPGRADE_TIMEOUT)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+    }
+}
 
-    let quic_transport = {
-        let mut config = quic::Config::new(&local_keypair);
-        config.support_draft_29 = true;
-        quic::tokio::Transport::new(config)
-    };
+/// The TLS config of secure WebSockets, from PEM files
+async fn read_tls_config(files: &TlsFiles) -> Result<websocket::tls::Config> {
+    let certs = pem_blocks(&fs::read_to_string(&files.cert).await?, "CERTIFICATE")
+        .with_context(|| format!("Failed to read {}", files.cert.display()))?;
+    let key = fs::read_to_string(&files.key).await?;
+    let key = ["PRIVATE KEY", "RSA PRIVATE KEY"]
+        .iter()
+        .find_map(|label| pem_blocks(&key, label).ok()?.into_iter().next())
+        .ok_or_else(|| anyhow!("No private key in {}", files.key.display()))?;
 
-    let webrtc_cert = read_or_create_certificate(Path::new(LOCAL_CERT_PATH))
-        .await
-        .context("Failed to read certificate")?;
+    info!("Using WebSocket certificate from {}", files.cert.display());
 
-    let webrtc = webrtc::tokio::Transport::new(local_keypair.clone(), webrtc_cert);
+    Ok(websocket::tls::Config::new(
+        websocket::tls::PrivateKey::new(key),
+        certs.into_iter().map(websocket::tls::Certificate::new),
+    )?)
+}
 
-    let transport = {
-        let dns_quic_or_tcp = dns::TokioDnsConfig::system(
-            libp2p::core::transport::OrTransport::new(quic_transport, tcp_transport),
-        )?;
-        dns_quic_or_tcp.or_transport(webrtc)
-    };
+/// The DER contents of the PEM blocks labelled `label`
+fn pem_blocks(pem: &str, label: &str) -> Result<Vec<Vec<u8>>> {
+    let (begin, end) = (
+        format!("-----BEGIN {label}-----"),
+        format!("-----END {label}-----"),
+    );
+    let mut blocks = vec![];
+    let mut lines = pem.lines().map(str::trim);
+    while lines.any(|line| line == begin) {
+        let body: String = lines.by_ref().take_while(|line| *line != end).collect();
+        blocks.push(base64::decode(body)?);
+    }
 
-    Ok(transport
-        .map(|dns_or_webrtc, _| match dns_or_webrtc {
-            Either::Left(quic_or_tcp) => match quic_or_tcp {
-                Either::Left((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
-                Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
-            },
-            Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
-        })
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
-        .boxed())
+    if blocks.is_empty() {
+        return Err(anyhow!("No {label} in PEM"));
+    }
+    Ok(blocks)
 }
 
-async fn read_or_create_certificate(path: &Path) -> Result<Certificate> {
-    if path.exists() {
-        let pem = fs::read_to_string(&path).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use libp2p::swarm::{dummy, Swarm, SwarmBuilder, SwarmEvent};
 
-        info!("Using existing certificate from {}", path.display());
+    /// A swarm over the memory transport, secured and multiplexed as configured
+    fn memory_swarm(security: Security, multiplexing: Multiplexing) -> Swarm<dummy::Behaviour> {
+        let keypair = identity::Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let mut config = TransportConfig::new();
+        config.security(security).multiplexing(multiplexing);
 
-        return Ok(Certificate::from_pem(&pem)?);
+        let transport = upgrade(MemoryTransport::default(), &keypair, &config).unwrap();
+        SwarmBuilder::with_tokio_executor(transport, dummy::Behaviour, peer_id).build()
     }
 
-    let cert = Certificate::generate(&mut rand::thread_rng())?;
-    fs::write(&path, &cert.serialize_pem().as_bytes()).await?;
+    /// Connect `dialer` to `listener` over `/memory/0`, checking both authenticate the other
+    async fn connect(mut listener: Swarm<dummy::Behaviour>, mut dialer: Swarm<dummy::Behaviour>) {
+        listener.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = listener.select_next_some().await {
+                break address;
+            }
+        };
+        dialer.dial(address).unwrap();
 
-    info!(
-        "Generated new certificate and wrote it to {}",
-        path.display()
-    );
+        let (mut dialed, mut accepted) = (None, None);
+        let connected = async {
+            while dialed.is_none() || accepted.is_none() {
+                tokio::select! {
+                    event = dialer.select_next_some() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, .. } => dialed = Some(peer_id),
+                        SwarmEvent::OutgoingConnectionError { error, .. } => panic!("{error}"),
+                        _ => {}
+                    },
+                    event = listener.select_next_some() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, .. } => accepted = Some(peer_id),
+                        SwarmEvent::IncomingConnectionError { error, .. } => panic!("{error}"),
+                        _ => {}
+                    },
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), connected)
+            .await
+            .expect("connected");
+
+        assert_eq!(dialed, Some(*listener.local_peer_id()));
+        assert_eq!(accepted, Some(*dialer.local_peer_id()));
+    }
+
+    #[tokio::test]
+    async fn test_noise_and_tls_yamux_and_mplex() {
+        let all = || memory_swarm(Security::NoiseAndTls, Multiplexing::YamuxAndMplex);
+        connect(all(), all()).await;
+
+        // peers supporting one of each are still accepted
+        connect(all(), memory_swarm(Security::Noise, Multiplexing::Mplex)).await;
+        connect(all(), memory_swarm(Security::Noise, Multiplexing::Yamux)).await;
+        connect(memory_swarm(Security::Noise, Multiplexing::Mplex), all()).await;
+    }
 
-    Ok(cert)
+    #[test]
+    fn test_transport_kind() {
+        let kind = |addr: &str| TransportKind::of(&addr.parse().unwrap());
+        assert_eq!(kind("/ip4/1.2.3.4/tcp/9092"), TransportKind::Tcp);
+        assert_eq!(kind("/ip6/::1/tcp/9093/ws"), TransportKind::WebSocket);
+        assert_eq!(kind("/ip4/1.2.3.4/udp/9091/quic-v1"), TransportKind::Quic);
+        assert_eq!(
+            kind("/ip4/1.2.3.4/udp/9090/webrtc-direct"),
+            TransportKind::WebRtc
+        );
+        assert_eq!(kind("/memory/1234"), TransportKind::Memory);
+        assert_eq!(
+            kind("/ip4/1.2.3.4/tcp/9092/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit"),
+            TransportKind::Relay
+        );
+        assert_eq!(kind("/dns4/example.com"), TransportKind::Other);
+        assert!(TransportKind::Quic.is_native());
+        assert!(!TransportKind::WebSocket.is_native());
+    }
+    #[test]
+    fn test_pem_blocks() {
+        let pem = "-----BEGIN CERTIFICATE-----\nAQID\nBA==\n-----END CERTIFICATE-----\n\
+                   -----BEGIN CERTIFICATE-----\nBQ==\n-----END CERTIFICATE-----\n";
+        assert_eq!(
+            pem_blocks(pem, "CERTIFICATE").unwrap(),
+            vec![vec![1, 2, 3, 4], vec![5]]
+        );
+        assert!(pem_blocks(pem, "PRIVATE KEY").is_err());
+    }
+
+    #[test]
+    fn test_listen_addresses() {
+        let mut config = TransportConfig::new();
+        config.webrtc(false).quic(false).memory(true);
+        assert_eq!(
+            config.listen_addresses(),
+            vec![
+                "/ip6/::/tcp/9092".parse().unwrap(),
+                "/memory/0".parse().unwrap()
+            ]
+        );
+
+        config
+            .tcp(false)
+            .memory(false)
+            .websocket_tls("cert.pem", "key.pem");
+        assert_eq!(
+            config.listen_addresses(),
+            vec!["/ip6/::/tcp/9093/wss".parse::<Multiaddr>().unwrap()]
+        );
+    }
 }